@@ -0,0 +1,56 @@
+//! Compare the tree-walking interpreter and the bytecode virtual machine on a
+//! loop-heavy recipe: nested loops whose body calls a function evaluating an
+//! `IfElse`. Run using `cargo bench --bench vm`.
+
+use std::time::{Duration, Instant};
+
+use stir::blocks::{BasicBlock, Boolean, Call, Function, IfElse, Loop, Number};
+use stir::executor::{Engine, FryOptions};
+use stir::recipe::Recipe;
+
+/// Number of runs of each engine, the fastest one is reported
+const RUNS: usize = 10;
+
+/// Return the fastest run of the recipe using the given engine
+fn fastest(recipe: &Recipe, engine: Engine) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let mut options = FryOptions::new();
+            options.set_engine(engine);
+
+            let start = Instant::now();
+            assert_eq!(recipe.fry_with(options), Ok(true));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let c = Boolean::new(false);
+    let t = Boolean::new(false);
+    let f = Boolean::new(true);
+    let ie = IfElse::new(&c, &t, Some(&f));
+    let stmts: Vec<&dyn BasicBlock> = vec![&ie];
+    let mut func = Function::new(None, &stmts);
+    func.set_retval(&ie);
+    let call = Call::new(&func, None);
+
+    let lo = Number::new(0.0);
+    let hi = Number::new(1000.0);
+    let inner = Loop::new(Some(&lo), Some(&hi), Some(&call));
+    let outer = Loop::new(Some(&lo), Some(&hi), Some(&inner));
+
+    let mut recipe = Recipe::new();
+    recipe.add_entry(&outer);
+
+    let interpreter = fastest(&recipe, Engine::Interpreter);
+    let bytecode = fastest(&recipe, Engine::Bytecode);
+
+    println!("interpreter  {:>12?}", interpreter);
+    println!("bytecode     {:>12?}", bytecode);
+    println!(
+        "speedup      {:>12.2}x",
+        interpreter.as_secs_f64() / bytecode.as_secs_f64()
+    );
+}
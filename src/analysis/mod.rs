@@ -0,0 +1,178 @@
+//! Analyses computing properties of blocks, used to decide which
+//! transformations and execution strategies are safe.
+
+mod structural;
+
+pub use structural::{structural_eq, structural_hash, StructuralHasher};
+
+use crate::blocks::{BasicBlock, BlockKind, Loop};
+
+/// Return `true` if the block is proven free of side effects and always
+/// terminates. Such a block can be executed speculatively, executed multiple
+/// times or not executed at all without changing the result of the program.
+///
+/// `Critical` blocks are never pure, as they guard shared state. Infinite
+/// loops and blocks defined outside of `stir` are not pure either.
+///
+/// # Example
+///
+/// ```
+/// use stir::analysis::is_pure;
+/// use stir::blocks::{Boolean, Critical, IfElse};
+///
+/// let c = Boolean::new(true);
+/// let t = Boolean::new(false);
+/// let crit = Critical::new(&t);
+///
+/// assert!(is_pure(&IfElse::new(&c, &t, None)));
+/// assert!(!is_pure(&IfElse::new(&c, &crit, None)));
+/// ```
+pub fn is_pure(block: &dyn BasicBlock) -> bool {
+    let kind = block.kind();
+
+    match kind {
+        BlockKind::Boolean(_) | BlockKind::Number(_) | BlockKind::Str(_) => true,
+        BlockKind::Critical(_) | BlockKind::Other => false,
+        BlockKind::Loop(l) if l.lo_bound().is_none() || l.hi_bound().is_none() => false,
+        BlockKind::IfElse(_)
+        | BlockKind::Loop(_)
+        | BlockKind::Function(_)
+        | BlockKind::Call(_)
+        | BlockKind::Arithmetic(_)
+        | BlockKind::Compare(_) => kind.children().into_iter().all(is_pure),
+    }
+}
+
+/// Return `true` if the block contains a `Critical` block, including in the
+/// functions it calls. Such a block has to be executed by one thread at a
+/// time, at least in part
+///
+/// # Example
+///
+/// ```
+/// use stir::analysis::has_critical;
+/// use stir::blocks::{Boolean, Critical, IfElse};
+///
+/// let c = Boolean::new(true);
+/// let crit = Critical::new(&c);
+///
+/// assert!(has_critical(&IfElse::new(&c, &crit, None)));
+/// assert!(!has_critical(&IfElse::new(&c, &c, None)));
+/// ```
+pub fn has_critical(block: &dyn BasicBlock) -> bool {
+    let kind = block.kind();
+
+    match kind {
+        BlockKind::Critical(_) => true,
+        _ => kind.children().into_iter().any(has_critical),
+    }
+}
+
+/// Return `true` if the iterations of a loop are independent and can be
+/// executed in parallel. This is the case of finite loops whose body only
+/// contains blocks provided by `stir`: since the body cannot refer to the
+/// induction variable or store values, no iteration depends on another one.
+/// `Critical` blocks in the body still need to be executed by one thread at
+/// a time.
+///
+/// # Example
+///
+/// ```
+/// use stir::analysis::is_parallel;
+/// use stir::blocks::{Boolean, Critical, Loop, Number};
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(100.0);
+/// let b = Boolean::new(true);
+/// let crit = Critical::new(&b);
+///
+/// assert!(is_parallel(&Loop::new(Some(&lo), Some(&hi), Some(&crit))));
+/// assert!(!is_parallel(&Loop::new(None, None, Some(&b))));
+/// ```
+pub fn is_parallel(l: &Loop) -> bool {
+    l.lo_bound().is_some() && l.hi_bound().is_some() && l.body().is_none_or(is_known)
+}
+
+/// Return `true` if the block and all the blocks it contains are provided by
+/// `stir`
+fn is_known(block: &dyn BasicBlock) -> bool {
+    let kind = block.kind();
+
+    match kind {
+        BlockKind::Other => false,
+        _ => kind.children().into_iter().all(is_known),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Call, Critical, Function, Loop, Number};
+
+    #[test]
+    fn primitives() {
+        assert!(is_pure(&Boolean::new(true)));
+        assert!(is_pure(&Number::new(1.0)));
+    }
+
+    #[test]
+    fn critical() {
+        let b = Boolean::new(true);
+
+        assert!(!is_pure(&Critical::new(&b)));
+    }
+
+    #[test]
+    fn infinite_loop() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+
+        assert!(is_pure(&Loop::new(Some(&lo), Some(&hi), None)));
+        assert!(!is_pure(&Loop::new(Some(&lo), None, None)));
+    }
+
+    #[test]
+    fn parallel_loop_with_unknown_body() {
+        #[derive(Debug)]
+        struct Unknown(String);
+
+        impl BasicBlock for Unknown {
+            fn label(&self) -> &String {
+                &self.0
+            }
+
+            fn output(&self) -> String {
+                String::from("unknown")
+            }
+
+            fn interpret(&self) -> bool {
+                true
+            }
+        }
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let unknown = Unknown(String::from("__unknown_0"));
+        let stmts: Vec<&dyn BasicBlock> = vec![&unknown];
+        let body = Function::new(None, &stmts);
+
+        assert!(!is_parallel(&Loop::new(Some(&lo), Some(&hi), Some(&body))));
+        assert!(is_parallel(&Loop::new(Some(&lo), Some(&hi), None)));
+    }
+
+    #[test]
+    fn call_to_impure_function() {
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+
+        let pure_stmts: Vec<&dyn BasicBlock> = vec![&b];
+        let impure_stmts: Vec<&dyn BasicBlock> = vec![&b, &crit];
+
+        let pure_f = Function::new(None, &pure_stmts);
+        let impure_f = Function::new(None, &impure_stmts);
+
+        assert!(is_pure(&Call::new(&pure_f, None)));
+        assert!(!is_pure(&Call::new(&impure_f, None)));
+    }
+}
@@ -0,0 +1,294 @@
+//! Structural hashing and equality compare blocks by what they compute,
+//! ignoring their labels: two `Boolean::new(true)` are structurally equal.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::blocks::{BasicBlock, BlockKind, Function, Primitive};
+
+/// Return the address of a block, identifying it regardless of its label
+fn address(block: &dyn BasicBlock) -> *const () {
+    block as *const dyn BasicBlock as *const ()
+}
+
+/// Computes the structural hash of blocks. The hash of every block is cached,
+/// so that blocks shared by several parents are only hashed once
+pub struct StructuralHasher {
+    hashes: HashMap<*const (), u64>,
+}
+
+impl StructuralHasher {
+    /// Create a new StructuralHasher, with an empty cache
+    pub fn new() -> StructuralHasher {
+        StructuralHasher {
+            hashes: HashMap::new(),
+        }
+    }
+
+    /// Return the structural hash of a block. Structurally equal blocks have
+    /// the same hash
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::analysis::StructuralHasher;
+    /// use stir::blocks::{Boolean, Critical};
+    ///
+    /// let t0 = Boolean::new(true);
+    /// let t1 = Boolean::new(true);
+    ///
+    /// let mut hasher = StructuralHasher::new();
+    ///
+    /// assert_eq!(hasher.hash(&t0), hasher.hash(&t1));
+    /// assert_ne!(hasher.hash(&t0), hasher.hash(&Critical::new(&t0)));
+    /// ```
+    pub fn hash(&mut self, block: &dyn BasicBlock) -> u64 {
+        if let Some(hash) = self.hashes.get(&address(block)) {
+            return *hash;
+        }
+
+        let mut state = DefaultHasher::new();
+        let kind = block.kind();
+
+        match kind {
+            BlockKind::Boolean(b) => (0u8, b.get()).hash(&mut state),
+            BlockKind::Number(n) => (1u8, n.get().to_bits()).hash(&mut state),
+            BlockKind::Str(s) => (2u8, s.get()).hash(&mut state),
+            BlockKind::IfElse(ie) => (3u8, ie.f_block().is_some()).hash(&mut state),
+            BlockKind::Loop(l) => (
+                4u8,
+                l.lo_bound().is_some(),
+                l.hi_bound().is_some(),
+                l.body().is_some(),
+            )
+                .hash(&mut state),
+            BlockKind::Function(f) => {
+                (5u8, f.stmts().len(), f.retval().is_some(), f.inline()).hash(&mut state);
+                self.args(f).hash(&mut state);
+            }
+            BlockKind::Call(c) => {
+                6u8.hash(&mut state);
+                c.args()
+                    .map(|args| args.iter().map(|arg| self.hash(*arg)).collect::<Vec<_>>())
+                    .hash(&mut state);
+            }
+            BlockKind::Critical(_) => 7u8.hash(&mut state),
+            // Blocks defined outside of `stir` are only equal to themselves
+            BlockKind::Other => (8u8, address(block)).hash(&mut state),
+            BlockKind::Arithmetic(a) => (9u8, a.op()).hash(&mut state),
+            BlockKind::Compare(c) => (10u8, c.op()).hash(&mut state),
+        }
+
+        for child in kind.children() {
+            self.hash(child).hash(&mut state);
+        }
+
+        let hash = state.finish();
+        self.hashes.insert(address(block), hash);
+
+        hash
+    }
+
+    /// Return the hashes of the arguments of a function
+    fn args(&mut self, f: &Function) -> Option<Vec<u64>> {
+        f.args()
+            .map(|args| args.iter().map(|arg| self.hash(*arg)).collect())
+    }
+}
+
+impl Default for StructuralHasher {
+    fn default() -> Self {
+        StructuralHasher::new()
+    }
+}
+
+/// Return the structural hash of a block. Use a `StructuralHasher` to hash
+/// several blocks sharing children
+pub fn structural_hash(block: &dyn BasicBlock) -> u64 {
+    StructuralHasher::new().hash(block)
+}
+
+/// Pairs of blocks already proven structurally equal
+struct Equalities {
+    proven: HashSet<(*const (), *const ())>,
+}
+
+impl Equalities {
+    fn eq(&mut self, a: &dyn BasicBlock, b: &dyn BasicBlock) -> bool {
+        let pair = (address(a), address(b));
+        if pair.0 == pair.1 || self.proven.contains(&pair) {
+            return true;
+        }
+
+        let (ka, kb) = (a.kind(), b.kind());
+
+        let equal = match (ka, kb) {
+            (BlockKind::Boolean(x), BlockKind::Boolean(y)) => x.get() == y.get(),
+            (BlockKind::Number(x), BlockKind::Number(y)) => x.get().to_bits() == y.get().to_bits(),
+            (BlockKind::Str(x), BlockKind::Str(y)) => x.get() == y.get(),
+            (BlockKind::IfElse(x), BlockKind::IfElse(y)) => {
+                x.f_block().is_some() == y.f_block().is_some()
+            }
+            (BlockKind::Loop(x), BlockKind::Loop(y)) => {
+                x.lo_bound().is_some() == y.lo_bound().is_some()
+                    && x.hi_bound().is_some() == y.hi_bound().is_some()
+                    && x.body().is_some() == y.body().is_some()
+            }
+            (BlockKind::Function(x), BlockKind::Function(y)) => {
+                x.stmts().len() == y.stmts().len()
+                    && x.retval().is_some() == y.retval().is_some()
+                    && x.inline() == y.inline()
+                    && self.all_eq(x.args(), y.args())
+            }
+            (BlockKind::Call(x), BlockKind::Call(y)) => self.all_eq(x.args(), y.args()),
+            (BlockKind::Critical(_), BlockKind::Critical(_)) => true,
+            (BlockKind::Arithmetic(x), BlockKind::Arithmetic(y)) => x.op() == y.op(),
+            (BlockKind::Compare(x), BlockKind::Compare(y)) => x.op() == y.op(),
+            _ => false,
+        };
+
+        // Children are listed in the same order for blocks of the same kind
+        let equal = equal
+            && ka
+                .children()
+                .into_iter()
+                .zip(kb.children())
+                .all(|(x, y)| self.eq(x, y));
+
+        if equal {
+            self.proven.insert(pair);
+        }
+
+        equal
+    }
+
+    /// Return `true` if both optional lists of blocks are structurally equal
+    fn all_eq(
+        &mut self,
+        a: Option<&Vec<&dyn BasicBlock>>,
+        b: Option<&Vec<&dyn BasicBlock>>,
+    ) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| self.eq(*x, *y))
+            }
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Return `true` if both blocks compute the same thing: they are of the same
+/// kind, hold the same values, and their children are structurally equal.
+/// Labels are ignored. Blocks defined outside of `stir` are only equal to
+/// themselves
+///
+/// # Example
+///
+/// ```
+/// use stir::analysis::structural_eq;
+/// use stir::blocks::{Boolean, IfElse};
+///
+/// let c0 = Boolean::new(true);
+/// let t0 = Boolean::new(false);
+/// let c1 = Boolean::new(true);
+/// let t1 = Boolean::new(false);
+///
+/// assert!(structural_eq(&IfElse::new(&c0, &t0, None), &IfElse::new(&c1, &t1, None)));
+/// assert!(!structural_eq(&IfElse::new(&c0, &t0, None), &IfElse::new(&t1, &c1, None)));
+/// ```
+pub fn structural_eq(a: &dyn BasicBlock, b: &dyn BasicBlock) -> bool {
+    Equalities {
+        proven: HashSet::new(),
+    }
+    .eq(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Call, IfElse, Loop, Number, Str};
+    use crate::recipe::Arena;
+
+    #[test]
+    fn primitives() {
+        let n0 = Number::new(1.0);
+        let n1 = Number::new(1.0);
+        let nan0 = Number::new(f64::NAN);
+        let nan1 = Number::new(f64::NAN);
+        let s0 = Str::new(String::from("stir"));
+        let s1 = Str::new(String::from("stir"));
+        let b = Boolean::new(true);
+
+        assert!(structural_eq(&n0, &n1));
+        assert!(structural_eq(&nan0, &nan1));
+        assert!(structural_eq(&s0, &s1));
+        assert!(!structural_eq(&n0, &b));
+
+        assert_eq!(structural_hash(&n0), structural_hash(&n1));
+        assert_eq!(structural_hash(&s0), structural_hash(&s1));
+    }
+
+    #[test]
+    fn optional_children() {
+        let lo = Number::new(0.0);
+        let b = Boolean::new(true);
+
+        // Same number of children, in different positions
+        let l0 = Loop::new(Some(&lo), None, None);
+        let l1 = Loop::new(None, None, Some(&lo));
+        let l2 = Loop::new(Some(&b), None, None);
+
+        assert!(!structural_eq(&l0, &l1));
+        assert!(!structural_eq(&l0, &l2));
+        assert_ne!(structural_hash(&l0), structural_hash(&l1));
+    }
+
+    #[test]
+    fn functions_and_calls() {
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+
+        let stmts0: Vec<&dyn BasicBlock> = vec![&t, &f];
+        let stmts1: Vec<&dyn BasicBlock> = vec![&t, &f];
+        let stmts2: Vec<&dyn BasicBlock> = vec![&f, &t];
+        let args: Vec<&dyn BasicBlock> = vec![&t];
+
+        let f0 = Function::new(None, &stmts0);
+        let f1 = Function::new(None, &stmts1);
+        let f2 = Function::new(None, &stmts2);
+        let f3 = Function::new(Some(&args), &stmts1);
+
+        assert!(structural_eq(&f0, &f1));
+        assert!(!structural_eq(&f0, &f2));
+        assert!(!structural_eq(&f0, &f3));
+
+        let c0 = Call::new(&f0, None);
+        let c1 = Call::new(&f1, None);
+        let c2 = Call::new(&f1, Some(&args));
+
+        assert!(structural_eq(&c0, &c1));
+        assert!(!structural_eq(&c0, &c2));
+        assert_eq!(structural_hash(&c0), structural_hash(&c1));
+    }
+
+    #[test]
+    fn shared_children() {
+        // Each level references the previous one twice
+        let arena = Arena::new();
+        let b = Boolean::new(true);
+
+        let mut hasher = StructuralHasher::new();
+        let first = IfElse::new(&b, &b, Some(&b));
+        let mut last: &dyn BasicBlock = &first;
+
+        for _ in 0..64 {
+            last = arena.alloc(IfElse::new(last, last, Some(last)));
+        }
+
+        assert_ne!(hasher.hash(last), hasher.hash(&first));
+        assert!(structural_eq(last, last));
+    }
+}
@@ -0,0 +1,528 @@
+//! Translation of a `Recipe` to x86-64 assembly, in the AT&T syntax of the
+//! GNU assembler. Assemble and link the result using the system `cc`.
+//!
+//! Every block evaluates to `0` or `1` in `%eax`, following the semantics of
+//! the interpreter. `Function` blocks become functions following the System
+//! V calling convention, and the entry block of the recipe is evaluated by
+//! `main`, whose exit code is `1` if the entry block evaluated to `true`.
+//! The arguments of `Call` blocks are not evaluated, as the interpreter
+//! ignores them. The generated program is single-threaded: `Critical` blocks
+//! simply evaluate the block they wrap.
+//!
+//! Loops store their index, higher bound and value in callee-saved
+//! registers. Each level of loop nesting uses three of them, and levels
+//! deeper than the available registers are spilled to the stack.
+
+use std::collections::HashSet;
+
+use super::BackendError;
+
+use crate::blocks::{ArithOp, BasicBlock, BlockKind, CompareOp, Function, Loop, Primitive};
+use crate::recipe::Recipe;
+
+/// Registers allocated to loop variables, preserved across calls
+const CALLEE_SAVED: [&str; 5] = ["%rbx", "%r12", "%r13", "%r14", "%r15"];
+
+/// Translate a recipe to an assembly file
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::asm;
+/// use stir::blocks::{Boolean, IfElse};
+/// use stir::recipe::Recipe;
+///
+/// let c = Boolean::new(true);
+/// let t = Boolean::new(false);
+/// let ie = IfElse::new(&c, &t, None);
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&ie);
+///
+/// let asm = asm::emit(&recipe).unwrap();
+///
+/// assert!(asm.contains("\t.globl\tmain\n"));
+/// ```
+pub fn emit(recipe: &Recipe) -> Result<String, BackendError> {
+    let entry = recipe.entry().ok_or(BackendError::NoEntry)?;
+
+    let mut unit = Unit::new();
+
+    let mut main = Frame::new(depth(entry));
+    unit.lower(&mut main, entry)?;
+
+    let mut asm = String::from("\t.text\n");
+
+    for function in unit.functions.iter() {
+        asm.push('\n');
+        asm.push_str(function);
+    }
+
+    asm.push_str("\n\t.globl\tmain\n");
+    asm.push_str(&main.finish("main"));
+
+    if !unit.constants.is_empty() {
+        asm.push_str("\n\t.section\t.rodata\n");
+        asm.push_str(&unit.constants);
+    }
+
+    // Do not request an executable stack
+    asm.push_str("\n\t.section\t.note.GNU-stack,\"\",@progbits\n");
+
+    Ok(asm)
+}
+
+/// Return the symbol of a block
+fn symbol(label: &str) -> String {
+    format!("stir{}", label)
+}
+
+/// Return the number of nested loops in a block, not counting the loops of
+/// the functions it calls
+fn depth(block: &dyn BasicBlock) -> usize {
+    let kind = block.kind();
+
+    let nested = match kind {
+        BlockKind::Function(_) | BlockKind::Call(_) => return 0,
+        _ => kind.children().iter().map(|c| depth(*c)).max().unwrap_or(0),
+    };
+
+    match kind {
+        BlockKind::Loop(_) => nested + 1,
+        _ => nested,
+    }
+}
+
+/// Definitions shared by all the functions of the file
+struct Unit {
+    functions: Vec<String>,
+
+    /// Content of the read-only data section
+    constants: String,
+
+    /// Labels of the blocks already defined in the file
+    defined: HashSet<String>,
+
+    /// Index used to generate unique local labels
+    next: usize,
+}
+
+/// Function being generated
+struct Frame {
+    text: String,
+
+    /// Number of locations needed by the loops of the function
+    locations: usize,
+
+    /// Current level of loop nesting
+    level: usize,
+}
+
+impl Frame {
+    fn new(depth: usize) -> Frame {
+        Frame {
+            text: String::new(),
+            locations: 3 * depth,
+            level: 0,
+        }
+    }
+
+    /// Number of callee-saved registers used by the function
+    fn saved(&self) -> usize {
+        self.locations.min(CALLEE_SAVED.len())
+    }
+
+    /// Number of locations spilled to the stack
+    fn spilled(&self) -> usize {
+        self.locations - self.saved()
+    }
+
+    /// Return the operand of a location: a register, or a slot of the frame
+    /// below the saved registers
+    fn location(&self, index: usize) -> String {
+        match CALLEE_SAVED.get(index) {
+            Some(register) => String::from(*register),
+            None => {
+                let slot = self.saved() + 1 + index - CALLEE_SAVED.len();
+                format!("-{}(%rbp)", 8 * slot)
+            }
+        }
+    }
+
+    /// Add an instruction
+    fn inst(&mut self, inst: &str) {
+        self.text.push('\t');
+        self.text.push_str(inst);
+        self.text.push('\n');
+    }
+
+    /// Add a label
+    fn label(&mut self, label: &str) {
+        self.text.push_str(label);
+        self.text.push_str(":\n");
+    }
+
+    fn finish(self, name: &str) -> String {
+        let mut function = Frame::new(0);
+        function.text = format!("\t.type\t{}, @function\n{}:\n", name, name);
+
+        function.inst("pushq\t%rbp");
+        function.inst("movq\t%rsp, %rbp");
+        for register in CALLEE_SAVED.iter().take(self.saved()) {
+            function.inst(&format!("pushq\t{}", register));
+        }
+
+        // Keep the stack aligned on 16 bytes at calls
+        let mut frame = 8 * self.spilled();
+        if !(8 * self.saved() + frame).is_multiple_of(16) {
+            frame += 8;
+        }
+        if frame != 0 {
+            function.inst(&format!("subq\t${}, %rsp", frame));
+        }
+
+        function.text.push_str(&self.text);
+
+        match self.saved() {
+            0 => function.inst("movq\t%rbp, %rsp"),
+            saved => function.inst(&format!("leaq\t-{}(%rbp), %rsp", 8 * saved)),
+        }
+        for register in CALLEE_SAVED.iter().take(self.saved()).rev() {
+            function.inst(&format!("popq\t{}", register));
+        }
+        function.inst("popq\t%rbp");
+        function.inst("ret");
+        function.inst(&format!(".size\t{}, .-{}", name, name));
+
+        function.text
+    }
+}
+
+impl Unit {
+    fn new() -> Unit {
+        Unit {
+            functions: Vec::new(),
+            constants: String::new(),
+            defined: HashSet::new(),
+            next: 0,
+        }
+    }
+
+    /// Return a new unique local label
+    fn fresh(&mut self) -> String {
+        self.next += 1;
+        format!(".L{}", self.next)
+    }
+
+    /// Generate the instructions computing the value of a block in `%eax`
+    fn lower(&mut self, f: &mut Frame, block: &dyn BasicBlock) -> Result<(), BackendError> {
+        match block.kind() {
+            BlockKind::Boolean(b) => f.inst(&format!("movl\t${}, %eax", b.get() as i32)),
+            BlockKind::Number(n) => {
+                let constant = self.fresh();
+                self.constants.push_str(&format!(
+                    "\t.p2align\t3\n{}:\n\t.quad\t{:#x}\n",
+                    constant,
+                    n.get().to_bits()
+                ));
+
+                // A number is `true` unless it is NaN, which is unordered
+                // with itself
+                f.inst(&format!("movsd\t{}(%rip), %xmm0", constant));
+                f.inst("ucomisd\t%xmm0, %xmm0");
+                f.inst("setnp\t%al");
+                f.inst("movzbl\t%al, %eax");
+            }
+            BlockKind::Str(s) => {
+                let name = symbol(s.label());
+
+                if self.defined.insert(s.label().clone()) {
+                    self.constants.push_str(&format!(
+                        "{}:\n\t.ascii\t\"{}\"\n{}_end:\n",
+                        name,
+                        escape(&s.get()),
+                        name
+                    ));
+                }
+
+                // Strings may contain null bytes: use their size
+                f.inst(&format!("movl\t${}_end - {}, %eax", name, name));
+                f.inst("testl\t%eax, %eax");
+                f.inst("setne\t%al");
+                f.inst("movzbl\t%al, %eax");
+            }
+            BlockKind::IfElse(ie) => {
+                let f_branch = self.fresh();
+                let end = self.fresh();
+
+                self.lower(f, ie.cond_block())?;
+                f.inst("testl\t%eax, %eax");
+                f.inst(&format!("je\t{}", f_branch));
+
+                self.lower(f, ie.t_block())?;
+                f.inst(&format!("jmp\t{}", end));
+
+                f.label(&f_branch);
+                match ie.f_block() {
+                    Some(f_block) => self.lower(f, f_block)?,
+                    None => f.inst("movl\t$0, %eax"),
+                }
+
+                f.label(&end);
+            }
+            BlockKind::Loop(l) => self.lower_loop(f, l)?,
+            BlockKind::Function(func) => self.call(f, func)?,
+            BlockKind::Call(call) => self.call(f, call.function())?,
+            BlockKind::Critical(crit) => {
+                f.inst(&format!("# critical {}", crit.label()));
+                self.lower(f, crit.block())?;
+            }
+            BlockKind::Arithmetic(_) => {
+                // The result is only used as an integer: the block is `true`
+                self.integer(f, block)?;
+                f.inst("movl\t$1, %eax");
+            }
+            BlockKind::Compare(c) => {
+                self.operands(f, c.lhs(), c.rhs())?;
+                f.inst("cmpq\t%rcx, %rax");
+                f.inst(match c.op() {
+                    CompareOp::Eq => "sete\t%al",
+                    CompareOp::Ne => "setne\t%al",
+                    CompareOp::Lt => "setl\t%al",
+                    CompareOp::Le => "setle\t%al",
+                    CompareOp::Gt => "setg\t%al",
+                    CompareOp::Ge => "setge\t%al",
+                });
+                f.inst("movzbl\t%al, %eax");
+            }
+            BlockKind::Other => return Err(BackendError::Unsupported(block.label().clone())),
+        }
+
+        Ok(())
+    }
+
+    /// Generate a loop. Its value is the conjunction of the values of the
+    /// body at each iteration
+    fn lower_loop(&mut self, f: &mut Frame, l: &Loop) -> Result<(), BackendError> {
+        let head = self.fresh();
+
+        let (lo, hi) = match (l.lo_bound(), l.hi_bound()) {
+            (Some(lo), Some(hi)) => (lo, hi),
+            _ => {
+                // Infinite loop: the instructions following it are never
+                // reached
+                f.label(&head);
+                if let Some(body) = l.body() {
+                    f.level += 1;
+                    self.lower(f, body)?;
+                    f.level -= 1;
+                }
+                f.inst(&format!("jmp\t{}", head));
+                f.inst("movl\t$1, %eax");
+
+                return Ok(());
+            }
+        };
+
+        let end = self.fresh();
+
+        let idx = f.location(3 * f.level);
+        let hi_loc = f.location(3 * f.level + 1);
+        let acc = f.location(3 * f.level + 2);
+
+        f.level += 1;
+
+        self.integer(f, lo)?;
+        f.inst(&format!("movq\t%rax, {}", idx));
+        self.integer(f, hi)?;
+        f.inst(&format!("movq\t%rax, {}", hi_loc));
+        f.inst(&format!("movq\t$1, {}", acc));
+
+        f.inst(&format!("movq\t{}, %rax", idx));
+        f.inst(&format!("cmpq\t{}, %rax", hi_loc));
+        f.inst(&format!("jge\t{}", end));
+
+        f.label(&head);
+        if let Some(body) = l.body() {
+            self.lower(f, body)?;
+            f.inst(&format!("andq\t%rax, {}", acc));
+        }
+
+        f.inst(&format!("incq\t{}", idx));
+        f.inst(&format!("movq\t{}, %rax", idx));
+        f.inst(&format!("cmpq\t{}, %rax", hi_loc));
+        f.inst(&format!("jl\t{}", head));
+
+        f.level -= 1;
+
+        f.label(&end);
+        f.inst(&format!("movq\t{}, %rax", acc));
+
+        Ok(())
+    }
+
+    /// Compute the value of a loop bound or an operand in `%rax`: `Number`
+    /// and `Arithmetic` blocks count as their value, other blocks as `1` if
+    /// they evaluate to `true`
+    fn integer(&mut self, f: &mut Frame, block: &dyn BasicBlock) -> Result<(), BackendError> {
+        match block.kind() {
+            BlockKind::Number(n) => f.inst(&format!("movabsq\t${}, %rax", n.get() as i64)),
+            BlockKind::Arithmetic(a) => {
+                self.operands(f, a.lhs(), a.rhs())?;
+                f.inst(match a.op() {
+                    ArithOp::Add => "addq\t%rcx, %rax",
+                    ArithOp::Sub => "subq\t%rcx, %rax",
+                    ArithOp::Mul => "imulq\t%rcx, %rax",
+                });
+            }
+            // Writing `%eax` clears the upper half of `%rax`
+            _ => self.lower(f, block)?,
+        }
+
+        Ok(())
+    }
+
+    /// Compute the integer values of two operands, in `%rax` and `%rcx`. The
+    /// left one is kept on the stack meanwhile, which stays aligned on 16
+    /// bytes for the calls computing the right one
+    fn operands(
+        &mut self,
+        f: &mut Frame,
+        lhs: &dyn BasicBlock,
+        rhs: &dyn BasicBlock,
+    ) -> Result<(), BackendError> {
+        self.integer(f, lhs)?;
+        f.inst("subq\t$16, %rsp");
+        f.inst("movq\t%rax, (%rsp)");
+        self.integer(f, rhs)?;
+        f.inst("movq\t%rax, %rcx");
+        f.inst("movq\t(%rsp), %rax");
+        f.inst("addq\t$16, %rsp");
+
+        Ok(())
+    }
+
+    /// Define a function if needed, and call it
+    fn call(&mut self, f: &mut Frame, func: &Function) -> Result<(), BackendError> {
+        let name = symbol(func.label());
+
+        if self.defined.insert(func.label().clone()) {
+            let depth = func
+                .stmts()
+                .iter()
+                .copied()
+                .chain(func.retval())
+                .map(depth)
+                .max();
+            let mut body = Frame::new(depth.unwrap_or(0));
+
+            for stmt in func.stmts().iter() {
+                self.lower(&mut body, *stmt)?;
+            }
+
+            match func.retval() {
+                Some(retval) => self.lower(&mut body, retval)?,
+                None => body.inst("movl\t$0, %eax"),
+            }
+
+            self.functions.push(body.finish(&name));
+        }
+
+        f.inst(&format!("call\t{}", name));
+
+        Ok(())
+    }
+}
+
+/// Escape a string to be used in an `.ascii` directive
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for byte in s.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::testing::{called_twice, compared_product, emit_entry};
+    use crate::blocks::{Boolean, Number, Str};
+
+    #[test]
+    fn main_without_loops() {
+        let b = Boolean::new(true);
+
+        assert_eq!(
+            emit_entry("asm", &b),
+            "\t.text\n\n\t.globl\tmain\n\t.type\tmain, @function\nmain:\n\tpushq\t%rbp\n\
+             \tmovq\t%rsp, %rbp\n\tmovl\t$1, %eax\n\tmovq\t%rbp, %rsp\n\tpopq\t%rbp\n\tret\n\
+             \t.size\tmain, .-main\n\n\t.section\t.note.GNU-stack,\"\",@progbits\n"
+        );
+    }
+
+    #[test]
+    fn primitives() {
+        let n = Number::new(f64::NAN);
+        let s = Str::new(String::from("a\"\\\0"));
+        let stmts: Vec<&dyn BasicBlock> = vec![&n, &s];
+        let func = Function::new(None, &stmts);
+
+        let asm = emit_entry("asm", &func);
+
+        assert!(asm.contains("\t.quad\t0x7ff8000000000000\n"));
+        assert!(asm.contains("ucomisd\t%xmm0, %xmm0\n\tsetnp\t%al\n"));
+        assert!(asm.contains(&format!(
+            "{}:\n\t.ascii\t\"a\\\"\\\\\\000\"\n",
+            symbol(s.label())
+        )));
+    }
+
+    #[test]
+    fn register_allocation() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(2.0);
+        let body = Boolean::new(true);
+        let inner = Loop::new(Some(&lo), Some(&hi), Some(&body));
+        let outer = Loop::new(Some(&lo), Some(&hi), Some(&inner));
+
+        let asm = emit_entry("asm", &outer);
+
+        // The outer loop uses three registers, the inner one two registers
+        // and a stack slot
+        assert_eq!(depth(&outer), 2);
+        assert!(asm.contains("\tpushq\t%r15\n\tsubq\t$8, %rsp\n"));
+        assert!(asm.contains("\tmovq\t%rax, %rbx\n"));
+        assert!(asm.contains("\tmovq\t$1, %r13\n"));
+        assert!(asm.contains("\tmovq\t$1, -48(%rbp)\n"));
+        assert!(asm.contains("\tleaq\t-40(%rbp), %rsp\n\tpopq\t%r15\n"));
+    }
+
+    #[test]
+    fn functions_defined_once() {
+        called_twice("asm", |asm, func, crit| {
+            let name = symbol(func.label());
+
+            assert_eq!(asm.matches(&format!("\n{}:\n", name)).count(), 1);
+            assert_eq!(asm.matches(&format!("\tcall\t{}\n", name)).count(), 2);
+            assert!(asm.contains(&format!("# critical {}\n", crit.label())));
+        });
+    }
+
+    #[test]
+    fn integers() {
+        let source = compared_product("asm");
+
+        assert!(source.contains("subq\t%rcx, %rax"));
+        assert!(source.contains("imulq\t%rcx, %rax"));
+        assert!(source.contains("setl\t%al"));
+    }
+}
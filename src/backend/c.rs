@@ -0,0 +1,837 @@
+//! Translation of a `Recipe` to a self-contained C11 translation unit.
+//!
+//! Every block evaluates to a `bool`, following the semantics of the
+//! interpreter. `Function` blocks become static C functions without
+//! arguments, and the entry block of the recipe is evaluated by `main`,
+//! whose exit code is `1` if the entry block evaluated to `true`. `Critical`
+//! blocks are guarded by a global pthread mutex, which can be locked again
+//! by the thread owning it.
+//!
+//! Identifiers are prefixed with `stir` as C reserves identifiers starting
+//! with two underscores.
+//!
+//! `emit_openmp` generates OpenMP annotations instead: the outermost loops
+//! whose iterations are independent and which the `CostModel` deems worth it
+//! become `omp parallel for` loops, reducing their value, and `Critical`
+//! blocks become `omp critical` regions sharing a single name, in which loops
+//! are not parallelized. Regions of the same name cannot be nested: functions
+//! called from a critical region get a second definition without them.
+//! Compile the result using `-fopenmp`.
+
+use std::collections::HashSet;
+
+use super::BackendError;
+
+use crate::analysis;
+use crate::blocks::{BasicBlock, BlockKind, Function, Loop, Primitive};
+use crate::cost::CostModel;
+use crate::opt::{Remark, RemarkKind};
+use crate::recipe::Recipe;
+
+/// Translate a recipe to a C11 translation unit
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::c;
+/// use stir::blocks::{Boolean, IfElse};
+/// use stir::recipe::Recipe;
+///
+/// let c = Boolean::new(true);
+/// let t = Boolean::new(false);
+/// let ie = IfElse::new(&c, &t, None);
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&ie);
+///
+/// let source = c::emit(&recipe).unwrap();
+///
+/// assert!(source.contains("int main(void)"));
+/// ```
+pub fn emit(recipe: &Recipe) -> Result<String, BackendError> {
+    translate(recipe, false, &CostModel::new()).map(|(source, _)| source)
+}
+
+/// Translate a recipe to a C11 translation unit using OpenMP to execute
+/// independent loop iterations in parallel, using the default `CostModel`
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::c;
+/// use stir::blocks::{Boolean, Loop, Number};
+/// use stir::recipe::Recipe;
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(1000.0);
+/// let body = Boolean::new(true);
+/// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&l);
+///
+/// let source = c::emit_openmp(&recipe).unwrap();
+///
+/// assert!(source.contains("#pragma omp parallel for reduction(&:v1)"));
+/// ```
+pub fn emit_openmp(recipe: &Recipe) -> Result<String, BackendError> {
+    translate(recipe, true, &CostModel::new()).map(|(source, _)| source)
+}
+
+/// Translate a recipe to a C11 translation unit using OpenMP, parallelizing
+/// the loops estimated to perform enough work by the given cost model
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::c;
+/// use stir::blocks::{Boolean, Loop, Number};
+/// use stir::cost::CostModel;
+/// use stir::recipe::Recipe;
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(2.0);
+/// let body = Boolean::new(true);
+/// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&l);
+///
+/// let mut model = CostModel::new();
+///
+/// assert!(!c::emit_openmp_with(&recipe, &model).unwrap().contains("#pragma"));
+///
+/// model.set_threshold(0);
+///
+/// assert!(c::emit_openmp_with(&recipe, &model).unwrap().contains("#pragma"));
+/// ```
+pub fn emit_openmp_with(recipe: &Recipe, cost_model: &CostModel) -> Result<String, BackendError> {
+    translate(recipe, true, cost_model).map(|(source, _)| source)
+}
+
+/// Translate a recipe to a C11 translation unit using OpenMP like
+/// `emit_openmp_with`, and return the remarks explaining why each loop was
+/// parallelized or not
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::c;
+/// use stir::blocks::{Boolean, Loop, Number};
+/// use stir::cost::CostModel;
+/// use stir::opt::RemarkKind;
+/// use stir::recipe::Recipe;
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(1000.0);
+/// let body = Boolean::new(true);
+/// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&l);
+///
+/// let (_, remarks) = c::emit_openmp_with_remarks(&recipe, &CostModel::new()).unwrap();
+///
+/// assert_eq!(remarks.len(), 1);
+/// assert_eq!(remarks[0].kind(), RemarkKind::Applied);
+/// ```
+pub fn emit_openmp_with_remarks(
+    recipe: &Recipe,
+    cost_model: &CostModel,
+) -> Result<(String, Vec<Remark>), BackendError> {
+    translate(recipe, true, cost_model)
+}
+
+fn translate(
+    recipe: &Recipe,
+    openmp: bool,
+    cost_model: &CostModel,
+) -> Result<(String, Vec<Remark>), BackendError> {
+    let entry = recipe.entry().ok_or(BackendError::NoEntry)?;
+
+    let mut unit = Unit::new(openmp, cost_model.clone());
+    let mut main = Body::new();
+
+    let value = unit.lower(&mut main, entry)?;
+    main.line(format!("return {};", value));
+
+    let mut source = String::from("#include <math.h>\n#include <stdbool.h>\n");
+    if unit.critical {
+        source.push_str("#include <pthread.h>\n");
+    }
+    source.push('\n');
+
+    if unit.critical {
+        source.push_str(CRITICAL);
+        source.push('\n');
+    }
+
+    for global in unit.globals.iter() {
+        source.push_str(global);
+        source.push('\n');
+    }
+    if !unit.globals.is_empty() {
+        source.push('\n');
+    }
+
+    for prototype in unit.prototypes.iter() {
+        source.push_str(prototype);
+        source.push_str(";\n");
+    }
+    if !unit.prototypes.is_empty() {
+        source.push('\n');
+    }
+
+    for function in unit.functions.iter() {
+        source.push_str(function);
+        source.push('\n');
+    }
+
+    source.push_str(&main.finish("int main(void)"));
+
+    Ok((source, unit.remarks))
+}
+
+/// Helpers guarding `Critical` blocks. The mutex is only locked by the
+/// outermost critical block of a thread
+const CRITICAL: &str = "static pthread_mutex_t stir_critical = PTHREAD_MUTEX_INITIALIZER;
+static _Thread_local unsigned stir_critical_depth = 0;
+
+static void stir_critical_enter(void)
+{
+    if (stir_critical_depth++ == 0)
+        pthread_mutex_lock(&stir_critical);
+}
+
+static void stir_critical_exit(void)
+{
+    if (--stir_critical_depth == 0)
+        pthread_mutex_unlock(&stir_critical);
+}
+";
+
+/// Return the C identifier of a block
+fn ident(label: &str) -> String {
+    format!("stir{}", label)
+}
+
+/// Definitions shared by all the functions of the translation unit
+struct Unit {
+    globals: Vec<String>,
+    prototypes: Vec<String>,
+    functions: Vec<String>,
+
+    /// Labels of the blocks already defined in the translation unit
+    defined: HashSet<String>,
+
+    /// If the critical section helpers are needed
+    critical: bool,
+
+    /// Use OpenMP to parallelize loops and guard critical blocks
+    openmp: bool,
+
+    /// Decides which loops are worth parallelizing
+    cost_model: CostModel,
+
+    /// Number of parallel loops and critical blocks containing the block
+    /// being generated. Loops nested inside them are executed by one thread
+    nested: usize,
+
+    /// Number of critical blocks containing the block being generated, when
+    /// using OpenMP
+    critical_depth: usize,
+
+    /// Why each loop was parallelized or not, when using OpenMP
+    remarks: Vec<Remark>,
+}
+
+/// Body of a C function being generated
+struct Body {
+    text: String,
+
+    /// Current indentation level
+    depth: usize,
+
+    /// Index used to generate unique variable names
+    next: usize,
+}
+
+impl Body {
+    fn new() -> Body {
+        Body {
+            text: String::new(),
+            depth: 1,
+            next: 0,
+        }
+    }
+
+    /// Return a new unique variable name using the given prefix
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.next += 1;
+        format!("{}{}", prefix, self.next)
+    }
+
+    /// Add a line at the current indentation level
+    fn line(&mut self, line: String) {
+        for _ in 0..self.depth {
+            self.text.push_str("    ");
+        }
+        self.text.push_str(&line);
+        self.text.push('\n');
+    }
+
+    /// Open a new C block
+    fn open(&mut self, line: String) {
+        self.line(line);
+        self.depth += 1;
+    }
+
+    /// Close the current C block
+    fn close(&mut self, line: &str) {
+        self.depth -= 1;
+        self.line(String::from(line));
+    }
+
+    fn finish(self, signature: &str) -> String {
+        format!("{}\n{{\n{}}}\n", signature, self.text)
+    }
+}
+
+impl Unit {
+    fn new(openmp: bool, cost_model: CostModel) -> Unit {
+        Unit {
+            globals: Vec::new(),
+            prototypes: Vec::new(),
+            functions: Vec::new(),
+            defined: HashSet::new(),
+            critical: false,
+            openmp,
+            cost_model,
+            nested: 0,
+            critical_depth: 0,
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Generate the statements evaluating a block, and return the C
+    /// expression holding its value
+    fn lower(&mut self, b: &mut Body, block: &dyn BasicBlock) -> Result<String, BackendError> {
+        match block.kind() {
+            BlockKind::Boolean(boolean) => Ok(boolean.get().to_string()),
+            BlockKind::Number(n) => Ok(format!("!isnan({})", number(n.get()))),
+            BlockKind::Str(s) => {
+                let name = ident(s.label());
+
+                if self.defined.insert(s.label().clone()) {
+                    self.globals.push(format!(
+                        "static const char {}[] = \"{}\";",
+                        name,
+                        escape(&s.get())
+                    ));
+                }
+
+                // Strings may contain null bytes: use their size
+                Ok(format!("(sizeof({}) > 1)", name))
+            }
+            BlockKind::IfElse(ie) => {
+                let value = b.fresh("v");
+                b.line(format!("bool {};", value));
+
+                let cond = self.lower(b, ie.cond_block())?;
+                b.open(format!("if ({}) {{", cond));
+                let t_value = self.lower(b, ie.t_block())?;
+                b.line(format!("{} = {};", value, t_value));
+                b.close("} else {");
+                b.depth += 1;
+                let f_value = match ie.f_block() {
+                    Some(f_block) => self.lower(b, f_block)?,
+                    None => String::from("false"),
+                };
+                b.line(format!("{} = {};", value, f_value));
+                b.close("}");
+
+                Ok(value)
+            }
+            BlockKind::Loop(l) => self.lower_loop(b, l),
+            BlockKind::Function(func) => self.call(func),
+            BlockKind::Call(call) => self.call(call.function()),
+            BlockKind::Critical(crit) if self.openmp => {
+                let value = b.fresh("v");
+                b.line(format!("bool {};", value));
+                if self.critical_depth == 0 {
+                    b.line(String::from("#pragma omp critical(stir)"));
+                }
+                b.open(String::from("{"));
+                self.nested += 1;
+                self.critical_depth += 1;
+                let inner = self.lower(b, crit.block())?;
+                self.critical_depth -= 1;
+                self.nested -= 1;
+                b.line(format!("{} = {};", value, inner));
+                b.close("}");
+
+                Ok(value)
+            }
+            BlockKind::Critical(crit) => {
+                self.critical = true;
+
+                let value = b.fresh("v");
+                b.line(format!("bool {};", value));
+                b.open(format!("{{ /* critical {} */", crit.label()));
+                b.line(String::from("stir_critical_enter();"));
+                let inner = self.lower(b, crit.block())?;
+                b.line(format!("{} = {};", value, inner));
+                b.line(String::from("stir_critical_exit();"));
+                b.close("}");
+
+                Ok(value)
+            }
+            BlockKind::Arithmetic(_) => {
+                // The result is only used as an integer: the block is `true`
+                let value = self.integer(b, block)?;
+                b.line(format!("(void){};", value));
+
+                Ok(String::from("true"))
+            }
+            BlockKind::Compare(c) => {
+                let lhs = self.integer(b, c.lhs())?;
+                let rhs = self.integer(b, c.rhs())?;
+
+                Ok(format!("({} {} {})", lhs, c.op().symbol(), rhs))
+            }
+            BlockKind::Other => Err(BackendError::Unsupported(block.label().clone())),
+        }
+    }
+
+    /// Generate a loop. Its value is the conjunction of the values of the
+    /// body at each iteration
+    fn lower_loop(&mut self, b: &mut Body, l: &Loop) -> Result<String, BackendError> {
+        let value = b.fresh("v");
+        b.line(format!("bool {} = true;", value));
+
+        let parallel = self.openmp && self.parallelize(l);
+
+        match (l.lo_bound(), l.hi_bound()) {
+            (Some(lo), Some(hi)) => {
+                let lo = self.integer(b, lo)?;
+                let hi = self.integer(b, hi)?;
+                let idx = b.fresh("i");
+
+                if parallel {
+                    b.line(format!("#pragma omp parallel for reduction(&:{})", value));
+                }
+                b.open(format!(
+                    "for (long long {} = {}; {} < {}; {}++) {{",
+                    idx, lo, idx, hi, idx
+                ));
+            }
+            _ => b.open(String::from("for (;;) {")),
+        }
+
+        if parallel {
+            self.nested += 1;
+        }
+
+        if let Some(body) = l.body() {
+            let body_value = self.lower(b, body)?;
+            b.line(format!("{} &= {};", value, body_value));
+        }
+
+        if parallel {
+            self.nested -= 1;
+        }
+
+        b.close("}");
+
+        Ok(value)
+    }
+
+    /// Decide if a loop is worth an OpenMP parallel region, and record why
+    fn parallelize(&mut self, l: &Loop) -> bool {
+        let remark = if l.lo_bound().is_none() || l.hi_bound().is_none() {
+            Remark::missed("c-openmp", l.label(), "the loop is infinite")
+        } else if self.nested > 0 {
+            // Nested parallel regions would be executed by a single thread
+            Remark::missed(
+                "c-openmp",
+                l.label(),
+                "the loop is nested in a parallel loop or a critical block",
+            )
+        } else if !analysis::is_parallel(l) {
+            Remark::missed(
+                "c-openmp",
+                l.label(),
+                "the body contains blocks unknown to stir",
+            )
+        } else {
+            self.cost_model.remark("c-openmp", l)
+        };
+
+        let parallel = remark.kind() == RemarkKind::Applied;
+        self.remarks.push(remark);
+
+        parallel
+    }
+
+    /// Generate the value of a loop bound or an operand: `Number` and
+    /// `Arithmetic` blocks count as their value, other blocks as `1` if they
+    /// evaluate to `true`
+    fn integer(&mut self, b: &mut Body, block: &dyn BasicBlock) -> Result<String, BackendError> {
+        match block.kind() {
+            BlockKind::Number(n) => Ok(format!("{}LL", n.get() as i64)),
+            BlockKind::Arithmetic(a) => {
+                let lhs = self.integer(b, a.lhs())?;
+                let rhs = self.integer(b, a.rhs())?;
+                let wide = b.fresh("b");
+
+                // Signed overflow is undefined: wrap around using unsigned
+                // arithmetic
+                b.line(format!(
+                    "long long {} = (long long)((unsigned long long){} {} (unsigned long long){});",
+                    wide,
+                    lhs,
+                    a.op().symbol(),
+                    rhs
+                ));
+                Ok(wide)
+            }
+            _ => {
+                let value = self.lower(b, block)?;
+                let wide = b.fresh("b");
+                b.line(format!("long long {} = {};", wide, value));
+                Ok(wide)
+            }
+        }
+    }
+
+    /// Define a function if needed, and return the expression calling it
+    fn call(&mut self, func: &Function) -> Result<String, BackendError> {
+        // Critical regions called from a critical region must not be entered
+        // again: call a definition without them
+        let critical = self.critical_depth > 0 && analysis::has_critical(func);
+        let (name, key) = match critical {
+            true => (
+                format!("{}_critical", ident(func.label())),
+                format!("{}_critical", func.label()),
+            ),
+            false => (ident(func.label()), func.label().clone()),
+        };
+
+        if self.defined.insert(key) {
+            let mut body = Body::new();
+
+            // Functions are defined once, possibly called from parallel
+            // loops or not
+            let nested = std::mem::replace(&mut self.nested, critical as usize);
+            let critical_depth = std::mem::replace(&mut self.critical_depth, critical as usize);
+
+            for stmt in func.stmts().iter() {
+                let value = self.lower(&mut body, *stmt)?;
+                body.line(format!("(void){};", value));
+            }
+
+            let retval = match func.retval() {
+                Some(retval) => self.lower(&mut body, retval)?,
+                None => String::from("false"),
+            };
+            body.line(format!("return {};", retval));
+
+            self.nested = nested;
+            self.critical_depth = critical_depth;
+
+            let signature = format!("static bool {}(void)", name);
+            self.functions.push(body.finish(&signature));
+            self.prototypes.push(signature);
+        }
+
+        Ok(format!("{}()", name))
+    }
+}
+
+/// Return the C literal of a double
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NAN")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "INFINITY" } else { "-INFINITY" })
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Escape a string to be used in a C string literal
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for byte in s.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b'?' => escaped.push_str("\\?"),
+            b' '..=b'~' => escaped.push(byte as char),
+            // Octal escapes are at most three digits long, unlike hexadecimal
+            // ones which would swallow the following characters
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::testing::{called_twice, compared_product, emit_entry};
+    use crate::blocks::{Boolean, Call, Critical, IfElse, Number, Str};
+
+    #[test]
+    fn main_returns_entry() {
+        let b = Boolean::new(true);
+        let source = emit_entry("c", &b);
+
+        assert!(source.starts_with("#include <math.h>\n#include <stdbool.h>\n\n"));
+        assert!(source.ends_with("int main(void)\n{\n    return true;\n}\n"));
+    }
+
+    #[test]
+    fn primitives() {
+        let n = Number::new(f64::NAN);
+        let s = Str::new(String::from("a \"str\"\n"));
+        let stmts: Vec<&dyn BasicBlock> = vec![&n, &s];
+        let func = Function::new(None, &stmts);
+
+        let source = emit_entry("c", &func);
+
+        assert!(source.contains("    (void)!isnan(NAN);\n"));
+        assert!(source.contains(&format!(
+            "static const char stir{}[] = \"a \\\"str\\\"\\012\";",
+            s.label()
+        )));
+        assert!(source.contains(&format!("(void)(sizeof(stir{}) > 1);", s.label())));
+        assert_eq!(number(12.0), "12.0");
+        assert_eq!(number(f64::NEG_INFINITY), "-INFINITY");
+    }
+
+    #[test]
+    fn if_else() {
+        let c = Boolean::new(true);
+        let t = Boolean::new(true);
+        let ie = IfElse::new(&c, &t, None);
+
+        let source = emit_entry("c", &ie);
+
+        assert!(source.contains(
+            "    bool v1;\n    if (true) {\n        v1 = true;\n    } else {\n        v1 = false;\n    }\n    return v1;\n"
+        ));
+    }
+
+    #[test]
+    fn loops() {
+        let lo = Number::new(2.0);
+        let hi = Number::new(10.0);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+        let infinite = Loop::new(None, None, None);
+        let stmts: Vec<&dyn BasicBlock> = vec![&l, &infinite];
+        let func = Function::new(None, &stmts);
+
+        let source = emit_entry("c", &func);
+
+        assert!(source.contains(
+            "    bool v1 = true;\n    for (long long i2 = 2LL; i2 < 10LL; i2++) {\n        v1 &= true;\n    }\n"
+        ));
+        assert!(source.contains("    bool v3 = true;\n    for (;;) {\n    }\n"));
+    }
+
+    #[test]
+    fn critical() {
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+
+        let source = emit_entry("c", &crit);
+
+        assert!(source.contains("#include <pthread.h>\n"));
+        assert!(source.contains("static _Thread_local unsigned stir_critical_depth = 0;"));
+        assert!(source.contains(
+            "        stir_critical_enter();\n        v1 = true;\n        stir_critical_exit();\n"
+        ));
+    }
+
+    #[test]
+    fn functions_defined_once() {
+        called_twice("c", |source, func, _| {
+            let signature = format!("static bool stir{}(void)", func.label());
+
+            assert_eq!(source.matches(&format!("{};\n", signature)).count(), 1);
+            assert_eq!(source.matches(&format!("{}\n{{", signature)).count(), 1);
+            assert_eq!(
+                source
+                    .matches(&format!("(void)stir{}();", func.label()))
+                    .count(),
+                2
+            );
+        });
+    }
+
+    #[test]
+    fn openmp_outermost_loop() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(100.0);
+        let body = Boolean::new(true);
+        let inner = Loop::new(Some(&lo), Some(&hi), Some(&body));
+        let outer = Loop::new(Some(&lo), Some(&hi), Some(&inner));
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&outer);
+
+        let source = emit_openmp(&recipe).unwrap();
+
+        assert!(source.contains(
+            "    bool v1 = true;\n    #pragma omp parallel for reduction(&:v1)\n    for (long long i2 = 0LL; i2 < 100LL; i2++) {\n"
+        ));
+        assert_eq!(source.matches("#pragma omp parallel for").count(), 1);
+    }
+
+    #[test]
+    fn openmp_cheap_loop() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(2.0);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&l);
+
+        assert!(!emit_openmp(&recipe).unwrap().contains("#pragma"));
+    }
+
+    #[test]
+    fn openmp_function_in_parallel_loop() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let body = Boolean::new(true);
+        let inner = Loop::new(Some(&lo), Some(&hi), Some(&body));
+        let stmts: Vec<&dyn BasicBlock> = vec![&inner];
+        let func = Function::new(None, &stmts);
+        let outer = Loop::new(Some(&lo), Some(&hi), Some(&func));
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&outer);
+
+        let mut model = CostModel::new();
+        model.set_threshold(0);
+
+        let source = emit_openmp_with(&recipe, &model).unwrap();
+
+        // The function is defined while generating the parallel loop, but may
+        // be called from anywhere
+        assert_eq!(source.matches("#pragma omp parallel for").count(), 2);
+    }
+
+    #[test]
+    fn openmp_infinite_loop() {
+        let infinite = Loop::new(None, None, None);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&infinite);
+
+        assert!(!emit_openmp(&recipe).unwrap().contains("#pragma"));
+    }
+
+    #[test]
+    fn openmp_critical() {
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&b));
+        let crit_loop = Critical::new(&l);
+        let stmts: Vec<&dyn BasicBlock> = vec![&crit_loop];
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&crit);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+
+        let source = emit_openmp(&recipe).unwrap();
+
+        assert!(!source.contains("pthread"));
+        assert!(!source.contains("parallel"));
+        assert!(source.contains(
+            "    bool v4;\n    #pragma omp critical(stir)\n    {\n        v4 = true;\n    }\n"
+        ));
+    }
+
+    #[test]
+    fn openmp_nested_critical() {
+        let b = Boolean::new(true);
+        let inner = Critical::new(&b);
+        let outer = Critical::new(&inner);
+
+        // f is called both inside and outside of a critical region
+        let f_stmts: Vec<&dyn BasicBlock> = vec![&inner];
+        let f = Function::new(None, &f_stmts);
+        let call_inside = Call::new(&f, None);
+        let call_outside = Call::new(&f, None);
+        let crit_call = Critical::new(&call_inside);
+
+        let stmts: Vec<&dyn BasicBlock> = vec![&outer, &crit_call, &call_outside];
+        let func = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+
+        let source = emit_openmp(&recipe).unwrap();
+
+        // All the regions share a single lock, entered once per thread
+        assert_eq!(source.matches("#pragma omp critical").count(), 3);
+        assert_eq!(source.matches("#pragma omp critical(stir)").count(), 3);
+        assert!(source.contains(&format!("static bool stir{}(void)", f.label())));
+        assert!(source.contains(&format!("static bool stir{}_critical(void)", f.label())));
+    }
+
+    #[test]
+    fn openmp_remarks() {
+        let b = Boolean::new(true);
+        let lo = Number::new(0.0);
+        let hi = Number::new(1000.0);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&b));
+        let crit_loop = Critical::new(&l);
+        let infinite = Loop::new(None, None, Some(&b));
+        let stmts: Vec<&dyn BasicBlock> = vec![&crit_loop, &infinite];
+        let func = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+
+        let (_, remarks) = emit_openmp_with_remarks(&recipe, &CostModel::new()).unwrap();
+        let reasons: Vec<&str> = remarks.iter().map(|remark| remark.reason()).collect();
+
+        assert_eq!(
+            reasons,
+            [
+                "the loop is nested in a parallel loop or a critical block",
+                "the loop is infinite"
+            ]
+        );
+        assert!(remarks
+            .iter()
+            .all(|remark| remark.kind() == RemarkKind::Missed && remark.pass() == "c-openmp"));
+
+        // Without OpenMP, no loop is considered for parallelization
+        assert!(translate(&recipe, false, &CostModel::new())
+            .unwrap()
+            .1
+            .is_empty());
+    }
+
+    #[test]
+    fn integers() {
+        let source = compared_product("c");
+
+        assert!(source.contains("(long long)((unsigned long long)2LL - (unsigned long long)5LL)"));
+        assert!(source.contains(" < 1LL)"));
+    }
+}
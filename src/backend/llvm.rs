@@ -0,0 +1,472 @@
+//! Translation of a `Recipe` to textual LLVM IR. The generated module can be
+//! compiled using `llc` or `clang` without linking against LLVM.
+//!
+//! Every block evaluates to an `i1`, following the semantics of the
+//! interpreter. `Function` blocks become internal LLVM functions without
+//! arguments, and the entry block of the recipe is evaluated by `@main`,
+//! whose exit code is `1` if the entry block evaluated to `true`.
+
+use std::collections::HashSet;
+
+use super::BackendError;
+
+use crate::blocks::{ArithOp, BasicBlock, BlockKind, CompareOp, Function, Loop, Primitive};
+use crate::recipe::Recipe;
+
+/// Translate a recipe to a textual LLVM IR module
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::llvm;
+/// use stir::blocks::{Boolean, IfElse};
+/// use stir::recipe::Recipe;
+///
+/// let c = Boolean::new(true);
+/// let t = Boolean::new(false);
+/// let ie = IfElse::new(&c, &t, None);
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&ie);
+///
+/// let ir = llvm::emit(&recipe).unwrap();
+///
+/// assert!(ir.contains("define i32 @main()"));
+/// ```
+pub fn emit(recipe: &Recipe) -> Result<String, BackendError> {
+    let entry = recipe.entry().ok_or(BackendError::NoEntry)?;
+
+    let mut module = Module::new();
+    let mut main = FunctionBuilder::new();
+
+    let value = module.lower(&mut main, entry)?;
+    main.inst(format!("%exit = zext i1 {} to i32", value));
+    main.inst(String::from("ret i32 %exit"));
+
+    let mut ir = String::from("; ModuleID = 'stir'\nsource_filename = \"stir\"\n\n");
+
+    for global in module.globals.iter() {
+        ir.push_str(global);
+        ir.push('\n');
+    }
+    if !module.globals.is_empty() {
+        ir.push('\n');
+    }
+
+    for function in module.functions.iter() {
+        ir.push_str(function);
+        ir.push('\n');
+    }
+
+    ir.push_str(&main.finish("define i32 @main()"));
+
+    Ok(ir)
+}
+
+/// Definitions shared by all the functions of the module
+struct Module {
+    globals: Vec<String>,
+    functions: Vec<String>,
+
+    /// Labels of the blocks already defined at the module level
+    defined: HashSet<String>,
+}
+
+/// Body of an LLVM function being generated
+struct FunctionBuilder {
+    body: String,
+
+    /// Index used to generate unique value and basic block names
+    next: usize,
+
+    /// Name of the basic block instructions are currently added to
+    current: String,
+}
+
+impl FunctionBuilder {
+    fn new() -> FunctionBuilder {
+        FunctionBuilder {
+            body: String::from("entry:\n"),
+            next: 0,
+            current: String::from("entry"),
+        }
+    }
+
+    /// Return a new unique name using the given prefix
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.next += 1;
+        format!("{}.{}", prefix, self.next)
+    }
+
+    /// Add an instruction to the current basic block
+    fn inst(&mut self, inst: String) {
+        self.body.push_str("  ");
+        self.body.push_str(&inst);
+        self.body.push('\n');
+    }
+
+    /// Start a new basic block
+    fn start(&mut self, name: &str) {
+        self.body.push_str(name);
+        self.body.push_str(":\n");
+        self.current = String::from(name);
+    }
+
+    fn finish(self, signature: &str) -> String {
+        format!("{} {{\n{}}}\n", signature, self.body)
+    }
+}
+
+impl Module {
+    fn new() -> Module {
+        Module {
+            globals: Vec::new(),
+            functions: Vec::new(),
+            defined: HashSet::new(),
+        }
+    }
+
+    /// Generate the instructions evaluating a block, and return the `i1`
+    /// operand holding its value
+    fn lower(
+        &mut self,
+        f: &mut FunctionBuilder,
+        block: &dyn BasicBlock,
+    ) -> Result<String, BackendError> {
+        match block.kind() {
+            BlockKind::Boolean(b) => Ok(b.get().to_string()),
+            BlockKind::Number(n) => {
+                let value = f.fresh("%num");
+                f.inst(format!(
+                    "{} = fcmp ord double 0x{:016X}, 0.0",
+                    value,
+                    n.get().to_bits()
+                ));
+                Ok(value)
+            }
+            BlockKind::Str(s) => {
+                let value = s.get();
+
+                if self.defined.insert(s.label().clone()) {
+                    self.globals.push(format!(
+                        "@{} = private unnamed_addr constant [{} x i8] c\"{}\"",
+                        s.label(),
+                        value.len(),
+                        escape(&value)
+                    ));
+                }
+
+                Ok((!value.is_empty()).to_string())
+            }
+            BlockKind::IfElse(ie) => {
+                let cond = self.lower(f, ie.cond_block())?;
+
+                let t_name = f.fresh("then");
+                let f_name = f.fresh("else");
+                let end_name = f.fresh("endif");
+
+                f.inst(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    cond, t_name, f_name
+                ));
+
+                f.start(&t_name);
+                let t_value = self.lower(f, ie.t_block())?;
+                let t_pred = f.current.clone();
+                f.inst(format!("br label %{}", end_name));
+
+                f.start(&f_name);
+                let f_value = match ie.f_block() {
+                    Some(f_block) => self.lower(f, f_block)?,
+                    None => String::from("false"),
+                };
+                let f_pred = f.current.clone();
+                f.inst(format!("br label %{}", end_name));
+
+                f.start(&end_name);
+                let value = f.fresh("%if");
+                f.inst(format!(
+                    "{} = phi i1 [ {}, %{} ], [ {}, %{} ]",
+                    value, t_value, t_pred, f_value, f_pred
+                ));
+
+                Ok(value)
+            }
+            BlockKind::Loop(l) => self.lower_loop(f, l),
+            BlockKind::Function(func) => self.call(f, func),
+            BlockKind::Call(call) => self.call(f, call.function()),
+            BlockKind::Critical(crit) => {
+                f.inst(format!("; critical {}", crit.label()));
+                self.lower(f, crit.block())
+            }
+            BlockKind::Arithmetic(_) => {
+                // The result is only used as an integer: the block is `true`
+                self.integer(f, block)?;
+                Ok(String::from("true"))
+            }
+            BlockKind::Compare(c) => {
+                let lhs = self.integer(f, c.lhs())?;
+                let rhs = self.integer(f, c.rhs())?;
+                let predicate = match c.op() {
+                    CompareOp::Eq => "eq",
+                    CompareOp::Ne => "ne",
+                    CompareOp::Lt => "slt",
+                    CompareOp::Le => "sle",
+                    CompareOp::Gt => "sgt",
+                    CompareOp::Ge => "sge",
+                };
+
+                let value = f.fresh("%cmp");
+                f.inst(format!(
+                    "{} = icmp {} i64 {}, {}",
+                    value, predicate, lhs, rhs
+                ));
+                Ok(value)
+            }
+            BlockKind::Other => Err(BackendError::Unsupported(block.label().clone())),
+        }
+    }
+
+    /// Generate a loop. The induction variable and the conjunction of the
+    /// values of the body are carried by phi nodes, updated in a latch block
+    /// the body branches to: its name is known before generating the body
+    fn lower_loop(&mut self, f: &mut FunctionBuilder, l: &Loop) -> Result<String, BackendError> {
+        let head = f.fresh("loop");
+        let body = f.fresh("body");
+        let exit = f.fresh("endloop");
+
+        let (lo, hi) = match (l.lo_bound(), l.hi_bound()) {
+            (Some(lo), Some(hi)) => (self.integer(f, lo)?, self.integer(f, hi)?),
+            _ => {
+                // Infinite loop: the exit is never reached
+                f.inst(format!("br label %{}", body));
+                f.start(&body);
+                if let Some(loop_body) = l.body() {
+                    self.lower(f, loop_body)?;
+                }
+                f.inst(format!("br label %{}", body));
+                f.start(&exit);
+
+                return Ok(String::from("true"));
+            }
+        };
+
+        let latch = f.fresh("latch");
+        let pre = f.current.clone();
+        let idx = f.fresh("%idx");
+        let acc = f.fresh("%acc");
+        let next_idx = f.fresh("%idx");
+        let next_acc = f.fresh("%acc");
+        let cond = f.fresh("%cond");
+
+        f.inst(format!("br label %{}", head));
+
+        f.start(&head);
+        f.inst(format!(
+            "{} = phi i64 [ {}, %{} ], [ {}, %{} ]",
+            idx, lo, pre, next_idx, latch
+        ));
+        f.inst(format!(
+            "{} = phi i1 [ true, %{} ], [ {}, %{} ]",
+            acc, pre, next_acc, latch
+        ));
+        f.inst(format!("{} = icmp slt i64 {}, {}", cond, idx, hi));
+        f.inst(format!("br i1 {}, label %{}, label %{}", cond, body, exit));
+
+        f.start(&body);
+        let value = match l.body() {
+            Some(loop_body) => self.lower(f, loop_body)?,
+            None => String::from("true"),
+        };
+        f.inst(format!("br label %{}", latch));
+
+        f.start(&latch);
+        f.inst(format!("{} = and i1 {}, {}", next_acc, acc, value));
+        f.inst(format!("{} = add i64 {}, 1", next_idx, idx));
+        f.inst(format!("br label %{}", head));
+
+        f.start(&exit);
+
+        Ok(acc)
+    }
+
+    /// Generate the `i64` value of a loop bound or an operand: `Number` and
+    /// `Arithmetic` blocks count as their value, other blocks as `1` if they
+    /// evaluate to `true`
+    fn integer(
+        &mut self,
+        f: &mut FunctionBuilder,
+        block: &dyn BasicBlock,
+    ) -> Result<String, BackendError> {
+        match block.kind() {
+            BlockKind::Number(n) => Ok((n.get() as i64).to_string()),
+            BlockKind::Arithmetic(a) => {
+                let lhs = self.integer(f, a.lhs())?;
+                let rhs = self.integer(f, a.rhs())?;
+                let op = match a.op() {
+                    ArithOp::Add => "add",
+                    ArithOp::Sub => "sub",
+                    ArithOp::Mul => "mul",
+                };
+
+                let value = f.fresh("%arith");
+                f.inst(format!("{} = {} i64 {}, {}", value, op, lhs, rhs));
+                Ok(value)
+            }
+            _ => {
+                let value = self.lower(f, block)?;
+                let wide = f.fresh("%bound");
+                f.inst(format!("{} = zext i1 {} to i64", wide, value));
+                Ok(wide)
+            }
+        }
+    }
+
+    /// Define a function if needed, and call it
+    fn call(&mut self, f: &mut FunctionBuilder, func: &Function) -> Result<String, BackendError> {
+        if self.defined.insert(func.label().clone()) {
+            let mut body = FunctionBuilder::new();
+
+            for stmt in func.stmts().iter() {
+                self.lower(&mut body, *stmt)?;
+            }
+
+            let retval = match func.retval() {
+                Some(retval) => self.lower(&mut body, retval)?,
+                None => String::from("false"),
+            };
+            body.inst(format!("ret i1 {}", retval));
+
+            let signature = format!("define internal i1 @{}()", func.label());
+            self.functions.push(body.finish(&signature));
+        }
+
+        let value = f.fresh("%call");
+        f.inst(format!("{} = call i1 @{}()", value, func.label()));
+
+        Ok(value)
+    }
+}
+
+/// Escape a string to be used in an LLVM string constant
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for byte in s.bytes() {
+        if byte.is_ascii_graphic() && byte != b'"' && byte != b'\\' || byte == b' ' {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("\\{:02X}", byte));
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::testing::{called_twice, compared_product, emit_entry};
+    use crate::blocks::{Boolean, IfElse, Number, Str};
+
+    #[test]
+    fn main_returns_entry() {
+        let b = Boolean::new(true);
+        let ir = emit_entry("llvm", &b);
+
+        assert!(ir.contains("define i32 @main() {\nentry:\n"));
+        assert!(ir.contains("%exit = zext i1 true to i32\n  ret i32 %exit\n}"));
+    }
+
+    #[test]
+    fn primitives() {
+        let n = Number::new(12.0);
+        let s = Str::new(String::from("a \"str\""));
+        let stmts: Vec<&dyn BasicBlock> = vec![&n, &s];
+        let func = Function::new(None, &stmts);
+
+        let ir = emit_entry("llvm", &func);
+
+        assert!(ir.contains("fcmp ord double 0x4028000000000000, 0.0"));
+        assert!(ir.contains(&format!(
+            "@{} = private unnamed_addr constant [7 x i8] c\"a \\22str\\22\"",
+            s.label()
+        )));
+    }
+
+    #[test]
+    fn if_else_phi() {
+        let c = Boolean::new(true);
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+        let ie = IfElse::new(&c, &t, Some(&f));
+
+        let ir = emit_entry("llvm", &ie);
+
+        assert!(ir.contains("br i1 true, label %then.1, label %else.2"));
+        assert!(ir.contains("%if.4 = phi i1 [ true, %then.1 ], [ false, %else.2 ]"));
+    }
+
+    #[test]
+    fn nested_if_else_predecessors() {
+        let c = Boolean::new(true);
+        let t = Boolean::new(true);
+        let inner = IfElse::new(&c, &t, None);
+        let outer = IfElse::new(&c, &inner, None);
+
+        let ir = emit_entry("llvm", &outer);
+
+        // The then branch of the outer block ends in the inner endif block
+        assert!(ir.contains("[ %if.7, %endif.6 ], [ false, %else.2 ]"));
+    }
+
+    #[test]
+    fn loop_phi() {
+        let lo = Number::new(2.0);
+        let hi = Number::new(10.0);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let ir = emit_entry("llvm", &l);
+
+        assert!(ir.contains("%idx.5 = phi i64 [ 2, %entry ], [ %idx.7, %latch.4 ]"));
+        assert!(ir.contains("%acc.6 = phi i1 [ true, %entry ], [ %acc.8, %latch.4 ]"));
+        assert!(ir.contains("%cond.9 = icmp slt i64 %idx.5, 10"));
+        assert!(ir.contains(
+            "body.2:\n  br label %latch.4\nlatch.4:\n  %acc.8 = and i1 %acc.6, true\n"
+        ));
+        assert!(ir.contains("%exit = zext i1 %acc.6 to i32"));
+    }
+
+    #[test]
+    fn infinite_loop() {
+        let l = Loop::new(None, None, None);
+        let ir = emit_entry("llvm", &l);
+
+        assert!(ir.contains("body.2:\n  br label %body.2\nendloop.3:\n"));
+    }
+
+    #[test]
+    fn functions_defined_once() {
+        called_twice("llvm", |ir, func, crit| {
+            let definition = format!("define internal i1 @{}()", func.label());
+
+            assert_eq!(ir.matches(&definition).count(), 1);
+            assert_eq!(
+                ir.matches(&format!("call i1 @{}()", func.label())).count(),
+                2
+            );
+            assert!(ir.contains(&format!("; critical {}", crit.label())));
+        });
+    }
+
+    #[test]
+    fn integers() {
+        let source = compared_product("llvm");
+
+        assert!(source.contains("%arith.1 = sub i64 2, 5"));
+        assert!(source.contains("%arith.2 = mul i64 %arith.1, 3"));
+        assert!(source.contains("%cmp.3 = icmp slt i64 %arith.2, 1"));
+    }
+}
@@ -0,0 +1,111 @@
+//! Backends translate a `Recipe` into source code for another language or
+//! another intermediate representation, to be compiled by external tools.
+//!
+//! Every backend implements the `Backend` trait, and can be selected by name
+//! from a `Registry`. Implement `Backend` and register it to plug in your own
+//! target.
+
+pub mod asm;
+pub mod c;
+pub mod llvm;
+mod registry;
+pub mod rust;
+#[cfg(test)]
+mod testing;
+pub mod wasm;
+
+pub use registry::Registry;
+
+use std::fmt;
+use std::io::Write;
+
+use crate::opt::Remark;
+use crate::recipe::Recipe;
+
+/// Code generation target
+pub trait Backend: Sync {
+    /// Return the name used to select the backend
+    fn name(&self) -> &str;
+
+    /// Translate a recipe, and write the result to `out`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::backend::{Backend, Registry};
+    /// use stir::blocks::Boolean;
+    /// use stir::recipe::Recipe;
+    ///
+    /// let b = Boolean::new(true);
+    ///
+    /// let mut recipe = Recipe::new();
+    /// recipe.add_entry(&b);
+    ///
+    /// let mut out = Vec::new();
+    /// Registry::new().get("c").unwrap().emit(&recipe, &mut out).unwrap();
+    ///
+    /// assert!(String::from_utf8(out).unwrap().contains("int main(void)"));
+    /// ```
+    fn emit(&self, recipe: &Recipe, out: &mut dyn Write) -> Result<(), BackendError>;
+
+    /// Translate a recipe like `emit`, and return the remarks explaining the
+    /// decisions taken, such as which loops were parallelized. Backends
+    /// taking no such decision return no remark
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::backend::{Backend, Registry};
+    /// use stir::blocks::{Boolean, Loop, Number};
+    /// use stir::recipe::Recipe;
+    ///
+    /// let lo = Number::new(0.0);
+    /// let hi = Number::new(1000.0);
+    /// let body = Boolean::new(true);
+    /// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+    ///
+    /// let mut recipe = Recipe::new();
+    /// recipe.add_entry(&l);
+    ///
+    /// let registry = Registry::new();
+    /// let mut out = Vec::new();
+    ///
+    /// let remarks = registry.get("rust").unwrap().emit_with_remarks(&recipe, &mut out);
+    /// assert_eq!(remarks.unwrap().len(), 1);
+    ///
+    /// let remarks = registry.get("llvm").unwrap().emit_with_remarks(&recipe, &mut out);
+    /// assert!(remarks.unwrap().is_empty());
+    /// ```
+    fn emit_with_remarks(
+        &self,
+        recipe: &Recipe,
+        out: &mut dyn Write,
+    ) -> Result<Vec<Remark>, BackendError> {
+        self.emit(recipe, out).map(|_| Vec::new())
+    }
+}
+
+/// Reason why a `Recipe` could not be translated
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendError {
+    /// The recipe does not have an entry block
+    NoEntry,
+
+    /// The block with the given label cannot be translated by the backend
+    Unsupported(String),
+
+    /// The result could not be written
+    Io(String),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::NoEntry => write!(f, "recipe has no entry block"),
+            BackendError::Unsupported(label) => write!(f, "unsupported block {}", label),
+            BackendError::Io(e) => write!(f, "cannot write the result: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
@@ -0,0 +1,232 @@
+//! The `Registry` selects backends by name
+
+use std::io::Write;
+
+use super::{asm, c, llvm, rust, wasm, Backend, BackendError};
+
+use crate::cost::CostModel;
+use crate::opt::Remark;
+use crate::recipe::Recipe;
+
+/// Translation function of the backends provided by `stir`, returning the
+/// code and the remarks about its generation
+type Translate = fn(&Recipe) -> Result<(String, Vec<Remark>), BackendError>;
+
+/// Pair code with the remarks of a backend which takes no decision
+fn without_remarks(code: String) -> (String, Vec<Remark>) {
+    (code, Vec::new())
+}
+
+/// Backend provided by `stir`, translating a recipe to a `String`
+struct Builtin {
+    name: &'static str,
+    translate: Translate,
+}
+
+impl Backend for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn emit(&self, recipe: &Recipe, out: &mut dyn Write) -> Result<(), BackendError> {
+        self.emit_with_remarks(recipe, out).map(|_| ())
+    }
+
+    fn emit_with_remarks(
+        &self,
+        recipe: &Recipe,
+        out: &mut dyn Write,
+    ) -> Result<Vec<Remark>, BackendError> {
+        let (code, remarks) = (self.translate)(recipe)?;
+
+        out.write_all(code.as_bytes())
+            .map_err(|e| BackendError::Io(e.to_string()))?;
+
+        Ok(remarks)
+    }
+}
+
+/// Backends available by name
+pub struct Registry {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Registry {
+    /// Create a new Registry containing the backends provided by `stir`:
+    /// `asm`, `c`, `c-openmp`, `llvm`, `rust` and `wasm`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::backend::Registry;
+    ///
+    /// let registry = Registry::new();
+    ///
+    /// assert!(registry.get("llvm").is_some());
+    /// assert!(registry.get("jvm").is_none());
+    /// ```
+    pub fn new() -> Registry {
+        let builtins: [(&'static str, Translate); 6] = [
+            ("asm", |recipe| asm::emit(recipe).map(without_remarks)),
+            ("c", |recipe| c::emit(recipe).map(without_remarks)),
+            ("c-openmp", |recipe| {
+                c::emit_openmp_with_remarks(recipe, &CostModel::new())
+            }),
+            ("llvm", |recipe| llvm::emit(recipe).map(without_remarks)),
+            ("rust", |recipe| {
+                rust::emit_with_remarks(recipe, &CostModel::new())
+            }),
+            ("wasm", |recipe| wasm::emit(recipe).map(without_remarks)),
+        ];
+
+        Registry {
+            backends: builtins
+                .iter()
+                .map(|(name, translate)| {
+                    Box::new(Builtin {
+                        name,
+                        translate: *translate,
+                    }) as Box<dyn Backend>
+                })
+                .collect(),
+        }
+    }
+
+    /// Add a backend to the registry. Returns `false` if a backend with the
+    /// same name is already registered, in which case the registry is left
+    /// unchanged
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::io::Write;
+    ///
+    /// use stir::backend::{Backend, BackendError, Registry};
+    /// use stir::recipe::Recipe;
+    ///
+    /// struct Count;
+    ///
+    /// impl Backend for Count {
+    ///     fn name(&self) -> &str {
+    ///         "count"
+    ///     }
+    ///
+    ///     fn emit(&self, recipe: &Recipe, out: &mut dyn Write) -> Result<(), BackendError> {
+    ///         write!(out, "{}", recipe.len()).map_err(|e| BackendError::Io(e.to_string()))
+    ///     }
+    /// }
+    ///
+    /// let mut registry = Registry::new();
+    ///
+    /// assert!(registry.register(Box::new(Count)));
+    /// assert!(!registry.register(Box::new(Count)));
+    ///
+    /// let mut out = Vec::new();
+    /// registry.get("count").unwrap().emit(&Recipe::new(), &mut out).unwrap();
+    ///
+    /// assert_eq!(out, b"0");
+    /// ```
+    pub fn register(&mut self, backend: Box<dyn Backend>) -> bool {
+        if self.get(backend.name()).is_some() {
+            return false;
+        }
+
+        self.backends.push(backend);
+
+        true
+    }
+
+    /// Return the backend with the given name, if any
+    pub fn get(&self, name: &str) -> Option<&dyn Backend> {
+        self.backends
+            .iter()
+            .find(|backend| backend.name() == name)
+            .map(|backend| backend.as_ref())
+    }
+
+    /// Return the names of the registered backends, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.backends.iter().map(|backend| backend.name()).collect()
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+
+    use crate::blocks::Boolean;
+
+    /// Writer failing on every write
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn builtins() {
+        let b = Boolean::new(true);
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&b);
+
+        let registry = Registry::new();
+
+        assert_eq!(
+            registry.names(),
+            ["asm", "c", "c-openmp", "llvm", "rust", "wasm"]
+        );
+
+        for name in registry.names() {
+            let mut out = Vec::new();
+            registry.get(name).unwrap().emit(&recipe, &mut out).unwrap();
+
+            assert!(!out.is_empty(), "{}", name);
+        }
+
+        let mut out = Vec::new();
+        registry
+            .get("rust")
+            .unwrap()
+            .emit(&recipe, &mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            rust::emit(&recipe).unwrap()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let b = Boolean::new(true);
+        let mut recipe = Recipe::new();
+
+        let llvm = Registry::new();
+        let llvm = llvm.get("llvm").unwrap();
+
+        assert_eq!(
+            llvm.emit(&recipe, &mut Vec::new()),
+            Err(BackendError::NoEntry)
+        );
+
+        recipe.add_entry(&b);
+
+        assert_eq!(
+            llvm.emit(&recipe, &mut Broken),
+            Err(BackendError::Io(String::from("broken")))
+        );
+    }
+}
@@ -0,0 +1,710 @@
+//! Translation of a `Recipe` to a Rust module, to be included in a crate
+//! using `mod`.
+//!
+//! Every block evaluates to a `bool`, following the semantics of the
+//! interpreter. `Function` blocks become private Rust functions without
+//! arguments, and the entry block of the recipe is evaluated by the public
+//! `run` function.
+//!
+//! The outermost loops whose iterations are independent, and which the
+//! `CostModel` deems worth it, are split between threads using
+//! `std::thread::scope`. `Critical` blocks are guarded by a
+//! global `Mutex`, which can be locked again by the thread owning it. Loops
+//! containing `Critical` blocks are never executed in parallel, so that
+//! workers do not wait for a lock held by the thread waiting for them.
+
+use std::collections::HashSet;
+
+use super::BackendError;
+
+use crate::analysis;
+use crate::blocks::{ArithOp, BasicBlock, BlockKind, Function, Loop, Primitive};
+use crate::cost::CostModel;
+use crate::opt::{Remark, RemarkKind};
+use crate::recipe::Recipe;
+
+/// Translate a recipe to a Rust module, using the default `CostModel` to
+/// decide which loops are parallelized
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::rust;
+/// use stir::blocks::{Boolean, IfElse};
+/// use stir::recipe::Recipe;
+///
+/// let c = Boolean::new(true);
+/// let t = Boolean::new(false);
+/// let ie = IfElse::new(&c, &t, None);
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&ie);
+///
+/// let source = rust::emit(&recipe).unwrap();
+///
+/// assert!(source.contains("pub fn run() -> bool {"));
+/// ```
+pub fn emit(recipe: &Recipe) -> Result<String, BackendError> {
+    emit_with(recipe, &CostModel::new())
+}
+
+/// Translate a recipe to a Rust module, parallelizing the loops estimated to
+/// perform enough work by the given cost model
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::rust;
+/// use stir::blocks::{Boolean, Loop, Number};
+/// use stir::cost::CostModel;
+/// use stir::recipe::Recipe;
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(2.0);
+/// let body = Boolean::new(true);
+/// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&l);
+///
+/// let mut model = CostModel::new();
+///
+/// assert!(!rust::emit_with(&recipe, &model).unwrap().contains("thread::scope"));
+///
+/// model.set_threshold(0);
+///
+/// assert!(rust::emit_with(&recipe, &model).unwrap().contains("thread::scope"));
+/// ```
+pub fn emit_with(recipe: &Recipe, cost_model: &CostModel) -> Result<String, BackendError> {
+    emit_with_remarks(recipe, cost_model).map(|(source, _)| source)
+}
+
+/// Translate a recipe to a Rust module like `emit_with`, and return the
+/// remarks explaining why each loop was parallelized or not
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::rust;
+/// use stir::blocks::{Boolean, Loop, Number};
+/// use stir::cost::CostModel;
+/// use stir::opt::RemarkKind;
+/// use stir::recipe::Recipe;
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(2.0);
+/// let body = Boolean::new(true);
+/// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&l);
+///
+/// let (_, remarks) = rust::emit_with_remarks(&recipe, &CostModel::new()).unwrap();
+///
+/// assert_eq!(remarks.len(), 1);
+/// assert_eq!(remarks[0].kind(), RemarkKind::Missed);
+/// ```
+pub fn emit_with_remarks(
+    recipe: &Recipe,
+    cost_model: &CostModel,
+) -> Result<(String, Vec<Remark>), BackendError> {
+    let entry = recipe.entry().ok_or(BackendError::NoEntry)?;
+
+    let mut module = Module::new(cost_model.clone());
+    let mut run = Body::new();
+
+    let value = module.lower(&mut run, entry)?;
+    run.line(value);
+
+    let mut source = String::from("// Generated by stir\n\n");
+
+    if module.critical {
+        source.push_str(CRITICAL);
+        source.push('\n');
+    }
+
+    for global in module.globals.iter() {
+        source.push_str(global);
+        source.push('\n');
+    }
+    if !module.globals.is_empty() {
+        source.push('\n');
+    }
+
+    for function in module.functions.iter() {
+        source.push_str(function);
+        source.push('\n');
+    }
+
+    source.push_str("/// Evaluate the entry block of the recipe\n");
+    source.push_str(&run.finish("pub fn run() -> bool"));
+
+    Ok((source, module.remarks))
+}
+
+/// Helper guarding `Critical` blocks. The mutex is only locked by the
+/// outermost critical block of a thread
+const CRITICAL: &str = "static STIR_CRITICAL: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+thread_local! {
+    static STIR_CRITICAL_DEPTH: std::cell::Cell<u32> = std::cell::Cell::new(0);
+}
+
+fn stir_critical(f: impl FnOnce() -> bool) -> bool {
+    let depth = STIR_CRITICAL_DEPTH.with(|depth| depth.replace(depth.get() + 1));
+    let guard = match depth {
+        0 => Some(STIR_CRITICAL.lock().unwrap_or_else(|e| e.into_inner())),
+        _ => None,
+    };
+
+    let value = f();
+
+    STIR_CRITICAL_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    drop(guard);
+
+    value
+}
+";
+
+/// Return the Rust identifier of a block. Labels start with two underscores,
+/// which would not be snake case
+fn ident(label: &str) -> String {
+    format!("stir_{}", label.trim_start_matches('_'))
+}
+
+/// Definitions shared by all the functions of the module
+struct Module {
+    globals: Vec<String>,
+    functions: Vec<String>,
+
+    /// Labels of the blocks already defined in the module
+    defined: HashSet<String>,
+
+    /// If the critical section helper is needed
+    critical: bool,
+
+    /// Decides which loops are worth parallelizing
+    cost_model: CostModel,
+
+    /// Number of parallel loops containing the block being generated. Loops
+    /// nested inside them are executed by one thread
+    nested: usize,
+
+    /// Why each loop was parallelized or not
+    remarks: Vec<Remark>,
+}
+
+/// Body of a Rust function being generated
+struct Body {
+    text: String,
+
+    /// Current indentation level
+    depth: usize,
+
+    /// Index used to generate unique variable names
+    next: usize,
+
+    /// If the function contains an infinite loop, after which the code is
+    /// unreachable
+    diverges: bool,
+}
+
+impl Body {
+    fn new() -> Body {
+        Body {
+            text: String::new(),
+            depth: 1,
+            next: 0,
+            diverges: false,
+        }
+    }
+
+    /// Return a new unique variable name using the given prefix
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.next += 1;
+        format!("{}{}", prefix, self.next)
+    }
+
+    /// Add a line at the current indentation level
+    fn line(&mut self, line: String) {
+        for _ in 0..self.depth {
+            self.text.push_str("    ");
+        }
+        self.text.push_str(&line);
+        self.text.push('\n');
+    }
+
+    /// Open a new Rust block
+    fn open(&mut self, line: String) {
+        self.line(line);
+        self.depth += 1;
+    }
+
+    /// Close the current Rust block
+    fn close(&mut self, line: &str) {
+        self.depth -= 1;
+        self.line(String::from(line));
+    }
+
+    fn finish(self, signature: &str) -> String {
+        let allow = if self.diverges {
+            "#[allow(unreachable_code, unused_variables)]\n"
+        } else {
+            ""
+        };
+
+        format!("{}{} {{\n{}}}\n", allow, signature, self.text)
+    }
+}
+
+impl Module {
+    fn new(cost_model: CostModel) -> Module {
+        Module {
+            globals: Vec::new(),
+            functions: Vec::new(),
+            defined: HashSet::new(),
+            critical: false,
+            cost_model,
+            nested: 0,
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Generate the statements evaluating a block, and return the Rust
+    /// expression holding its value
+    fn lower(&mut self, b: &mut Body, block: &dyn BasicBlock) -> Result<String, BackendError> {
+        match block.kind() {
+            BlockKind::Boolean(boolean) => Ok(boolean.get().to_string()),
+            BlockKind::Number(n) => Ok(format!("!{}.is_nan()", number(n.get()))),
+            BlockKind::Str(s) => {
+                let name = ident(s.label()).to_uppercase();
+
+                if self.defined.insert(s.label().clone()) {
+                    self.globals
+                        .push(format!("static {}: &str = {:?};", name, s.get()));
+                }
+
+                Ok(format!("!{}.is_empty()", name))
+            }
+            BlockKind::IfElse(ie) => {
+                let value = b.fresh("v");
+
+                let cond = self.lower(b, ie.cond_block())?;
+                b.open(format!("let {} = if {} {{", value, cond));
+                let t_value = self.lower(b, ie.t_block())?;
+                b.line(t_value);
+                b.close("} else {");
+                b.depth += 1;
+                let f_value = match ie.f_block() {
+                    Some(f_block) => self.lower(b, f_block)?,
+                    None => String::from("false"),
+                };
+                b.line(f_value);
+                b.close("};");
+
+                Ok(value)
+            }
+            BlockKind::Loop(l) => self.lower_loop(b, l),
+            BlockKind::Function(func) => self.call(func),
+            BlockKind::Call(call) => self.call(call.function()),
+            BlockKind::Critical(crit) => {
+                self.critical = true;
+
+                let value = b.fresh("v");
+                b.line(format!("// critical {}", crit.label()));
+                b.open(format!("let {} = stir_critical(|| {{", value));
+                let inner = self.lower(b, crit.block())?;
+                b.line(inner);
+                b.close("});");
+
+                Ok(value)
+            }
+            BlockKind::Arithmetic(_) => {
+                // The result is only used as an integer: the block is `true`
+                let value = self.integer(b, block)?;
+                b.line(format!("let _ = {};", value));
+
+                Ok(String::from("true"))
+            }
+            BlockKind::Compare(c) => {
+                let lhs = self.integer(b, c.lhs())?;
+                let rhs = self.integer(b, c.rhs())?;
+
+                // Comparisons are only used as whole expressions, where
+                // parentheses would be reported as unused
+                Ok(format!("{} {} {}", lhs, c.op().symbol(), rhs))
+            }
+            BlockKind::Other => Err(BackendError::Unsupported(block.label().clone())),
+        }
+    }
+
+    /// Generate a loop. Its value is the conjunction of the values of the
+    /// body at each iteration
+    fn lower_loop(&mut self, b: &mut Body, l: &Loop) -> Result<String, BackendError> {
+        let value = b.fresh("v");
+
+        let (lo, hi) = match (l.lo_bound(), l.hi_bound()) {
+            (Some(lo), Some(hi)) => (self.integer(b, lo)?, self.integer(b, hi)?),
+            _ => {
+                self.remarks
+                    .push(Remark::missed("rust", l.label(), "the loop is infinite"));
+
+                // Infinite loops never produce a value
+                b.diverges = true;
+                b.open(format!("let {}: bool = loop {{", value));
+                if let Some(body) = l.body() {
+                    let body_value = self.lower(b, body)?;
+                    b.line(format!("let _ = {};", body_value));
+                }
+                b.close("};");
+
+                return Ok(value);
+            }
+        };
+
+        let remark = if self.nested > 0 {
+            Remark::missed("rust", l.label(), "the loop is nested in a parallel loop")
+        } else if !analysis::is_parallel(l) {
+            Remark::missed(
+                "rust",
+                l.label(),
+                "the body contains blocks unknown to stir",
+            )
+        } else if l.body().is_some_and(analysis::has_critical) {
+            Remark::missed("rust", l.label(), "the body contains a critical block")
+        } else {
+            self.cost_model.remark("rust", l)
+        };
+
+        let parallel = remark.kind() == RemarkKind::Applied;
+        self.remarks.push(remark);
+
+        if parallel {
+            self.lower_parallel_loop(b, l, &value, &lo, &hi)?;
+        } else {
+            let mutability = if l.body().is_some() { "mut " } else { "" };
+            b.line(format!("let {}{} = true;", mutability, value));
+            b.open(format!("for _ in {}..{} {{", lo, hi));
+            if let Some(body) = l.body() {
+                let body_value = self.lower(b, body)?;
+                b.line(format!("{} &= {};", value, body_value));
+            }
+            b.close("}");
+        }
+
+        Ok(value)
+    }
+
+    /// Generate a loop whose iterations are split between as many scoped
+    /// threads as the available parallelism
+    fn lower_parallel_loop(
+        &mut self,
+        b: &mut Body,
+        l: &Loop,
+        value: &str,
+        lo: &str,
+        hi: &str,
+    ) -> Result<(), BackendError> {
+        let (start, end) = (b.fresh("start"), b.fresh("end"));
+
+        b.open(format!("let {} = std::thread::scope(|scope| {{", value));
+        b.line(format!("let ({}, {}) = ({}, {});", start, end, lo, hi));
+        b.line(String::from(
+            "let workers = std::thread::available_parallelism().map_or(1, |n| n.get() as i64);",
+        ));
+        b.line(format!(
+            "let step = ({} - {} + workers - 1).max(0) / workers;",
+            end, start
+        ));
+        b.open(String::from("let handles: Vec<_> = (0..workers)"));
+        b.open(String::from(".map(|worker| {"));
+        b.line(format!("let from = {} + worker * step;", start));
+        b.line(format!("let to = (from + step).min({});", end));
+        b.open(String::from("scope.spawn(move || {"));
+        let mutability = if l.body().is_some() { "mut " } else { "" };
+        b.line(format!("let {}value = true;", mutability));
+        b.open(String::from("for _ in from..to {"));
+
+        self.nested += 1;
+        if let Some(body) = l.body() {
+            let body_value = self.lower(b, body)?;
+            b.line(format!("value &= {};", body_value));
+        }
+        self.nested -= 1;
+
+        b.close("}");
+        b.line(String::from("value"));
+        b.close("})");
+        b.close("})");
+        b.line(String::from(".collect();"));
+        b.depth -= 1;
+        b.line(String::from(
+            "handles.into_iter().fold(true, |value, handle| handle.join().unwrap() & value)",
+        ));
+        b.close("});");
+
+        Ok(())
+    }
+
+    /// Generate the value of a loop bound or an operand: `Number` and
+    /// `Arithmetic` blocks count as their value, other blocks as `1` if they
+    /// evaluate to `true`
+    fn integer(&mut self, b: &mut Body, block: &dyn BasicBlock) -> Result<String, BackendError> {
+        match block.kind() {
+            BlockKind::Number(n) => Ok(format!("{}_i64", n.get() as i64)),
+            BlockKind::Arithmetic(a) => {
+                let lhs = self.integer(b, a.lhs())?;
+                let rhs = self.integer(b, a.rhs())?;
+                let method = match a.op() {
+                    ArithOp::Add => "wrapping_add",
+                    ArithOp::Sub => "wrapping_sub",
+                    ArithOp::Mul => "wrapping_mul",
+                };
+
+                Ok(format!("i64::{}({}, {})", method, lhs, rhs))
+            }
+            _ => {
+                let value = self.lower(b, block)?;
+                Ok(format!("i64::from({})", value))
+            }
+        }
+    }
+
+    /// Define a function if needed, and return the expression calling it
+    fn call(&mut self, func: &Function) -> Result<String, BackendError> {
+        let name = ident(func.label());
+
+        if self.defined.insert(func.label().clone()) {
+            let mut body = Body::new();
+
+            // Functions are defined once, possibly called from parallel
+            // loops or not
+            let nested = std::mem::replace(&mut self.nested, 0);
+
+            for stmt in func.stmts().iter() {
+                let value = self.lower(&mut body, *stmt)?;
+                body.line(format!("let _ = {};", value));
+            }
+
+            let retval = match func.retval() {
+                Some(retval) => self.lower(&mut body, retval)?,
+                None => String::from("false"),
+            };
+            body.line(retval);
+
+            self.nested = nested;
+
+            self.functions
+                .push(body.finish(&format!("fn {}() -> bool", name)));
+        }
+
+        Ok(format!("{}()", name))
+    }
+}
+
+/// Return the Rust expression of a `f64`
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        String::from("f64::NAN")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 {
+            "f64::INFINITY"
+        } else {
+            "f64::NEG_INFINITY"
+        })
+    } else {
+        format!("({:?}_f64)", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::testing::{called_twice, compared_product, emit_entry};
+    use crate::blocks::{Boolean, Compare, CompareOp, Critical, IfElse, Number, Str};
+
+    #[test]
+    fn run() {
+        let b = Boolean::new(true);
+
+        assert_eq!(
+            emit_entry("rust", &b),
+            "// Generated by stir\n\n/// Evaluate the entry block of the recipe\n\
+             pub fn run() -> bool {\n    true\n}\n"
+        );
+    }
+
+    #[test]
+    fn primitives() {
+        let n = Number::new(-2.5);
+        let nan = Number::new(f64::NAN);
+        let s = Str::new(String::from("a\"\0"));
+        let stmts: Vec<&dyn BasicBlock> = vec![&n, &nan, &s];
+        let func = Function::new(None, &stmts);
+
+        let source = emit_entry("rust", &func);
+        let name = ident(s.label()).to_uppercase();
+
+        assert!(source.contains("let _ = !(-2.5_f64).is_nan();\n"));
+        assert!(source.contains("let _ = !f64::NAN.is_nan();\n"));
+        assert!(source.contains(&format!("static {}: &str = \"a\\\"\\0\";\n", name)));
+        assert!(source.contains(&format!("let _ = !{}.is_empty();\n", name)));
+    }
+
+    #[test]
+    fn if_else() {
+        let c = Boolean::new(false);
+        let t = Boolean::new(true);
+        let ie = IfElse::new(&c, &t, None);
+
+        assert!(emit_entry("rust", &ie).contains(
+            "    let v1 = if false {\n        true\n    } else {\n        false\n    };\n"
+        ));
+    }
+
+    #[test]
+    fn loops() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let inner = Loop::new(Some(&lo), Some(&hi), Some(&b));
+        let parallel = Loop::new(Some(&lo), Some(&b), Some(&inner));
+        let sequential = Loop::new(Some(&lo), Some(&hi), Some(&crit));
+        let infinite = Loop::new(None, None, Some(&b));
+        let stmts: Vec<&dyn BasicBlock> = vec![&parallel, &sequential, &infinite];
+        let func = Function::new(None, &stmts);
+
+        // Consider every loop worth parallelizing
+        let mut model = CostModel::new();
+        model.set_threshold(0);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+        let source = emit_with(&recipe, &model).unwrap();
+
+        // Only the outermost loop without critical blocks is parallel
+        assert_eq!(source.matches("std::thread::scope").count(), 1);
+        assert!(source.contains("let (start2, end3) = (0_i64, i64::from(true));\n"));
+        assert!(source.contains("for _ in 0_i64..10_i64 {\n"));
+        assert!(source.contains("let v7: bool = loop {\n        let _ = true;\n    };\n"));
+        assert!(source.contains("#[allow(unreachable_code, unused_variables)]\nfn stir_function_"));
+        assert!(source.contains("static STIR_CRITICAL"));
+        assert!(source.contains("let v6 = stir_critical(|| {\n"));
+    }
+
+    #[test]
+    fn remarks() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let inner = Loop::new(Some(&lo), Some(&hi), Some(&b));
+        let parallel = Loop::new(Some(&lo), Some(&hi), Some(&inner));
+        let sequential = Loop::new(Some(&lo), Some(&hi), Some(&crit));
+        let cheap = Loop::new(Some(&lo), Some(&b), Some(&b));
+        let stmts: Vec<&dyn BasicBlock> = vec![&parallel, &sequential, &cheap];
+        let func = Function::new(None, &stmts);
+
+        let mut model = CostModel::new();
+        model.set_threshold(100);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+        let (_, remarks) = emit_with_remarks(&recipe, &model).unwrap();
+
+        let reasons: Vec<(&str, RemarkKind, &str)> = remarks
+            .iter()
+            .map(|remark| (remark.label(), remark.kind(), remark.reason()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                (
+                    parallel.label().as_str(),
+                    RemarkKind::Applied,
+                    "the estimated cost 232 reaches the threshold 100"
+                ),
+                (
+                    inner.label().as_str(),
+                    RemarkKind::Missed,
+                    "the loop is nested in a parallel loop"
+                ),
+                (
+                    sequential.label().as_str(),
+                    RemarkKind::Missed,
+                    "the body contains a critical block"
+                ),
+                (
+                    cheap.label().as_str(),
+                    RemarkKind::Missed,
+                    "the estimated cost 4 is below the threshold 100"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_loops() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let l = Loop::new(Some(&lo), Some(&hi), None);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&l);
+
+        // Without a body, the value is never updated
+        let source = emit_with(&recipe, &CostModel::new()).unwrap();
+        assert!(source.contains("let v1 = true;\n"));
+
+        let mut model = CostModel::new();
+        model.set_threshold(0);
+
+        let source = emit_with(&recipe, &model).unwrap();
+        assert!(source.contains("std::thread::scope"));
+        assert!(source.contains("let value = true;\n"));
+        assert!(!source.contains("let mut"));
+    }
+
+    #[test]
+    fn comparison_statement() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let cmp = Compare::new(CompareOp::Lt, &lo, &hi);
+        let stmts: Vec<&dyn BasicBlock> = vec![&cmp];
+        let func = Function::new(None, &stmts);
+
+        assert!(emit_entry("rust", &func).contains("let _ = 0_i64 < 10_i64;\n"));
+    }
+
+    #[test]
+    fn cheap_loop() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(2.0);
+        let b = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&b));
+
+        assert!(!emit_entry("rust", &l).contains("std::thread::scope"));
+    }
+
+    #[test]
+    fn functions_defined_once() {
+        called_twice("rust", |source, func, _| {
+            let name = ident(func.label());
+
+            assert!(name.starts_with("stir_function_"));
+            assert_eq!(source.matches(&format!("fn {}() -> bool", name)).count(), 1);
+            assert_eq!(source.matches(&format!("let _ = {}();", name)).count(), 2);
+        });
+    }
+
+    #[test]
+    fn integers() {
+        let source = compared_product("rust");
+
+        assert!(
+            source.contains("i64::wrapping_mul(i64::wrapping_sub(2_i64, 5_i64), 3_i64) < 1_i64\n")
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Fixtures shared by the tests of the backends, and the tests every backend
+//! of the `Registry` must pass
+
+use super::{BackendError, Registry};
+
+use crate::blocks::{
+    ArithOp, Arithmetic, BasicBlock, Boolean, Call, Compare, CompareOp, Critical, Function, Number,
+};
+use crate::recipe::Recipe;
+
+/// Translate a recipe using the backend registered under `name`
+fn emit(name: &str, recipe: &Recipe) -> Result<String, BackendError> {
+    let registry = Registry::new();
+    let mut out = Vec::new();
+
+    registry.get(name).unwrap().emit(recipe, &mut out)?;
+
+    Ok(String::from_utf8(out).unwrap())
+}
+
+/// Translate a recipe whose only block is its entry block, using the backend
+/// registered under `name`
+pub(crate) fn emit_entry(name: &str, entry: &dyn BasicBlock) -> String {
+    let mut recipe = Recipe::new();
+    recipe.add_entry(entry);
+
+    emit(name, &recipe).unwrap()
+}
+
+/// Translate a function calling another function twice, using the backend
+/// registered under `name`. The called function has a critical statement,
+/// and returns `true`. `check` gets the result, the called function and its
+/// critical statement
+pub(crate) fn called_twice(name: &str, check: impl FnOnce(&str, &Function, &Critical)) {
+    let b = Boolean::new(true);
+    let crit = Critical::new(&b);
+    let stmts: Vec<&dyn BasicBlock> = vec![&crit];
+    let mut func = Function::new(None, &stmts);
+    func.set_retval(&b);
+
+    let call0 = Call::new(&func, None);
+    let call1 = Call::new(&func, None);
+    let main_stmts: Vec<&dyn BasicBlock> = vec![&call0, &call1];
+    let main = Function::new(None, &main_stmts);
+
+    check(&emit_entry(name, &main), &func, &crit);
+}
+
+/// Translate `(2 - 5) * 3 < 1`, using the backend registered under `name`
+pub(crate) fn compared_product(name: &str) -> String {
+    let two = Number::new(2.0);
+    let five = Number::new(5.0);
+    let three = Number::new(3.0);
+    let one = Number::new(1.0);
+
+    let diff = Arithmetic::new(ArithOp::Sub, &two, &five);
+    let product = Arithmetic::new(ArithOp::Mul, &diff, &three);
+    let cmp = Compare::new(CompareOp::Lt, &product, &one);
+
+    emit_entry(name, &cmp)
+}
+
+#[test]
+fn no_entry() {
+    for name in Registry::new().names() {
+        assert_eq!(emit(name, &Recipe::new()), Err(BackendError::NoEntry), "{}", name);
+    }
+}
+
+#[test]
+fn functions_defined_once() {
+    for name in Registry::new().names() {
+        called_twice(name, |source, func, _| {
+            assert!(!source.is_empty(), "{}", name);
+            assert!(source.contains(func.label().trim_start_matches('_')), "{}", name);
+        });
+    }
+}
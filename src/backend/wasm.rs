@@ -0,0 +1,461 @@
+//! Translation of a `Recipe` to the WebAssembly text format (`.wat`).
+//!
+//! Every block evaluates to an `i32` which is `1` if the block evaluated to
+//! `true`, `0` otherwise, following the semantics of the interpreter.
+//! `Function` blocks become WebAssembly functions without parameters, and
+//! the entry block of the recipe is evaluated by the exported `main`
+//! function. WebAssembly modules are single-threaded: `Critical` blocks
+//! simply evaluate the block they wrap.
+
+use std::collections::HashSet;
+
+use super::BackendError;
+
+use crate::blocks::{ArithOp, BasicBlock, BlockKind, CompareOp, Function, Loop, Primitive};
+use crate::recipe::Recipe;
+
+/// Translate a recipe to a WebAssembly text module
+///
+/// # Example
+///
+/// ```
+/// use stir::backend::wasm;
+/// use stir::blocks::{Boolean, IfElse};
+/// use stir::recipe::Recipe;
+///
+/// let c = Boolean::new(true);
+/// let t = Boolean::new(false);
+/// let ie = IfElse::new(&c, &t, None);
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&ie);
+///
+/// let wat = wasm::emit(&recipe).unwrap();
+///
+/// assert!(wat.contains("(func $main (export \"main\") (result i32)"));
+/// ```
+pub fn emit(recipe: &Recipe) -> Result<String, BackendError> {
+    let entry = recipe.entry().ok_or(BackendError::NoEntry)?;
+
+    let mut module = Module::new();
+    let mut main = Func::new();
+
+    module.lower(&mut main, entry)?;
+
+    let mut wat = String::from("(module\n");
+
+    for function in module.functions.iter() {
+        wat.push_str(function);
+    }
+
+    wat.push_str(&main.finish("$main (export \"main\")"));
+    wat.push_str(")\n");
+
+    Ok(wat)
+}
+
+/// Functions of the module
+struct Module {
+    functions: Vec<String>,
+
+    /// Labels of the functions already defined
+    defined: HashSet<String>,
+}
+
+/// WebAssembly function being generated
+struct Func {
+    locals: Vec<String>,
+    body: String,
+
+    /// Current nesting level of structured instructions
+    depth: usize,
+
+    /// Index used to generate unique local and label names
+    next: usize,
+}
+
+impl Func {
+    fn new() -> Func {
+        Func {
+            locals: Vec::new(),
+            body: String::new(),
+            depth: 2,
+            next: 0,
+        }
+    }
+
+    /// Return a new unique identifier using the given prefix
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.next += 1;
+        format!("${}{}", prefix, self.next)
+    }
+
+    /// Declare a new local of the given type, and return its identifier
+    fn local(&mut self, prefix: &str, ty: &str) -> String {
+        let local = self.fresh(prefix);
+        self.locals.push(format!("(local {} {})", local, ty));
+        local
+    }
+
+    /// Add an instruction at the current nesting level
+    fn inst(&mut self, inst: &str) {
+        for _ in 0..self.depth {
+            self.body.push_str("  ");
+        }
+        self.body.push_str(inst);
+        self.body.push('\n');
+    }
+
+    /// Add an instruction starting a structured instruction
+    fn open(&mut self, inst: &str) {
+        self.inst(inst);
+        self.depth += 1;
+    }
+
+    /// Add an instruction ending a structured instruction, or separating its
+    /// parts if `reopen` is `true`
+    fn close(&mut self, inst: &str, reopen: bool) {
+        self.depth -= 1;
+        self.inst(inst);
+        if reopen {
+            self.depth += 1;
+        }
+    }
+
+    fn finish(self, name: &str) -> String {
+        let mut func = format!("  (func {} (result i32)\n", name);
+
+        for local in self.locals.iter() {
+            func.push_str("    ");
+            func.push_str(local);
+            func.push('\n');
+        }
+
+        func.push_str(&self.body);
+        func.push_str("  )\n");
+
+        func
+    }
+}
+
+impl Module {
+    fn new() -> Module {
+        Module {
+            functions: Vec::new(),
+            defined: HashSet::new(),
+        }
+    }
+
+    /// Generate the instructions pushing the value of a block on the stack
+    fn lower(&mut self, f: &mut Func, block: &dyn BasicBlock) -> Result<(), BackendError> {
+        match block.kind() {
+            BlockKind::Boolean(b) => f.inst(&format!("i32.const {}", b.get() as i32)),
+            BlockKind::Number(n) => {
+                // A number is `true` unless it is NaN, the only value which
+                // is not equal to itself
+                let value = number(n.get());
+                f.inst(&format!("f64.const {}", value));
+                f.inst(&format!("f64.const {}", value));
+                f.inst("f64.eq");
+            }
+            BlockKind::Str(s) => f.inst(&format!("i32.const {}", !s.get().is_empty() as i32)),
+            BlockKind::IfElse(ie) => {
+                self.lower(f, ie.cond_block())?;
+                f.open("if (result i32)");
+                self.lower(f, ie.t_block())?;
+                f.close("else", true);
+                match ie.f_block() {
+                    Some(f_block) => self.lower(f, f_block)?,
+                    None => f.inst("i32.const 0"),
+                }
+                f.close("end", false);
+            }
+            BlockKind::Loop(l) => self.lower_loop(f, l)?,
+            BlockKind::Function(func) => self.call(f, func)?,
+            BlockKind::Call(call) => self.call(f, call.function())?,
+            BlockKind::Critical(crit) => {
+                f.inst(&format!(";; critical {}", crit.label()));
+                self.lower(f, crit.block())?;
+            }
+            BlockKind::Arithmetic(_) => {
+                // The result is only used as an integer: the block is `true`
+                self.integer(f, block)?;
+                f.inst("drop");
+                f.inst("i32.const 1");
+            }
+            BlockKind::Compare(c) => {
+                self.integer(f, c.lhs())?;
+                self.integer(f, c.rhs())?;
+                f.inst(match c.op() {
+                    CompareOp::Eq => "i64.eq",
+                    CompareOp::Ne => "i64.ne",
+                    CompareOp::Lt => "i64.lt_s",
+                    CompareOp::Le => "i64.le_s",
+                    CompareOp::Gt => "i64.gt_s",
+                    CompareOp::Ge => "i64.ge_s",
+                });
+            }
+            BlockKind::Other => return Err(BackendError::Unsupported(block.label().clone())),
+        }
+
+        Ok(())
+    }
+
+    /// Generate a loop. Its value is the conjunction of the values of the
+    /// body at each iteration
+    fn lower_loop(&mut self, f: &mut Func, l: &Loop) -> Result<(), BackendError> {
+        let (lo, hi) = match (l.lo_bound(), l.hi_bound()) {
+            (Some(lo), Some(hi)) => (lo, hi),
+            _ => {
+                // Infinite loop: the instructions following it are never
+                // reached
+                let head = f.fresh("loop");
+
+                f.open(&format!("loop {}", head));
+                if let Some(body) = l.body() {
+                    self.lower(f, body)?;
+                    f.inst("drop");
+                }
+                f.inst(&format!("br {}", head));
+                f.close("end", false);
+                f.inst("i32.const 1");
+
+                return Ok(());
+            }
+        };
+
+        let idx = f.local("idx", "i64");
+        let end = f.local("hi", "i64");
+        let acc = f.local("acc", "i32");
+        let exit = f.fresh("endloop");
+        let head = f.fresh("loop");
+
+        self.integer(f, lo)?;
+        f.inst(&format!("local.set {}", idx));
+        self.integer(f, hi)?;
+        f.inst(&format!("local.set {}", end));
+        f.inst("i32.const 1");
+        f.inst(&format!("local.set {}", acc));
+
+        f.open(&format!("block {}", exit));
+        f.open(&format!("loop {}", head));
+
+        f.inst(&format!("local.get {}", idx));
+        f.inst(&format!("local.get {}", end));
+        f.inst("i64.ge_s");
+        f.inst(&format!("br_if {}", exit));
+
+        if let Some(body) = l.body() {
+            self.lower(f, body)?;
+            f.inst(&format!("local.get {}", acc));
+            f.inst("i32.and");
+            f.inst(&format!("local.set {}", acc));
+        }
+
+        f.inst(&format!("local.get {}", idx));
+        f.inst("i64.const 1");
+        f.inst("i64.add");
+        f.inst(&format!("local.set {}", idx));
+        f.inst(&format!("br {}", head));
+
+        f.close("end", false);
+        f.close("end", false);
+
+        f.inst(&format!("local.get {}", acc));
+
+        Ok(())
+    }
+
+    /// Push the `i64` value of a loop bound or an operand: `Number` and
+    /// `Arithmetic` blocks count as their value, other blocks as `1` if they
+    /// evaluate to `true`
+    fn integer(&mut self, f: &mut Func, block: &dyn BasicBlock) -> Result<(), BackendError> {
+        match block.kind() {
+            BlockKind::Number(n) => f.inst(&format!("i64.const {}", n.get() as i64)),
+            BlockKind::Arithmetic(a) => {
+                self.integer(f, a.lhs())?;
+                self.integer(f, a.rhs())?;
+                f.inst(match a.op() {
+                    ArithOp::Add => "i64.add",
+                    ArithOp::Sub => "i64.sub",
+                    ArithOp::Mul => "i64.mul",
+                });
+            }
+            _ => {
+                self.lower(f, block)?;
+                f.inst("i64.extend_i32_u");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Define a function if needed, and call it
+    fn call(&mut self, f: &mut Func, func: &Function) -> Result<(), BackendError> {
+        let name = format!("${}", func.label());
+
+        if self.defined.insert(func.label().clone()) {
+            let mut body = Func::new();
+
+            for stmt in func.stmts().iter() {
+                self.lower(&mut body, *stmt)?;
+                body.inst("drop");
+            }
+
+            match func.retval() {
+                Some(retval) => self.lower(&mut body, retval)?,
+                None => body.inst("i32.const 0"),
+            }
+
+            self.functions.push(body.finish(&name));
+        }
+
+        f.inst(&format!("call {}", name));
+
+        Ok(())
+    }
+}
+
+/// Return the text format of a `f64` constant
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        String::from("nan")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "inf" } else { "-inf" })
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::testing::{called_twice, compared_product, emit_entry};
+    use crate::blocks::{Boolean, IfElse, Number, Str};
+
+    /// Check that parentheses are balanced and that every structured
+    /// instruction is terminated, and return the names of the functions
+    fn validate(wat: &str) -> Vec<String> {
+        let mut parens = 0i64;
+        let mut structured = 0i64;
+        let mut functions = Vec::new();
+
+        for line in wat.lines() {
+            let line = line.trim();
+
+            for c in line.chars() {
+                match c {
+                    '(' => parens += 1,
+                    ')' => parens -= 1,
+                    _ => (),
+                }
+                assert!(parens >= 0, "unbalanced parentheses");
+            }
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("block") | Some("loop") | Some("if") => structured += 1,
+                Some("end") => structured -= 1,
+                Some("(func") => functions.push(String::from(words.next().unwrap())),
+                _ => (),
+            }
+            assert!(structured >= 0, "unmatched end");
+        }
+
+        assert_eq!(parens, 0);
+        assert_eq!(structured, 0);
+        assert!(wat.starts_with("(module\n"));
+
+        functions
+    }
+
+    #[test]
+    fn exported_main() {
+        let b = Boolean::new(true);
+        let wat = emit_entry("wasm", &b);
+
+        assert_eq!(validate(&wat), vec!["$main"]);
+        assert_eq!(
+            wat,
+            "(module\n  (func $main (export \"main\") (result i32)\n    i32.const 1\n  )\n)\n"
+        );
+    }
+
+    #[test]
+    fn primitives() {
+        let n = Number::new(f64::NAN);
+        let big = Number::new(1e300);
+        let s = Str::new(String::new());
+        let stmts: Vec<&dyn BasicBlock> = vec![&n, &big, &s];
+        let func = Function::new(None, &stmts);
+
+        let wat = emit_entry("wasm", &func);
+
+        validate(&wat);
+        assert!(wat.contains("f64.const nan\n    f64.const nan\n    f64.eq\n    drop\n"));
+        assert!(wat.contains("f64.const 1e300\n"));
+        assert!(wat.contains("i32.const 0\n    drop\n"));
+        assert_eq!(number(f64::NEG_INFINITY), "-inf");
+    }
+
+    #[test]
+    fn if_else() {
+        let c = Boolean::new(false);
+        let t = Boolean::new(true);
+        let inner = IfElse::new(&c, &t, None);
+        let outer = IfElse::new(&c, &t, Some(&inner));
+
+        let wat = emit_entry("wasm", &outer);
+
+        validate(&wat);
+        assert!(wat.contains(
+            "    i32.const 0\n    if (result i32)\n      i32.const 1\n    else\n      i32.const 0\n      if (result i32)\n"
+        ));
+    }
+
+    #[test]
+    fn loops() {
+        let lo = Number::new(2.0);
+        let c = Boolean::new(true);
+        let hi = IfElse::new(&c, &c, None);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+        let infinite = Loop::new(None, None, Some(&body));
+        let stmts: Vec<&dyn BasicBlock> = vec![&l, &infinite];
+        let func = Function::new(None, &stmts);
+
+        let wat = emit_entry("wasm", &func);
+
+        validate(&wat);
+        assert!(wat.contains("(local $idx1 i64)\n    (local $hi2 i64)\n    (local $acc3 i32)\n"));
+        assert!(wat.contains("i64.const 2\n    local.set $idx1\n"));
+        assert!(wat.contains("i64.extend_i32_u\n    local.set $hi2\n"));
+        assert!(wat.contains("block $endloop4\n      loop $loop5\n"));
+        assert!(wat.contains("br_if $endloop4\n"));
+        assert!(wat.contains("loop $loop6\n      i32.const 1\n      drop\n      br $loop6\n    end\n    i32.const 1\n"));
+    }
+
+    #[test]
+    fn functions_defined_once() {
+        called_twice("wasm", |wat, func, crit| {
+            let functions = validate(wat);
+
+            assert_eq!(functions.len(), 3);
+            assert_eq!(functions[0], format!("${}", func.label()));
+            assert_eq!(functions[2], "$main");
+            assert_eq!(wat.matches(&format!("call ${}\n", func.label())).count(), 2);
+            assert!(wat.contains(&format!(";; critical {}\n", crit.label())));
+        });
+    }
+
+    #[test]
+    fn integers() {
+        let source = compared_product("wasm");
+
+        assert!(source.contains("i64.const 2"));
+        assert!(source.contains("i64.sub"));
+        assert!(source.contains("i64.mul"));
+        assert!(source.contains("i64.lt_s"));
+    }
+}
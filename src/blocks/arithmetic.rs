@@ -0,0 +1,196 @@
+//! The Arithmetic block computes an integer from the integer values of two
+//! other blocks.
+
+use super::{BasicBlock, BlockKind};
+
+use crate::executor::{ExecResult, Executor, InterpreterError};
+use crate::label::Label;
+
+/// Operation of an `Arithmetic` block. Operations wrap around on overflow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+}
+
+impl ArithOp {
+    /// Apply the operation to two integers
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::ArithOp;
+    ///
+    /// assert_eq!(ArithOp::Sub.apply(2, 5), -3);
+    /// assert_eq!(ArithOp::Add.apply(i64::MAX, 1), i64::MIN);
+    /// ```
+    pub fn apply(self, lhs: i64, rhs: i64) -> i64 {
+        match self {
+            ArithOp::Add => lhs.wrapping_add(rhs),
+            ArithOp::Sub => lhs.wrapping_sub(rhs),
+            ArithOp::Mul => lhs.wrapping_mul(rhs),
+        }
+    }
+
+    /// Return the symbol of the operation
+    pub fn symbol(self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+        }
+    }
+}
+
+/// An Arithmetic block applies an operation to the integer values of its
+/// operands. A `Number` operand counts as its value truncated to an integer,
+/// an `Arithmetic` operand as its result, and any other operand as `1` if it
+/// evaluates to `true`, `0` otherwise, like loop bounds.
+///
+/// Its result is used as an integer by loop bounds and other `Arithmetic` or
+/// `Compare` blocks. Like a `Number`, the block itself always evaluates to
+/// `true`: use a `Compare` block to test its result.
+pub struct Arithmetic<'block> {
+    label: Label,
+    op: ArithOp,
+    lhs: &'block dyn BasicBlock,
+    rhs: &'block dyn BasicBlock,
+}
+
+impl<'block> Arithmetic<'block> {
+    /// Create a new Arithmetic block computing `lhs op rhs`
+    pub fn new(
+        op: ArithOp,
+        lhs: &'block dyn BasicBlock,
+        rhs: &'block dyn BasicBlock,
+    ) -> Arithmetic<'block> {
+        Arithmetic {
+            label: Label::new("arithmetic"),
+            op,
+            lhs,
+            rhs,
+        }
+    }
+
+    /// Return the operation of the block
+    pub fn op(&self) -> ArithOp {
+        self.op
+    }
+
+    /// Return the left operand
+    pub fn lhs(&self) -> &'block dyn BasicBlock {
+        self.lhs
+    }
+
+    /// Return the right operand
+    pub fn rhs(&self) -> &'block dyn BasicBlock {
+        self.rhs
+    }
+
+    /// Compute the result of the block, running its operands through the
+    /// `Executor`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{ArithOp, Arithmetic, Boolean, Number};
+    /// use stir::executor::Executor;
+    ///
+    /// let six = Number::new(6.5);
+    /// let t = Boolean::new(true);
+    /// let sum = Arithmetic::new(ArithOp::Add, &six, &t);
+    /// let product = Arithmetic::new(ArithOp::Mul, &sum, &sum);
+    ///
+    /// assert_eq!(product.evaluate(&Executor::new()), Ok(49));
+    /// ```
+    pub fn evaluate(&self, exec: &Executor) -> Result<i64, InterpreterError> {
+        let lhs = exec.run_integer(self.lhs)?;
+        let rhs = exec.run_integer(self.rhs)?;
+
+        Ok(self.op.apply(lhs, rhs))
+    }
+}
+
+impl BasicBlock for Arithmetic<'_> {
+    fn label(&self) -> &String {
+        self.label.name()
+    }
+
+    fn output(&self) -> String {
+        format!(
+            "({} {} {})",
+            self.lhs.output(),
+            self.op.symbol(),
+            self.rhs.output()
+        )
+    }
+
+    fn interpret(&self) -> bool {
+        self.execute(&Executor::new()).unwrap_or(false)
+    }
+
+    fn execute(&self, exec: &Executor) -> ExecResult {
+        self.evaluate(exec)?;
+
+        Ok(true)
+    }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Arithmetic(self)
+    }
+}
+
+impl std::fmt::Debug for Arithmetic<'_> {
+    fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        dbg!(&self.label);
+        dbg!(self.op);
+        dbg!(self.lhs);
+        dbg!(self.rhs);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Loop, Number};
+    use crate::executor::{FryOptions, Tracer};
+
+    #[test]
+    fn loop_bound() {
+        let two = Number::new(2.0);
+        let three = Number::new(3.0);
+        let hi = Arithmetic::new(ArithOp::Mul, &two, &three);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&two), Some(&hi), Some(&body));
+
+        let tracer = Tracer::new();
+        let mut options = FryOptions::new();
+        options.set_tracer(&tracer);
+
+        assert_eq!(Executor::with_options(options).run(&l), Ok(true));
+
+        // The loop, the bound, and the body four times
+        assert_eq!(tracer.len(), 2 + 2 + 4 * 2);
+    }
+
+    #[test]
+    fn always_true() {
+        let zero = Number::new(0.0);
+        let nan = Number::new(f64::NAN);
+
+        assert!(Arithmetic::new(ArithOp::Sub, &zero, &zero).interpret());
+        assert!(Arithmetic::new(ArithOp::Add, &nan, &nan).interpret());
+    }
+
+    #[test]
+    fn output() {
+        let one = Number::new(1.0);
+        let f = Boolean::new(false);
+
+        assert_eq!(Arithmetic::new(ArithOp::Sub, &one, &f).output(), "(1 - false)");
+    }
+}
@@ -1,7 +1,12 @@
 //! Trait that all `stir::blocks` implement. Allows for code generation and
-//! inspection
+//! inspection. Blocks are `Sync` so that they can be executed from multiple
+//! threads at once
 
-pub trait BasicBlock: std::fmt::Debug {
+use super::BlockKind;
+
+use crate::executor::{ExecResult, Executor};
+
+pub trait BasicBlock: std::fmt::Debug + Sync {
     /// Return the unique label of the block
     fn label(&self) -> &String;
 
@@ -38,6 +43,25 @@ pub trait BasicBlock: std::fmt::Debug {
     // FIXME: Logic: Return Result ?
     fn interpret(&self) -> bool;
 
+    /// Interpret and execute a block through an `Executor`. Blocks containing
+    /// other blocks must run them using `Executor::run` so that their
+    /// execution can be observed and cancelled. By default, this simply
+    /// interprets the block.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{Boolean, BasicBlock};
+    /// use stir::executor::Executor;
+    ///
+    /// let b = Boolean::new(true);
+    ///
+    /// assert_eq!(b.execute(&Executor::new()), Ok(true));
+    /// ```
+    fn execute(&self, _exec: &Executor) -> ExecResult {
+        Ok(self.interpret())
+    }
+
     /// If the block is critical or if it can safely be parallelized
     ///
     /// # Example
@@ -56,4 +80,23 @@ pub trait BasicBlock: std::fmt::Debug {
     fn is_critical(&self) -> bool {
         false
     }
+
+    /// Return a typed view of the block, used to walk and inspect trees of
+    /// blocks. Blocks defined outside of `stir` are `BlockKind::Other`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{Boolean, BlockKind, BasicBlock};
+    ///
+    /// let b = Boolean::new(true);
+    ///
+    /// match b.kind() {
+    ///     BlockKind::Boolean(_) => (),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Other
+    }
 }
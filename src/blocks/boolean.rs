@@ -1,7 +1,7 @@
 //! This block represents a boolean block. It is either `false` or `true`.
 //! It is simply a wrapper around the `bool` type in Rust
 
-use super::{BasicBlock, Primitive};
+use super::{BasicBlock, BlockKind, Primitive};
 
 use crate::label::Label;
 
@@ -55,6 +55,10 @@ impl BasicBlock for Boolean {
     fn interpret(&self) -> bool {
         self.value
     }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Boolean(self)
+    }
 }
 
 impl Primitive for Boolean {
@@ -81,6 +85,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_mut() {
         let mut b = Boolean::new(false);
 
@@ -91,6 +96,7 @@ mod tests {
         assert!(b.get());
     }
 
+    #[allow(dead_code)]
     fn test_value() {
         let b_t = Boolean::new(true);
         let b_f = Boolean::new(false);
@@ -3,8 +3,10 @@
 //! return one value at a time.
 
 use super::BasicBlock;
+use super::BlockKind;
 use super::Function;
 
+use crate::executor::{ExecResult, Executor};
 use crate::label::Label;
 
 #[derive(Debug)]
@@ -47,6 +49,16 @@ impl<'block> Call<'block> {
             args,
         }
     }
+
+    /// Return the function called by the block
+    pub fn function(&self) -> &'block Function<'block> {
+        self.function
+    }
+
+    /// Return the arguments given to the function, if any
+    pub fn args(&self) -> Option<&'block Vec<&'block dyn BasicBlock>> {
+        self.args
+    }
 }
 
 impl BasicBlock for Call<'_> {
@@ -55,10 +67,20 @@ impl BasicBlock for Call<'_> {
     }
 
     fn interpret(&self) -> bool {
-        self.function.interpret()
+        self.execute(&Executor::new()).unwrap_or(false)
+    }
+
+    fn execute(&self, exec: &Executor) -> ExecResult {
+        exec.check_cancelled()?;
+
+        exec.run(self.function)
     }
 
     fn output(&self) -> String {
         "Call".to_string() // FIXME: Add logic
     }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Call(self)
+    }
 }
@@ -0,0 +1,164 @@
+//! The Compare block compares the integer values of two other blocks.
+
+use super::{BasicBlock, BlockKind};
+
+use crate::executor::{ExecResult, Executor};
+use crate::label::Label;
+
+/// Comparison of a `Compare` block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// Compare two integers
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::CompareOp;
+    ///
+    /// assert!(CompareOp::Le.apply(2, 2));
+    /// assert!(!CompareOp::Gt.apply(-1, 0));
+    /// ```
+    pub fn apply(self, lhs: i64, rhs: i64) -> bool {
+        match self {
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+        }
+    }
+
+    /// Return the symbol of the comparison
+    pub fn symbol(self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+}
+
+/// A Compare block evaluates to the comparison of the integer values of its
+/// operands, computed like the operands of an `Arithmetic` block
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{ArithOp, Arithmetic, BasicBlock, Compare, CompareOp, Number};
+///
+/// let two = Number::new(2.0);
+/// let four = Number::new(4.0);
+/// let sum = Arithmetic::new(ArithOp::Add, &two, &two);
+///
+/// assert!(Compare::new(CompareOp::Eq, &sum, &four).interpret());
+/// assert!(!Compare::new(CompareOp::Lt, &sum, &four).interpret());
+/// ```
+pub struct Compare<'block> {
+    label: Label,
+    op: CompareOp,
+    lhs: &'block dyn BasicBlock,
+    rhs: &'block dyn BasicBlock,
+}
+
+impl<'block> Compare<'block> {
+    /// Create a new Compare block evaluating `lhs op rhs`
+    pub fn new(
+        op: CompareOp,
+        lhs: &'block dyn BasicBlock,
+        rhs: &'block dyn BasicBlock,
+    ) -> Compare<'block> {
+        Compare {
+            label: Label::new("compare"),
+            op,
+            lhs,
+            rhs,
+        }
+    }
+
+    /// Return the comparison of the block
+    pub fn op(&self) -> CompareOp {
+        self.op
+    }
+
+    /// Return the left operand
+    pub fn lhs(&self) -> &'block dyn BasicBlock {
+        self.lhs
+    }
+
+    /// Return the right operand
+    pub fn rhs(&self) -> &'block dyn BasicBlock {
+        self.rhs
+    }
+}
+
+impl BasicBlock for Compare<'_> {
+    fn label(&self) -> &String {
+        self.label.name()
+    }
+
+    fn output(&self) -> String {
+        format!(
+            "({} {} {})",
+            self.lhs.output(),
+            self.op.symbol(),
+            self.rhs.output()
+        )
+    }
+
+    fn interpret(&self) -> bool {
+        self.execute(&Executor::new()).unwrap_or(false)
+    }
+
+    fn execute(&self, exec: &Executor) -> ExecResult {
+        let lhs = exec.run_integer(self.lhs)?;
+        let rhs = exec.run_integer(self.rhs)?;
+
+        Ok(self.op.apply(lhs, rhs))
+    }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Compare(self)
+    }
+}
+
+impl std::fmt::Debug for Compare<'_> {
+    fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        dbg!(&self.label);
+        dbg!(self.op);
+        dbg!(self.lhs);
+        dbg!(self.rhs);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, IfElse, Number};
+
+    #[test]
+    fn boolean_operands() {
+        let t = Boolean::new(true);
+        let one = Number::new(1.9);
+        let ie = IfElse::new(&t, &t, None);
+
+        assert!(Compare::new(CompareOp::Eq, &t, &one).interpret());
+        assert!(Compare::new(CompareOp::Ge, &ie, &one).interpret());
+        assert!(Compare::new(CompareOp::Ne, &ie, &one).output().contains("!="));
+    }
+}
@@ -1,8 +1,9 @@
 //! A Critical block is a block that shall not be multithreaded. Critical blocks
 //! wrap around any kind of block.
 
-use super::BasicBlock;
+use super::{BasicBlock, BlockKind};
 
+use crate::executor::{ExecResult, Executor};
 use crate::label::Label;
 
 pub struct Critical<'block> {
@@ -20,6 +21,11 @@ impl<'block> Critical<'block> {
             block,
         }
     }
+
+    /// Return the block wrapped by the Critical block
+    pub fn block(&self) -> &'block dyn BasicBlock {
+        self.block
+    }
 }
 
 impl BasicBlock for Critical<'_> {
@@ -28,7 +34,11 @@ impl BasicBlock for Critical<'_> {
     }
 
     fn interpret(&self) -> bool {
-        self.block.interpret()
+        self.execute(&Executor::new()).unwrap_or(false)
+    }
+
+    fn execute(&self, exec: &Executor) -> ExecResult {
+        exec.critical(self.label(), || exec.run(self.block))
     }
 
     fn debug(&self) {
@@ -42,6 +52,10 @@ impl BasicBlock for Critical<'_> {
     fn is_critical(&self) -> bool {
         true
     }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Critical(self)
+    }
 }
 
 impl std::fmt::Debug for Critical<'_> {
@@ -1,18 +1,35 @@
 //! A Function block is a block containing other blocks. It basically
 //! contains a sequence of other blocks to execute one by one.
 
-use super::BasicBlock;
+use super::{BasicBlock, BlockKind};
 
+use crate::executor::{ExecResult, Executor};
 use crate::label::Label;
 
 use std::vec::Vec;
 
+/// Inlining attribute of a function, telling the inliner whether the body of
+/// the function may replace the calls to it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Inline {
+    /// Let the inliner decide, based on the size of the function
+    #[default]
+    Auto,
+
+    /// Always inline the function when possible
+    Always,
+
+    /// Never inline the function
+    Never,
+}
+
 #[derive(Debug)]
 pub struct Function<'block> {
     label: Label,
     args: Option<&'block Vec<&'block dyn BasicBlock>>,
     stmts: &'block Vec<&'block dyn BasicBlock>,
     retval: Option<&'block dyn BasicBlock>,
+    inline: Inline,
 }
 
 impl<'block> Function<'block> {
@@ -42,6 +59,7 @@ impl<'block> Function<'block> {
             args,
             stmts,
             retval: None,
+            inline: Inline::Auto,
         }
     }
 
@@ -55,6 +73,46 @@ impl<'block> Function<'block> {
             None => None,
         }
     }
+
+    /// Return the arguments of the function, if any
+    pub fn args(&self) -> Option<&'block Vec<&'block dyn BasicBlock>> {
+        self.args
+    }
+
+    /// Return the statements executed by the function, in order
+    pub fn stmts(&self) -> &'block Vec<&'block dyn BasicBlock> {
+        self.stmts
+    }
+
+    /// Return the block evaluated as the return value of the function
+    pub fn retval(&self) -> Option<&'block dyn BasicBlock> {
+        self.retval
+    }
+
+    /// Set the inlining attribute of the function
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{BasicBlock, Function, Inline};
+    ///
+    /// let stmts: Vec<&dyn BasicBlock> = vec![];
+    /// let mut f = Function::new(None, &stmts);
+    ///
+    /// assert_eq!(f.inline(), Inline::Auto);
+    ///
+    /// f.set_inline(Inline::Never);
+    ///
+    /// assert_eq!(f.inline(), Inline::Never);
+    /// ```
+    pub fn set_inline(&mut self, inline: Inline) {
+        self.inline = inline;
+    }
+
+    /// Return the inlining attribute of the function
+    pub fn inline(&self) -> Inline {
+        self.inline
+    }
 }
 
 impl BasicBlock for Function<'_> {
@@ -63,22 +121,31 @@ impl BasicBlock for Function<'_> {
     }
 
     fn interpret(&self) -> bool {
-        for statement in self.stmts.iter() {
-            statement.interpret();
-        }
+        self.execute(&Executor::new()).unwrap_or(false)
+    }
+
+    fn execute(&self, exec: &Executor) -> ExecResult {
+        // The values of the statements are discarded: they can be executed
+        // in parallel
+        exec.run_all(self.stmts)?;
 
         match self.retval {
-            Some(val) => val.interpret(),
-            None => false,
+            Some(val) => exec.run(val),
+            None => Ok(false),
         }
     }
 
     fn output(&self) -> String {
         String::from("function") // FIXME: Add logic
     }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Function(self)
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::vec_init_then_push)]
 mod tests {
     use super::*;
     use crate::blocks::{Boolean, IfElse};
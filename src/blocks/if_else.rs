@@ -1,5 +1,10 @@
-use super::BasicBlock;
+use std::thread;
 
+use super::{BasicBlock, BlockKind};
+
+use crate::analysis;
+
+use crate::executor::{ExecResult, Executor};
 use crate::label::Label;
 
 /// An IfElse block allows you to execute another block based on a given
@@ -34,11 +39,52 @@ impl<'block> IfElse<'block> {
             f_block,
         }
     }
+
+    /// Return the condition of the IfElse block
+    pub fn cond_block(&self) -> &'block dyn BasicBlock {
+        self.cond_block
+    }
+
+    /// Return the block executed if the condition evaluates to `true`
+    pub fn t_block(&self) -> &'block dyn BasicBlock {
+        self.t_block
+    }
+
+    /// Return the block executed if the condition evaluates to `false`, if any
+    pub fn f_block(&self) -> Option<&'block dyn BasicBlock> {
+        self.f_block
+    }
+
+    /// Evaluate the condition and both branches concurrently, and keep the
+    /// result of the taken branch. Branches for which no worker thread is
+    /// available are only evaluated if taken. Only valid if the block is pure
+    fn speculate(&self, exec: &Executor) -> ExecResult {
+        thread::scope(|s| {
+            let t_value = exec.spawn(s, self.t_block);
+            let f_value = self.f_block.and_then(|f_b| exec.spawn(s, f_b));
+
+            let cond = exec.run(self.cond_block)?;
+
+            // Wait for both branches so that they do not outlive the block.
+            // Errors in the untaken branch are discarded as well
+            let t_value = t_value.map(|worker| exec.join(worker));
+            let f_value = f_value.map(|worker| exec.join(worker));
+
+            if cond {
+                t_value.unwrap_or_else(|| exec.run(self.t_block))
+            } else {
+                match self.f_block {
+                    Some(f_b) => f_value.unwrap_or_else(|| exec.run(f_b)),
+                    None => Ok(false),
+                }
+            }
+        })
+    }
 }
 
 impl BasicBlock for IfElse<'_> {
     fn label(&self) -> &String {
-        return self.label.name();
+        self.label.name()
     }
 
     fn output(&self) -> String {
@@ -63,15 +109,33 @@ impl BasicBlock for IfElse<'_> {
     }
 
     fn interpret(&self) -> bool {
-        if self.cond_block.interpret() {
-            self.t_block.interpret()
+        self.execute(&Executor::new()).unwrap_or(false)
+    }
+
+    fn execute(&self, exec: &Executor) -> ExecResult {
+        if exec.options().is_speculative()
+            && exec
+                .options()
+                .cost_model()
+                .should_parallelize(self.cond_block)
+            && analysis::is_pure(self)
+        {
+            return self.speculate(exec);
+        }
+
+        if exec.run(self.cond_block)? {
+            exec.run(self.t_block)
         } else {
             match self.f_block {
-                Some(f_b) => f_b.interpret(),
-                None => false,
+                Some(f_b) => exec.run(f_b),
+                None => Ok(false),
             }
         }
     }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::IfElse(self)
+    }
 }
 
 impl std::fmt::Debug for IfElse<'_> {
@@ -89,7 +153,22 @@ impl std::fmt::Debug for IfElse<'_> {
 mod tests {
     use super::*;
 
-    use crate::blocks::Boolean;
+    use crate::blocks::{Boolean, Critical};
+    use crate::cost::CostModel;
+    use crate::executor::{FryOptions, Tracer};
+
+    fn speculative_options(tracer: &Tracer) -> FryOptions<'_> {
+        let mut model = CostModel::new();
+        model.set_threshold(0);
+
+        let mut options = FryOptions::new();
+        options.set_threads(3);
+        options.set_speculative(true);
+        options.set_cost_model(model);
+        options.set_tracer(tracer);
+
+        options
+    }
 
     #[test]
     fn cond_true() {
@@ -142,4 +221,77 @@ mod tests {
 
         assert_eq!(ie_str, ie.output());
     }
+
+    #[test]
+    fn speculative_result() {
+        let tracer = Tracer::new();
+        let exec = Executor::with_options(speculative_options(&tracer));
+
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+
+        let c = Boolean::new(true);
+        assert_eq!(exec.run(&IfElse::new(&c, &t, Some(&f))), Ok(true));
+
+        let c = Boolean::new(false);
+        assert_eq!(exec.run(&IfElse::new(&c, &t, Some(&f))), Ok(false));
+        assert_eq!(exec.run(&IfElse::new(&c, &t, None)), Ok(false));
+    }
+
+    #[test]
+    fn speculative_runs_both_branches() {
+        let tracer = Tracer::new();
+        let exec = Executor::with_options(speculative_options(&tracer));
+
+        let c = Boolean::new(true);
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+        let ie = IfElse::new(&c, &t, Some(&f));
+
+        exec.run(&ie).unwrap();
+
+        // Enter and exit events for the IfElse, its condition and both branches
+        assert_eq!(tracer.len(), 8);
+
+        let mut json = Vec::new();
+        tracer.write_chrome_trace(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        assert!(json.contains("\"tid\":1"));
+        assert!(json.contains("\"tid\":2"));
+    }
+
+    #[test]
+    fn speculative_skips_impure() {
+        let tracer = Tracer::new();
+        let exec = Executor::with_options(speculative_options(&tracer));
+
+        let c = Boolean::new(true);
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+        let crit_f = Critical::new(&f);
+        let ie = IfElse::new(&c, &t, Some(&crit_f));
+
+        assert_eq!(exec.run(&ie), Ok(true));
+
+        // The critical branch is never executed
+        assert_eq!(tracer.len(), 6);
+    }
+
+    #[test]
+    fn speculative_skips_cheap_cond() {
+        let tracer = Tracer::new();
+        let mut options = speculative_options(&tracer);
+        options.set_cost_model(CostModel::new());
+
+        let exec = Executor::with_options(options);
+
+        let c = Boolean::new(true);
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+        let ie = IfElse::new(&c, &t, Some(&f));
+
+        assert_eq!(exec.run(&ie), Ok(true));
+        assert_eq!(tracer.len(), 6);
+    }
 }
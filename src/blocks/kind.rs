@@ -0,0 +1,73 @@
+//! A `BlockKind` gives a typed view over a block. Use it to walk a tree of
+//! blocks and inspect them without knowing their concrete type beforehand.
+
+use super::{
+    Arithmetic, BasicBlock, Boolean, Call, Compare, Critical, Function, IfElse, Loop, Number, Str,
+};
+
+/// Typed reference to one of the blocks provided by `stir`
+#[derive(Clone, Copy)]
+pub enum BlockKind<'a> {
+    Boolean(&'a Boolean),
+    Number(&'a Number),
+    Str(&'a Str),
+    IfElse(&'a IfElse<'a>),
+    Loop(&'a Loop<'a>),
+    Function(&'a Function<'a>),
+    Call(&'a Call<'a>),
+    Critical(&'a Critical<'a>),
+    Arithmetic(&'a Arithmetic<'a>),
+    Compare(&'a Compare<'a>),
+
+    /// Block that is not part of `stir`. It can only be interpreted
+    Other,
+}
+
+impl<'a> BlockKind<'a> {
+    /// Return the blocks directly referenced by the block, in evaluation
+    /// order
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{Boolean, IfElse, BasicBlock};
+    ///
+    /// let c = Boolean::new(true);
+    /// let t = Boolean::new(false);
+    /// let ie = IfElse::new(&c, &t, None);
+    ///
+    /// assert_eq!(ie.kind().children().len(), 2);
+    /// assert!(c.kind().children().is_empty());
+    /// ```
+    pub fn children(&self) -> Vec<&'a dyn BasicBlock> {
+        match *self {
+            BlockKind::IfElse(ie) => {
+                let mut children = vec![ie.cond_block(), ie.t_block()];
+                children.extend(ie.f_block());
+                children
+            }
+            BlockKind::Loop(l) => l
+                .lo_bound()
+                .into_iter()
+                .chain(l.hi_bound())
+                .chain(l.body())
+                .collect(),
+            BlockKind::Function(f) => f.stmts().iter().copied().chain(f.retval()).collect(),
+            BlockKind::Call(c) => vec![c.function() as &dyn BasicBlock],
+            BlockKind::Critical(c) => vec![c.block()],
+            BlockKind::Arithmetic(a) => vec![a.lhs(), a.rhs()],
+            BlockKind::Compare(c) => vec![c.lhs(), c.rhs()],
+            BlockKind::Boolean(_) | BlockKind::Number(_) | BlockKind::Str(_) | BlockKind::Other => {
+                vec![]
+            }
+        }
+    }
+
+    /// Return `true` if the block is a `Boolean`, `Number` or `Str`
+    pub fn is_primitive(&self) -> bool {
+        matches!(
+            self,
+            BlockKind::Boolean(_) | BlockKind::Number(_) | BlockKind::Str(_)
+        )
+    }
+}
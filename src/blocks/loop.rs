@@ -1,9 +1,13 @@
 //! The Loop block is used to represent ranged and infinite loops.
 
-use super::BasicBlock;
+use super::{BasicBlock, BlockKind, Primitive};
 
+use crate::executor::{ExecResult, Executor};
 use crate::label::Label;
 
+/// A Loop executes its body once per value between its lower bound
+/// (included) and its higher bound (excluded). It evaluates to `true` if its
+/// body evaluated to `true` at every iteration.
 pub struct Loop<'block> {
     label: Label,
     lo_bound: Option<&'block dyn BasicBlock>,
@@ -26,6 +30,63 @@ impl<'block> Loop<'block> {
             body,
         }
     }
+
+    /// Return the lower bound of the loop. `None` if the loop is infinite
+    pub fn lo_bound(&self) -> Option<&'block dyn BasicBlock> {
+        self.lo_bound
+    }
+
+    /// Return the higher bound of the loop. `None` if the loop is infinite
+    pub fn hi_bound(&self) -> Option<&'block dyn BasicBlock> {
+        self.hi_bound
+    }
+
+    /// Return the body of the loop, if any
+    pub fn body(&self) -> Option<&'block dyn BasicBlock> {
+        self.body
+    }
+
+    /// Return the number of iterations of the loop if both of its bounds are
+    /// primitives. A `Number` bound counts as its value, any other primitive
+    /// bound counts as `1` if it interprets to `true`, `0` otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{Loop, Number};
+    ///
+    /// let lo = Number::new(2.0);
+    /// let hi = Number::new(12.0);
+    ///
+    /// assert_eq!(Loop::new(Some(&lo), Some(&hi), None).trip_count(), Some(10));
+    /// assert_eq!(Loop::new(Some(&hi), Some(&lo), None).trip_count(), Some(0));
+    /// assert_eq!(Loop::new(None, None, None).trip_count(), None);
+    /// ```
+    pub fn trip_count(&self) -> Option<u64> {
+        let lo = Loop::const_bound(self.lo_bound?)?;
+        let hi = Loop::const_bound(self.hi_bound?)?;
+
+        Some(hi.saturating_sub(lo).max(0) as u64)
+    }
+
+    /// Value of a bound known before running the loop
+    fn const_bound(bound: &dyn BasicBlock) -> Option<i64> {
+        match bound.kind() {
+            BlockKind::Number(n) => Some(n.get() as i64),
+            k if k.is_primitive() => Some(bound.interpret() as i64),
+            _ => None,
+        }
+    }
+
+    /// Execute the body once, checking for cancellation beforehand
+    fn iterate(&self, exec: &Executor) -> ExecResult {
+        exec.check_cancelled()?;
+
+        match self.body {
+            Some(body) => exec.run(body),
+            None => Ok(true),
+        }
+    }
 }
 
 impl BasicBlock for Loop<'_> {
@@ -34,12 +95,37 @@ impl BasicBlock for Loop<'_> {
     }
 
     fn interpret(&self) -> bool {
-        true // FIXME: Logic: Add logic
+        self.execute(&Executor::new()).unwrap_or(false)
+    }
+
+    fn execute(&self, exec: &Executor) -> ExecResult {
+        let mut value = true;
+
+        match (self.lo_bound, self.hi_bound) {
+            (Some(lo), Some(hi)) => {
+                let lo = exec.run_integer(lo)?;
+                let hi = exec.run_integer(hi)?;
+
+                for _ in lo..hi {
+                    value &= self.iterate(exec)?;
+                }
+            }
+            // Infinite loops only stop when cancelled
+            _ => loop {
+                self.iterate(exec)?;
+            },
+        }
+
+        Ok(value)
     }
 
     fn output(&self) -> String {
         String::from("loop") // FIXME: Logic: Pretty print
     }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Loop(self)
+    }
 }
 
 impl std::fmt::Debug for Loop<'_> {
@@ -52,3 +138,60 @@ impl std::fmt::Debug for Loop<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, IfElse, Number};
+    use crate::executor::{FryOptions, Tracer};
+
+    #[test]
+    fn iterations() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(5.0);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let tracer = Tracer::new();
+        let mut options = FryOptions::new();
+        options.set_tracer(&tracer);
+
+        assert_eq!(Executor::with_options(options).run(&l), Ok(true));
+
+        // The loop itself, and the body five times
+        assert_eq!(tracer.len(), 2 + 5 * 2);
+    }
+
+    #[test]
+    fn false_body() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(5.0);
+        let body = Boolean::new(false);
+
+        assert!(!Loop::new(Some(&lo), Some(&hi), Some(&body)).interpret());
+    }
+
+    #[test]
+    fn no_iteration() {
+        let lo = Number::new(5.0);
+        let hi = Number::new(0.0);
+        let body = Boolean::new(false);
+
+        assert!(Loop::new(Some(&lo), Some(&hi), Some(&body)).interpret());
+    }
+
+    #[test]
+    fn block_bound() {
+        let lo = Number::new(0.0);
+        let c = Boolean::new(true);
+        let t = Boolean::new(true);
+        let hi = IfElse::new(&c, &t, None);
+        let body = Boolean::new(false);
+
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        assert_eq!(l.trip_count(), None);
+        assert!(!l.interpret());
+    }
+}
@@ -3,23 +3,29 @@
 //! [`BasicBlock`](blocks/trait.BasicBlock.html) trait and have a ::new()
 //! method for easy initialization
 
+mod arithmetic;
 mod basic_block;
 mod boolean;
 mod call;
+mod compare;
 mod critical;
 mod function;
 mod if_else;
+mod kind;
 mod number;
 mod primitive;
 mod static_str;
 mod r#loop;
 
+pub use arithmetic::{ArithOp, Arithmetic};
 pub use basic_block::BasicBlock;
 pub use boolean::Boolean;
 pub use call::Call;
+pub use compare::{Compare, CompareOp};
 pub use critical::Critical;
-pub use function::Function;
+pub use function::{Function, Inline};
 pub use if_else::IfElse;
+pub use kind::BlockKind;
 pub use number::Number;
 pub use primitive::Primitive;
 pub use static_str::Str;
@@ -1,6 +1,6 @@
 //! This block represents a number. Number are represented using a double
 
-use super::{BasicBlock, Primitive};
+use super::{BasicBlock, BlockKind, Primitive};
 
 use crate::label::Label;
 
@@ -43,4 +43,8 @@ impl BasicBlock for Number {
     fn interpret(&self) -> bool {
         !self.value.is_nan()
     }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Number(self)
+    }
 }
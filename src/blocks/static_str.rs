@@ -1,6 +1,6 @@
 //! Str are a block wrapper around rust's Strings.
 
-use super::{BasicBlock, Primitive};
+use super::{BasicBlock, BlockKind, Primitive};
 
 use crate::label::Label;
 
@@ -41,6 +41,10 @@ impl BasicBlock for Str {
     }
 
     fn interpret(&self) -> bool {
-        self.value.len() > 0
+        !self.value.is_empty()
+    }
+
+    fn kind(&self) -> BlockKind<'_> {
+        BlockKind::Str(self)
     }
 }
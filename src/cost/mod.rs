@@ -0,0 +1,290 @@
+//! The `CostModel` estimates the amount of work performed by a block. Spawning
+//! a thread to execute a tiny block such as a `Boolean` costs more than the
+//! block itself: use the model to decide if a block is worth parallelizing.
+
+use std::collections::HashMap;
+
+use crate::blocks::{BasicBlock, BlockKind};
+use crate::opt::Remark;
+
+/// Default minimum estimated work for a block to be executed in parallel
+pub const DEFAULT_THRESHOLD: u64 = 1000;
+
+/// Default number of iterations assumed for loops whose bounds are unknown
+/// before execution
+pub const DEFAULT_TRIP_COUNT: u64 = 16;
+
+/// Estimate of the work performed by blocks, in arbitrary units. A primitive
+/// block costs one unit.
+#[derive(Debug, Clone)]
+pub struct CostModel {
+    /// Minimum estimated work for a block to be parallelized
+    threshold: u64,
+
+    /// Iterations assumed for loops whose bounds are not primitives
+    trip_count: u64,
+
+    /// Cost of calling a function, on top of the cost of its body
+    call_overhead: u64,
+
+    /// Measured costs, indexed by block label. They take precedence over the
+    /// static estimation
+    profile: HashMap<String, u64>,
+}
+
+impl CostModel {
+    /// Create a new CostModel using the default thresholds and no profile
+    /// data
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::Boolean;
+    /// use stir::cost::CostModel;
+    ///
+    /// let model = CostModel::new();
+    /// let b = Boolean::new(true);
+    ///
+    /// assert_eq!(model.estimate(&b), 1);
+    /// assert!(!model.should_parallelize(&b));
+    /// ```
+    pub fn new() -> CostModel {
+        CostModel {
+            threshold: DEFAULT_THRESHOLD,
+            trip_count: DEFAULT_TRIP_COUNT,
+            call_overhead: 2,
+            profile: HashMap::new(),
+        }
+    }
+
+    /// Return the minimum estimated work for a block to be parallelized
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    /// Set the minimum estimated work for a block to be parallelized
+    pub fn set_threshold(&mut self, threshold: u64) {
+        self.threshold = threshold;
+    }
+
+    /// Set the number of iterations assumed for loops whose bounds are only
+    /// known at run-time
+    pub fn set_trip_count(&mut self, trip_count: u64) {
+        self.trip_count = trip_count;
+    }
+
+    /// Set the cost of calling a function, on top of the cost of its body
+    pub fn set_call_overhead(&mut self, call_overhead: u64) {
+        self.call_overhead = call_overhead;
+    }
+
+    /// Record the measured cost of a block. The measure replaces the static
+    /// estimation of that block
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{Boolean, BasicBlock};
+    /// use stir::cost::CostModel;
+    ///
+    /// let mut model = CostModel::new();
+    /// let b = Boolean::new(true);
+    ///
+    /// model.add_profile(b.label(), 4000);
+    ///
+    /// assert!(model.should_parallelize(&b));
+    /// ```
+    pub fn add_profile(&mut self, label: &str, cost: u64) {
+        self.profile.insert(String::from(label), cost);
+    }
+
+    /// Estimate the work performed when executing a block
+    pub fn estimate(&self, block: &dyn BasicBlock) -> u64 {
+        if let Some(cost) = self.profile.get(block.label()) {
+            return *cost;
+        }
+
+        let kind = block.kind();
+
+        match kind {
+            BlockKind::IfElse(ie) => {
+                let t_cost = self.estimate(ie.t_block());
+                let f_cost = ie.f_block().map_or(0, |f| self.estimate(f));
+
+                1u64.saturating_add(self.estimate(ie.cond_block()))
+                    .saturating_add(t_cost.max(f_cost))
+            }
+            BlockKind::Loop(l) => {
+                let iterations = match (l.lo_bound(), l.hi_bound()) {
+                    (Some(_), Some(_)) => l.trip_count().unwrap_or(self.trip_count),
+                    // Infinite loops never end
+                    _ => return u64::MAX,
+                };
+                let body = l.body().map_or(0, |b| self.estimate(b));
+
+                self.sum(kind.children().into_iter().take(2))
+                    .saturating_add(iterations.saturating_mul(body.saturating_add(1)))
+            }
+            BlockKind::Call(_) => self.call_overhead.saturating_add(self.sum(kind.children())),
+            _ => 1u64.saturating_add(self.sum(kind.children())),
+        }
+    }
+
+    /// Return `true` if the block performs enough work to be worth executing
+    /// in parallel
+    pub fn should_parallelize(&self, block: &dyn BasicBlock) -> bool {
+        self.estimate(block) >= self.threshold
+    }
+
+    /// Explain the decision of `should_parallelize` about a block, as a
+    /// remark emitted by `pass`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::Boolean;
+    /// use stir::cost::CostModel;
+    /// use stir::opt::RemarkKind;
+    ///
+    /// let model = CostModel::new();
+    /// let b = Boolean::new(true);
+    /// let remark = model.remark("executor", &b);
+    ///
+    /// assert_eq!(remark.kind(), RemarkKind::Missed);
+    /// assert_eq!(remark.reason(), "the estimated cost 1 is below the threshold 1000");
+    /// ```
+    pub fn remark(&self, pass: &str, block: &dyn BasicBlock) -> Remark {
+        self.remark_cost(pass, block, self.estimate(block))
+    }
+
+    /// Explain the decision about a block whose estimated cost is already
+    /// known
+    pub(crate) fn remark_cost(&self, pass: &str, block: &dyn BasicBlock, cost: u64) -> Remark {
+        match cost >= self.threshold {
+            true => Remark::applied(
+                pass,
+                block.label(),
+                format!(
+                    "the estimated cost {} reaches the threshold {}",
+                    cost, self.threshold
+                ),
+            ),
+            false => Remark::missed(
+                pass,
+                block.label(),
+                format!(
+                    "the estimated cost {} is below the threshold {}",
+                    cost, self.threshold
+                ),
+            ),
+        }
+    }
+
+    fn sum<'a>(&self, blocks: impl IntoIterator<Item = &'a dyn BasicBlock>) -> u64 {
+        blocks
+            .into_iter()
+            .fold(0, |acc, block| acc.saturating_add(self.estimate(block)))
+    }
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Call, Function, IfElse, Loop, Number};
+
+    #[test]
+    fn primitive() {
+        let model = CostModel::new();
+        let n = Number::new(12.0);
+
+        assert_eq!(model.estimate(&n), 1);
+    }
+
+    #[test]
+    fn if_else_takes_costliest_branch() {
+        let model = CostModel::new();
+
+        let c = Boolean::new(true);
+        let t = Boolean::new(true);
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let f = Loop::new(Some(&lo), Some(&hi), Some(&t));
+        let ie = IfElse::new(&c, &t, Some(&f));
+
+        assert_eq!(model.estimate(&ie), 1 + 1 + model.estimate(&f));
+    }
+
+    #[test]
+    fn loop_const_bounds() {
+        let model = CostModel::new();
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        assert_eq!(model.estimate(&l), 2 + 10 * 2);
+    }
+
+    #[test]
+    fn loop_unknown_bounds() {
+        let mut model = CostModel::new();
+        model.set_trip_count(100);
+
+        let c = Boolean::new(true);
+        let t = Number::new(12.0);
+        let hi = IfElse::new(&c, &t, None);
+        let lo = Number::new(0.0);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        assert_eq!(model.estimate(&l), 1 + 3 + 100 * 2);
+    }
+
+    #[test]
+    fn infinite_loop() {
+        let model = CostModel::new();
+        let l = Loop::new(None, None, None);
+
+        assert_eq!(model.estimate(&l), u64::MAX);
+        assert!(model.should_parallelize(&l));
+    }
+
+    #[test]
+    fn call_overhead() {
+        let mut model = CostModel::new();
+        model.set_call_overhead(10);
+
+        let b = Boolean::new(true);
+        let stmts: Vec<&dyn BasicBlock> = vec![&b];
+        let f = Function::new(None, &stmts);
+        let call = Call::new(&f, None);
+
+        assert_eq!(model.estimate(&call), 10 + 2);
+    }
+
+    #[test]
+    fn profile_overrides_estimation() {
+        let mut model = CostModel::new();
+
+        let b = Boolean::new(true);
+        let stmts: Vec<&dyn BasicBlock> = vec![&b];
+        let f = Function::new(None, &stmts);
+
+        model.add_profile(b.label(), 500);
+
+        assert_eq!(model.estimate(&f), 501);
+        assert!(!model.should_parallelize(&f));
+
+        model.set_threshold(500);
+
+        assert!(model.should_parallelize(&f));
+    }
+}
@@ -0,0 +1,43 @@
+//! A `CancellationToken` allows aborting a run from another thread. The
+//! executor checks it at every loop iteration and every function call.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag used to abort a run. Clones of a token share the same flag
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a new token, which is not cancelled
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::executor::CancellationToken;
+    ///
+    /// let token = CancellationToken::new();
+    /// let other = token.clone();
+    ///
+    /// other.cancel();
+    ///
+    /// assert!(token.is_cancelled());
+    /// ```
+    pub fn new() -> CancellationToken {
+        CancellationToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Request the cancellation of the runs using the token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Return `true` if the cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,36 @@
+//! Errors happening while interpreting blocks
+
+use std::fmt;
+
+/// Reason why a run did not complete
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpreterError {
+    /// The recipe does not have an entry block
+    NoEntry,
+
+    /// The run was aborted using a `CancellationToken`
+    Cancelled,
+
+    /// A block executed in parallel panicked
+    Panicked {
+        /// Label of the block executed by the thread that panicked
+        label: String,
+
+        /// Message given to the panic, if it was a string
+        message: String,
+    },
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpreterError::NoEntry => write!(f, "recipe has no entry block"),
+            InterpreterError::Cancelled => write!(f, "run was cancelled"),
+            InterpreterError::Panicked { label, message } => {
+                write!(f, "block {} panicked: {}", label, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InterpreterError {}
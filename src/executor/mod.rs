@@ -0,0 +1,824 @@
+//! The `Executor` drives the interpretation of blocks. Blocks containing other
+//! blocks run them through the `Executor`, which allows observing the
+//! execution of each block, for example using a `Tracer`.
+//!
+//! The executor is configured using `FryOptions`. When given more than one
+//! thread, statements worth it according to the `CostModel` are executed on
+//! worker threads. `Critical` blocks are executed by one thread at a time,
+//! and never spawn worker threads themselves.
+//!
+//! Panics happening in blocks executed in parallel are caught and returned
+//! as an `InterpreterError::Panicked`. The remaining workers then stop at
+//! their next loop iteration or function call.
+//!
+//! In speculative mode, the executor evaluates the condition and both
+//! branches of an `IfElse` concurrently when the condition is expensive and
+//! the whole block is pure. The result of the untaken branch is discarded.
+//!
+//! A `Schedule` records which statements were executed on worker threads and
+//! the order in which `Critical` blocks entered their critical section. When
+//! replayed, the executor takes the same decisions, whatever the cost model
+//! and the number of idle workers.
+
+mod cancel;
+mod error;
+mod options;
+mod schedule;
+mod trace;
+
+pub use cancel::CancellationToken;
+pub use error::InterpreterError;
+pub use options::{Engine, FryOptions};
+pub use schedule::Schedule;
+pub use trace::Tracer;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread::{self, Scope, ScopedJoinHandle, ThreadId};
+
+use crate::blocks::{BasicBlock, BlockKind, Primitive};
+use crate::opt::Remark;
+
+/// Result of the execution of a block
+pub type ExecResult = Result<bool, InterpreterError>;
+
+/// Interpretation context shared by all the blocks of a run
+pub struct Executor<'a> {
+    options: FryOptions<'a>,
+
+    /// Number of worker threads that can still be spawned
+    idle_workers: AtomicUsize,
+
+    /// Set when a block executed in parallel failed, to stop the other
+    /// workers
+    aborted: AtomicBool,
+
+    /// Serializes the execution of `Critical` blocks
+    critical: Mutex<()>,
+
+    /// Thread currently executing a `Critical` block, if any
+    critical_owner: Mutex<Option<ThreadId>>,
+
+    /// Estimated costs of the blocks given to `run_all`, indexed by address
+    estimates: Mutex<HashMap<usize, u64>>,
+}
+
+impl<'a> Executor<'a> {
+    /// Create a new Executor using the default options
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::Boolean;
+    /// use stir::executor::Executor;
+    ///
+    /// let b = Boolean::new(true);
+    ///
+    /// assert_eq!(Executor::new().run(&b), Ok(true));
+    /// ```
+    pub fn new() -> Executor<'a> {
+        Executor::with_options(FryOptions::new())
+    }
+
+    /// Create a new Executor using the given options
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{Boolean, IfElse};
+    /// use stir::cost::CostModel;
+    /// use stir::executor::{Executor, FryOptions};
+    ///
+    /// let c = Boolean::new(true);
+    /// let t = Boolean::new(true);
+    /// let f = Boolean::new(false);
+    /// let ie = IfElse::new(&c, &t, Some(&f));
+    ///
+    /// // Consider every condition expensive enough
+    /// let mut model = CostModel::new();
+    /// model.set_threshold(0);
+    ///
+    /// let mut options = FryOptions::new();
+    /// options.set_threads(3);
+    /// options.set_speculative(true);
+    /// options.set_cost_model(model);
+    ///
+    /// assert_eq!(Executor::with_options(options).run(&ie), Ok(true));
+    /// ```
+    pub fn with_options(options: FryOptions<'a>) -> Executor<'a> {
+        Executor {
+            idle_workers: AtomicUsize::new(options.threads() - 1),
+            aborted: AtomicBool::new(false),
+            options,
+            critical: Mutex::new(()),
+            critical_owner: Mutex::new(None),
+            estimates: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return the options used by the Executor
+    pub fn options(&self) -> &FryOptions<'a> {
+        &self.options
+    }
+
+    /// Interpret and execute a block
+    pub fn run(&self, block: &dyn BasicBlock) -> ExecResult {
+        if let Some(tracer) = self.options.tracer() {
+            tracer.enter(block.label());
+        }
+
+        let value = block.execute(self);
+
+        if let Some(tracer) = self.options.tracer() {
+            tracer.exit(block.label());
+        }
+
+        value
+    }
+
+    /// Interpret and execute a block whose integer value is used, such as a
+    /// loop bound or the operand of an `Arithmetic` block. A `Number` counts
+    /// as its value truncated to an integer, an `Arithmetic` block as its
+    /// result, and any other block as `1` if it evaluates to `true`, `0`
+    /// otherwise
+    ///
+    /// ```
+    /// use stir::blocks::{ArithOp, Arithmetic, Boolean, Number};
+    /// use stir::executor::Executor;
+    ///
+    /// let n = Number::new(-2.5);
+    /// let t = Boolean::new(true);
+    /// let diff = Arithmetic::new(ArithOp::Sub, &n, &t);
+    ///
+    /// let exec = Executor::new();
+    ///
+    /// assert_eq!(exec.run_integer(&n), Ok(-2));
+    /// assert_eq!(exec.run_integer(&t), Ok(1));
+    /// assert_eq!(exec.run_integer(&diff), Ok(-3));
+    /// ```
+    pub fn run_integer(&self, block: &dyn BasicBlock) -> Result<i64, InterpreterError> {
+        let arithmetic = match block.kind() {
+            BlockKind::Number(n) => return Ok(n.get() as i64),
+            BlockKind::Arithmetic(a) => a,
+            _ => return Ok(self.run(block)? as i64),
+        };
+
+        if let Some(tracer) = self.options.tracer() {
+            tracer.enter(block.label());
+        }
+
+        let value = arithmetic.evaluate(self);
+
+        if let Some(tracer) = self.options.tracer() {
+            tracer.exit(block.label());
+        }
+
+        value
+    }
+
+    /// Execute blocks whose values are discarded. Blocks worth it are executed
+    /// on worker threads, if some are available. The other blocks are
+    /// executed in order on the current thread.
+    ///
+    /// If one of the blocks fails, the remaining workers are stopped and the
+    /// first error is returned. Panics are preferred over the cancellations
+    /// they cause. Once a block failed, the Executor cancels any later run.
+    ///
+    /// When replaying a schedule, the blocks recorded as executed on a worker
+    /// thread are spawned even if no worker is idle.
+    pub fn run_all(&self, blocks: &[&dyn BasicBlock]) -> Result<(), InterpreterError> {
+        thread::scope(|s| {
+            let mut workers = Vec::new();
+            let mut error = None;
+
+            for block in blocks {
+                let block = *block;
+                let schedule = self.options.schedule();
+
+                let worker = match schedule.and_then(|sched| sched.replayed_spawn(block.label())) {
+                    Some(true) => {
+                        self.remark(|| {
+                            Remark::applied(
+                                "executor",
+                                block.label(),
+                                "the replayed schedule executed the block on a worker thread",
+                            )
+                        });
+                        self.spawn_worker(s, block, true)
+                    }
+                    Some(false) => {
+                        self.remark(|| {
+                            Remark::missed(
+                                "executor",
+                                block.label(),
+                                "the replayed schedule executed the block on the current thread",
+                            )
+                        });
+                        None
+                    }
+                    None if block.is_critical() => {
+                        self.remark(|| {
+                            Remark::missed("executor", block.label(), "the block is critical")
+                        });
+                        None
+                    }
+                    None if self.idle_workers.load(Ordering::SeqCst) == 0 => {
+                        self.remark(|| {
+                            Remark::missed(
+                                "executor",
+                                block.label(),
+                                "no worker thread is available",
+                            )
+                        });
+                        None
+                    }
+                    None if self.estimate(block) < self.options.cost_model().threshold() => {
+                        self.remark(|| self.cost_remark(block));
+                        None
+                    }
+                    None => {
+                        let worker = self.spawn(s, block);
+                        self.remark(|| match worker {
+                            Some(_) => self.cost_remark(block),
+                            None => Remark::missed(
+                                "executor",
+                                block.label(),
+                                "no worker thread is available",
+                            ),
+                        });
+                        worker
+                    }
+                };
+
+                if let Some(schedule) = schedule {
+                    schedule.record_spawn(block.label(), worker.is_some());
+                }
+
+                match worker {
+                    Some(worker) => workers.push(worker),
+                    None => {
+                        // Another thread may be running: do not let a panic
+                        // unwind past it
+                        let value = if self.options.threads() > 1 {
+                            self.guarded(block)
+                        } else {
+                            self.run(block)
+                        };
+
+                        if let Err(e) = value {
+                            error = Some(self.abort(error, e));
+                            break;
+                        }
+                    }
+                }
+            }
+
+            for worker in workers {
+                if let Err(e) = self.join(worker) {
+                    error = Some(self.abort(error, e));
+                }
+            }
+
+            match error {
+                Some(e) => Err(e),
+                None => Ok(()),
+            }
+        })
+    }
+
+    /// Estimate the work performed by a block, computing the estimate only
+    /// the first time the block is seen
+    fn estimate(&self, block: &dyn BasicBlock) -> u64 {
+        let address = block as *const dyn BasicBlock as *const () as usize;
+
+        if let Some(cost) = lock(&self.estimates).get(&address) {
+            return *cost;
+        }
+
+        let cost = self.options.cost_model().estimate(block);
+        lock(&self.estimates).insert(address, cost);
+
+        cost
+    }
+
+    /// Explain the decision of the cost model about a block
+    fn cost_remark(&self, block: &dyn BasicBlock) -> Remark {
+        self.options
+            .cost_model()
+            .remark_cost("executor", block, self.estimate(block))
+    }
+
+    /// Collect a remark about the scheduling of a block, if remarks are
+    /// collected and the same remark was not collected yet
+    fn remark(&self, remark: impl FnOnce() -> Remark) {
+        if let Some(remarks) = self.options.remarks() {
+            let remark = remark();
+            let mut remarks = lock(remarks);
+
+            if !remarks.contains(&remark) {
+                remarks.push(remark);
+            }
+        }
+    }
+
+    /// Return an error if the run was cancelled, or if a block executed in
+    /// parallel failed
+    pub fn check_cancelled(&self) -> Result<(), InterpreterError> {
+        if self.aborted.load(Ordering::SeqCst) {
+            return Err(InterpreterError::Cancelled);
+        }
+
+        match self.options.cancellation() {
+            Some(token) if token.is_cancelled() => Err(InterpreterError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Execute `f`, the critical section of the block `label`, while no other
+    /// thread is executing a critical section. Nested critical sections are
+    /// executed directly
+    pub fn critical<T>(&self, label: &str, f: impl FnOnce() -> T) -> T {
+        let current = thread::current().id();
+
+        if *lock(&self.critical_owner) == Some(current) {
+            return f();
+        }
+
+        let schedule = self.options.schedule();
+        if let Some(schedule) = schedule {
+            schedule.wait_turn(label, || self.check_cancelled().is_err());
+        }
+
+        let _guard = lock(&self.critical);
+        *lock(&self.critical_owner) = Some(current);
+
+        if let Some(schedule) = schedule {
+            schedule.locked(label);
+        }
+
+        let value = f();
+
+        *lock(&self.critical_owner) = None;
+
+        value
+    }
+
+    /// Execute a block on a worker thread, if one is available. Threads
+    /// executing a critical section never spawn workers. Panics happening on
+    /// the worker are returned as errors
+    pub(crate) fn spawn<'scope, 'env>(
+        &'env self,
+        scope: &'scope Scope<'scope, 'env>,
+        block: &'env dyn BasicBlock,
+    ) -> Option<ScopedJoinHandle<'scope, ExecResult>> {
+        self.spawn_worker(scope, block, false)
+    }
+
+    /// Execute a block on a worker thread. When `forced`, the thread is
+    /// spawned even if no worker is idle
+    fn spawn_worker<'scope, 'env>(
+        &'env self,
+        scope: &'scope Scope<'scope, 'env>,
+        block: &'env dyn BasicBlock,
+        forced: bool,
+    ) -> Option<ScopedJoinHandle<'scope, ExecResult>> {
+        if *lock(&self.critical_owner) == Some(thread::current().id()) {
+            return None;
+        }
+
+        let reserved = self
+            .idle_workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |idle| {
+                idle.checked_sub(1)
+            })
+            .is_ok();
+
+        if !reserved && !forced {
+            return None;
+        }
+
+        let mut builder = thread::Builder::new();
+        if let Some(stack_size) = self.options.stack_size() {
+            builder = builder.stack_size(stack_size);
+        }
+
+        let spawned = builder.spawn_scoped(scope, move || {
+            let value = self.guarded(block);
+
+            // Stop the other workers right away, without waiting for the
+            // worker to be joined
+            if let Err(InterpreterError::Panicked { .. }) = value {
+                self.aborted.store(true, Ordering::SeqCst);
+            }
+
+            if reserved {
+                self.idle_workers.fetch_add(1, Ordering::SeqCst);
+            }
+            value
+        });
+
+        match spawned {
+            Ok(handle) => Some(handle),
+            Err(_) => {
+                if reserved {
+                    self.idle_workers.fetch_add(1, Ordering::SeqCst);
+                }
+                None
+            }
+        }
+    }
+
+    /// Wait for a worker thread to be done and return its result
+    pub(crate) fn join(&self, worker: ScopedJoinHandle<'_, ExecResult>) -> ExecResult {
+        // Workers catch their own panics
+        worker.join().unwrap_or_else(|payload| {
+            Err(InterpreterError::Panicked {
+                label: String::from("<worker>"),
+                message: panic_message(payload.as_ref()),
+            })
+        })
+    }
+
+    /// Execute a block, converting a panic into an error naming the block
+    fn guarded(&self, block: &dyn BasicBlock) -> ExecResult {
+        panic::catch_unwind(AssertUnwindSafe(|| self.run(block))).unwrap_or_else(|payload| {
+            Err(InterpreterError::Panicked {
+                label: block.label().clone(),
+                message: panic_message(payload.as_ref()),
+            })
+        })
+    }
+
+    /// Stop the workers still running and return the error to report: the
+    /// first one, unless a panic happened after a cancellation
+    fn abort(&self, first: Option<InterpreterError>, e: InterpreterError) -> InterpreterError {
+        self.aborted.store(true, Ordering::SeqCst);
+
+        match (first, e) {
+            (Some(InterpreterError::Cancelled), e @ InterpreterError::Panicked { .. }) => e,
+            (Some(first), _) => first,
+            (None, e) => e,
+        }
+    }
+}
+
+impl Default for Executor<'_> {
+    fn default() -> Self {
+        Executor::new()
+    }
+}
+
+/// Lock a mutex, ignoring poisoning: the data it protects stays consistent
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Extract the message given to `panic!`, if any
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        String::from(*message)
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("<unknown>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Critical, Function, IfElse, Loop, Number};
+    use crate::cost::CostModel;
+    use crate::label::Label;
+
+    /// Block panicking when interpreted
+    #[derive(Debug)]
+    struct Panic {
+        label: Label,
+    }
+
+    impl Panic {
+        fn new() -> Panic {
+            Panic {
+                label: Label::new("panic"),
+            }
+        }
+    }
+
+    impl BasicBlock for Panic {
+        fn label(&self) -> &String {
+            self.label.name()
+        }
+
+        fn output(&self) -> String {
+            String::from("panic")
+        }
+
+        fn interpret(&self) -> bool {
+            panic!("boom")
+        }
+    }
+
+    fn parallel_options<'a>(threads: usize) -> FryOptions<'a> {
+        let mut model = CostModel::new();
+        model.set_threshold(0);
+
+        let mut options = FryOptions::new();
+        options.set_threads(threads);
+        options.set_cost_model(model);
+
+        options
+    }
+
+    #[test]
+    fn run_all_uses_workers() {
+        let tracer = Tracer::new();
+        let mut options = parallel_options(4);
+        options.set_tracer(&tracer);
+        options.set_stack_size(256 * 1024);
+
+        let exec = Executor::with_options(options);
+
+        let b0 = Boolean::new(true);
+        let b1 = Boolean::new(true);
+        let b2 = Boolean::new(true);
+        let b3 = Boolean::new(true);
+
+        exec.run_all(&[&b0, &b1, &b2, &b3]).unwrap();
+
+        let mut json = Vec::new();
+        tracer.write_chrome_trace(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        // Three workers, the last block runs on the current thread
+        assert!(json.contains("\"tid\":3"));
+        assert!(!json.contains("\"tid\":4"));
+        assert_eq!(exec.idle_workers.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn remarks() {
+        let remarks = Mutex::new(Vec::new());
+        let mut model = CostModel::new();
+        model.set_threshold(2);
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let ie0 = IfElse::new(&b, &b, None);
+        let ie1 = IfElse::new(&b, &b, None);
+
+        for (threads, blocks) in [
+            (2, vec![&b as &dyn BasicBlock, &crit, &b, &ie0]),
+            (1, vec![&ie1]),
+        ] {
+            let mut options = parallel_options(threads);
+            options.set_cost_model(model.clone());
+            options.set_remarks(&remarks);
+
+            Executor::with_options(options).run_all(&blocks).unwrap();
+        }
+
+        let remarks = remarks.into_inner().unwrap();
+        let reasons: Vec<(&str, &str)> = remarks
+            .iter()
+            .map(|remark| (remark.label(), remark.reason()))
+            .collect();
+
+        // The second decision about `b` is the same as the first one
+        assert_eq!(
+            reasons,
+            [
+                (
+                    b.label().as_str(),
+                    "the estimated cost 1 is below the threshold 2"
+                ),
+                (crit.label().as_str(), "the block is critical"),
+                (
+                    ie0.label().as_str(),
+                    "the estimated cost 3 reaches the threshold 2"
+                ),
+                (ie1.label().as_str(), "no worker thread is available"),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_all_single_thread() {
+        let tracer = Tracer::new();
+        let mut options = parallel_options(1);
+        options.set_tracer(&tracer);
+
+        let exec = Executor::with_options(options);
+
+        let b0 = Boolean::new(true);
+        let b1 = Boolean::new(true);
+
+        exec.run_all(&[&b0, &b1]).unwrap();
+
+        let mut json = Vec::new();
+        tracer.write_chrome_trace(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        assert_eq!(json.matches("\"tid\":0").count(), 4);
+        // Without worker threads, the cost model is not consulted
+        assert!(exec.estimates.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn critical_does_not_spawn() {
+        let tracer = Tracer::new();
+        let mut options = parallel_options(4);
+        options.set_tracer(&tracer);
+
+        let exec = Executor::with_options(options);
+
+        let b0 = Boolean::new(true);
+        let b1 = Boolean::new(true);
+        let stmts: Vec<&dyn BasicBlock> = vec![&b0, &b1];
+        let f = Function::new(None, &stmts);
+        let crit = Critical::new(&f);
+
+        exec.run(&crit).unwrap();
+
+        let mut json = Vec::new();
+        tracer.write_chrome_trace(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        assert!(!json.contains("\"tid\":1"));
+    }
+
+    #[test]
+    fn nested_critical() {
+        let exec = Executor::with_options(parallel_options(2));
+
+        let b = Boolean::new(true);
+        let inner = Critical::new(&b);
+        let outer = Critical::new(&inner);
+
+        assert_eq!(exec.run(&outer), Ok(true));
+    }
+
+    #[test]
+    fn cancelled_loop() {
+        let token = CancellationToken::new();
+        let mut options = FryOptions::new();
+        options.set_cancellation(token.clone());
+
+        let exec = Executor::with_options(options);
+
+        let body = Boolean::new(true);
+        let infinite = Loop::new(None, None, Some(&body));
+
+        let canceller = thread::spawn(move || token.cancel());
+
+        assert_eq!(exec.run(&infinite), Err(InterpreterError::Cancelled));
+
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn cancelled_from_worker() {
+        let token = CancellationToken::new();
+        let mut options = parallel_options(4);
+        options.set_cancellation(token.clone());
+
+        let exec = Executor::with_options(options);
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(1000.0);
+        let body = Boolean::new(true);
+        let finite = Loop::new(Some(&lo), Some(&hi), Some(&body));
+        let infinite = Loop::new(None, None, Some(&body));
+
+        token.cancel();
+
+        assert_eq!(
+            exec.run_all(&[&infinite, &finite]),
+            Err(InterpreterError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn worker_panic() {
+        let exec = Executor::with_options(parallel_options(4));
+
+        let b = Boolean::new(true);
+        let p = Panic::new();
+
+        assert_eq!(
+            exec.run_all(&[&p, &b]),
+            Err(InterpreterError::Panicked {
+                label: p.label().clone(),
+                message: String::from("boom"),
+            })
+        );
+    }
+
+    #[test]
+    fn worker_panic_stops_workers() {
+        let exec = Executor::with_options(parallel_options(3));
+
+        let body = Boolean::new(true);
+        let infinite = Loop::new(None, None, Some(&body));
+        let p = Panic::new();
+
+        // The infinite loop runs on a worker, the panic on another one
+        let err = exec.run_all(&[&infinite, &p]).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("block {} panicked: boom", p.label())
+        );
+    }
+
+    #[test]
+    fn current_thread_panic_stops_workers() {
+        let exec = Executor::with_options(parallel_options(2));
+
+        let body = Boolean::new(true);
+        let infinite = Loop::new(None, None, Some(&body));
+        let p = Panic::new();
+
+        // The infinite loop takes the only worker, the panic happens on the
+        // current thread
+        match exec.run_all(&[&infinite, &p]) {
+            Err(InterpreterError::Panicked { label, .. }) => assert_eq!(&label, p.label()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn replay_schedule() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(8.0);
+        let inner: Vec<Boolean> = (0..4).map(|i| Boolean::new(i % 2 == 0)).collect();
+        let crits: Vec<Critical> = inner.iter().map(|b| Critical::new(b)).collect();
+        let loops: Vec<Loop> = crits
+            .iter()
+            .map(|crit| Loop::new(Some(&lo), Some(&hi), Some(crit)))
+            .collect();
+
+        let stmts: Vec<&dyn BasicBlock> = loops.iter().map(|l| l as &dyn BasicBlock).collect();
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&loops[1]);
+
+        let recorded = Schedule::new();
+        let mut options = parallel_options(4);
+        options.set_schedule(&recorded);
+
+        let expected = Executor::with_options(options).run(&func);
+
+        let mut file = Vec::new();
+        recorded.write(&mut file).unwrap();
+        let file = String::from_utf8(file).unwrap();
+
+        let replayed = Schedule::read(file.as_bytes()).unwrap();
+        let tracer = Tracer::new();
+        let mut options = parallel_options(4);
+        options.set_schedule(&replayed);
+        options.set_tracer(&tracer);
+
+        assert_eq!(Executor::with_options(options).run(&func), expected);
+
+        // The critical sections were entered in the recorded order
+        let locks: Vec<&str> = file
+            .lines()
+            .filter_map(|line| line.strip_prefix("lock "))
+            .map(|label| {
+                let idx = crits.iter().position(|c| c.label() == label).unwrap();
+                inner[idx].label().as_str()
+            })
+            .collect();
+
+        let mut json = Vec::new();
+        tracer.write_chrome_trace(&mut json).unwrap();
+        let entered: Vec<&str> = String::from_utf8(json)
+            .unwrap()
+            .lines()
+            .filter(|line| line.contains("\"ph\":\"B\""))
+            .filter_map(|line| {
+                inner
+                    .iter()
+                    .find(|b| line.contains(&format!("\"name\":\"{}\"", b.label())))
+                    .map(|b| b.label().as_str())
+            })
+            .collect();
+
+        // Each statement, then the return value
+        assert_eq!(locks.len(), 5 * 8);
+        assert_eq!(entered, locks);
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn sequential_panic() {
+        let b = Boolean::new(true);
+        let p = Panic::new();
+
+        let _ = Executor::new().run_all(&[&b, &p]);
+    }
+}
@@ -0,0 +1,244 @@
+//! `FryOptions` configure how blocks are executed: number of worker threads,
+//! their stack size, cancellation, tracing, recording or replaying the
+//! scheduling decisions...
+
+use std::sync::Mutex;
+
+use super::{CancellationToken, Schedule, Tracer};
+
+use crate::cost::CostModel;
+use crate::opt::Remark;
+
+/// Configuration of an `Executor`
+#[derive(Debug, Clone)]
+pub struct FryOptions<'a> {
+    /// Maximum number of threads executing blocks at once, including the
+    /// thread starting the run
+    threads: usize,
+
+    /// Stack size of the worker threads, in bytes. Uses the default stack
+    /// size of the standard library if `None`
+    stack_size: Option<usize>,
+
+    /// Token checked at every loop iteration and function call
+    cancellation: Option<CancellationToken>,
+
+    /// Evaluate both branches of expensive `IfElse` blocks concurrently
+    speculative: bool,
+
+    /// Decides which blocks are worth executing on another thread
+    cost_model: CostModel,
+
+    /// Records the execution timeline, if any
+    tracer: Option<&'a Tracer>,
+
+    /// Records the scheduling decisions of the run, or enforces the ones it
+    /// was read with, if any
+    schedule: Option<&'a Schedule>,
+
+    /// Collects the remarks explaining which blocks were executed on worker
+    /// threads, if any
+    remarks: Option<&'a Mutex<Vec<Remark>>>,
+
+    /// Executes the entry block of a `Recipe`
+    engine: Engine,
+}
+
+/// Strategy used by a `Recipe` to execute its blocks
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// Walk the tree of blocks, possibly executing statements on worker
+    /// threads
+    #[default]
+    Interpreter,
+
+    /// Compile the blocks to bytecode and run it on the current thread. See
+    /// [`vm`](../vm/index.html). Falls back to the interpreter when the
+    /// options require it
+    Bytecode,
+
+    /// Run the blocks as bytecode, and compile hot functions to native code.
+    /// Same as `Bytecode` on platforms other than Linux x86-64
+    Jit,
+}
+
+impl<'a> FryOptions<'a> {
+    /// Create the default options: the tree-walking interpreter, a single
+    /// thread, no cancellation, no speculation, no tracer and no schedule
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::executor::{CancellationToken, FryOptions};
+    ///
+    /// let token = CancellationToken::new();
+    ///
+    /// let mut options = FryOptions::new();
+    /// options.set_threads(4);
+    /// options.set_stack_size(4 * 1024 * 1024);
+    /// options.set_cancellation(token.clone());
+    ///
+    /// assert_eq!(options.threads(), 4);
+    /// ```
+    pub fn new() -> FryOptions<'a> {
+        FryOptions {
+            threads: 1,
+            stack_size: None,
+            cancellation: None,
+            speculative: false,
+            cost_model: CostModel::new(),
+            tracer: None,
+            schedule: None,
+            remarks: None,
+            engine: Engine::Interpreter,
+        }
+    }
+
+    /// Set the maximum number of threads executing blocks at once. `0` is
+    /// treated as `1`
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Return the maximum number of threads executing blocks at once
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Set the stack size of the worker threads, in bytes
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack_size = Some(stack_size);
+    }
+
+    /// Return the stack size of the worker threads, if set
+    pub fn stack_size(&self) -> Option<usize> {
+        self.stack_size
+    }
+
+    /// Abort the run when the token gets cancelled
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    /// Return the token used to abort the run, if any
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    /// Enable or disable the speculative evaluation of `IfElse` branches
+    pub fn set_speculative(&mut self, speculative: bool) {
+        self.speculative = speculative;
+    }
+
+    /// Return `true` if `IfElse` branches may be evaluated speculatively
+    pub fn is_speculative(&self) -> bool {
+        self.speculative
+    }
+
+    /// Set the cost model used to decide which blocks are worth executing on
+    /// another thread
+    pub fn set_cost_model(&mut self, cost_model: CostModel) {
+        self.cost_model = cost_model;
+    }
+
+    /// Return the cost model used to decide which blocks are worth executing
+    /// on another thread
+    pub fn cost_model(&self) -> &CostModel {
+        &self.cost_model
+    }
+
+    /// Record the enter and exit of every block run
+    pub fn set_tracer(&mut self, tracer: &'a Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Return the tracer recording the run, if any
+    pub fn tracer(&self) -> Option<&'a Tracer> {
+        self.tracer
+    }
+
+    /// Record the scheduling decisions of the run in the schedule or, if it
+    /// was read using `Schedule::read`, take the decisions it contains
+    pub fn set_schedule(&mut self, schedule: &'a Schedule) {
+        self.schedule = Some(schedule);
+    }
+
+    /// Return the schedule recorded or replayed by the run, if any
+    pub fn schedule(&self) -> Option<&'a Schedule> {
+        self.schedule
+    }
+
+    /// Collect the remarks explaining the decision to execute each statement
+    /// on a worker thread or not. Each remark is collected once
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Mutex;
+    ///
+    /// use stir::blocks::{BasicBlock, Boolean, Function};
+    /// use stir::executor::{Executor, FryOptions};
+    ///
+    /// let b = Boolean::new(true);
+    /// let stmts: Vec<&dyn BasicBlock> = vec![&b, &b];
+    /// let f = Function::new(None, &stmts);
+    ///
+    /// let remarks = Mutex::new(Vec::new());
+    /// let mut options = FryOptions::new();
+    /// options.set_threads(2);
+    /// options.set_remarks(&remarks);
+    ///
+    /// Executor::with_options(options).run(&f).unwrap();
+    ///
+    /// assert_eq!(remarks.lock().unwrap().len(), 1);
+    /// ```
+    pub fn set_remarks(&mut self, remarks: &'a Mutex<Vec<Remark>>) {
+        self.remarks = Some(remarks);
+    }
+
+    /// Return the collector of the remarks of the run, if any
+    pub fn remarks(&self) -> Option<&'a Mutex<Vec<Remark>>> {
+        self.remarks
+    }
+
+    /// Set the engine executing the entry block of a `Recipe`
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    /// Return the engine executing the entry block of a `Recipe`
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Return `true` if only the interpreter can honor the options: worker
+    /// threads, speculation, tracing, schedules and remarks are not supported
+    /// by the bytecode virtual machine
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::executor::FryOptions;
+    ///
+    /// let mut options = FryOptions::new();
+    ///
+    /// assert!(!options.requires_interpreter());
+    ///
+    /// options.set_speculative(true);
+    ///
+    /// assert!(options.requires_interpreter());
+    /// ```
+    pub fn requires_interpreter(&self) -> bool {
+        self.threads > 1
+            || self.speculative
+            || self.tracer.is_some()
+            || self.schedule.is_some()
+            || self.remarks.is_some()
+    }
+}
+
+impl Default for FryOptions<'_> {
+    fn default() -> Self {
+        FryOptions::new()
+    }
+}
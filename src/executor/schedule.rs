@@ -0,0 +1,288 @@
+//! A `Schedule` records the scheduling decisions of a parallel run: which
+//! statements were executed on a worker thread, and in which order the
+//! `Critical` blocks entered their critical section. Read back from a file,
+//! it makes another run take the same decisions.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, Write};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Delay between two checks of the cancellation while waiting for a turn
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Scheduling decision taken during a run
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    /// A statement was executed on a worker thread
+    Spawn(String),
+
+    /// A statement was executed on the thread running its function
+    Inline(String),
+
+    /// A `Critical` block entered its critical section
+    Lock(String),
+}
+
+/// Decisions left to replay
+#[derive(Debug, Default)]
+struct Replay {
+    /// Spawn decisions of each statement, in the order they were taken
+    decisions: HashMap<String, VecDeque<bool>>,
+
+    /// Labels of the critical blocks, in the order they got the lock
+    locks: Vec<String>,
+
+    /// Index of the next critical block allowed to get the lock
+    next_lock: usize,
+}
+
+impl Replay {
+    /// Return `true` if the critical block may get the lock now: it is its
+    /// turn, or it is not part of the remaining lock order
+    fn is_turn(&self, label: &str) -> bool {
+        match self.locks.get(self.next_lock) {
+            Some(next) if next != label => !self.locks[self.next_lock..].iter().any(|l| l == label),
+            _ => true,
+        }
+    }
+}
+
+/// Scheduling decisions of a run, recorded or replayed by an `Executor`
+#[derive(Debug)]
+pub struct Schedule {
+    events: Mutex<Vec<Event>>,
+
+    /// Decisions enforced on the run, if the schedule was read from a file
+    replay: Option<Mutex<Replay>>,
+
+    /// Signaled when the lock order advances
+    turn: Condvar,
+}
+
+impl Schedule {
+    /// Create a new, empty Schedule recording the decisions of the runs
+    /// using it
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{BasicBlock, Boolean, Critical, Function};
+    /// use stir::executor::{Executor, FryOptions, Schedule};
+    ///
+    /// let b = Boolean::new(true);
+    /// let crit = Critical::new(&b);
+    /// let stmts: Vec<&dyn BasicBlock> = vec![&crit];
+    /// let func = Function::new(None, &stmts);
+    ///
+    /// let schedule = Schedule::new();
+    /// let mut options = FryOptions::new();
+    /// options.set_threads(2);
+    /// options.set_schedule(&schedule);
+    ///
+    /// Executor::with_options(options).run(&func).unwrap();
+    ///
+    /// let mut out = Vec::new();
+    /// schedule.write(&mut out).unwrap();
+    ///
+    /// assert_eq!(
+    ///     String::from_utf8(out).unwrap(),
+    ///     format!("inline {}\nlock {}\n", crit.label(), crit.label())
+    /// );
+    /// ```
+    pub fn new() -> Schedule {
+        Schedule {
+            events: Mutex::new(Vec::new()),
+            replay: None,
+            turn: Condvar::new(),
+        }
+    }
+
+    /// Read a schedule written by `Schedule::write`. The runs using it take
+    /// the decisions it contains instead of recording theirs
+    pub fn read<R: BufRead>(input: R) -> io::Result<Schedule> {
+        let mut events = Vec::new();
+        let mut replay = Replay::default();
+
+        for line in input.lines() {
+            let line = line?;
+
+            let event = match line.split_once(' ') {
+                Some(("spawn", label)) => Event::Spawn(String::from(label)),
+                Some(("inline", label)) => Event::Inline(String::from(label)),
+                Some(("lock", label)) => Event::Lock(String::from(label)),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid schedule event: {}", line),
+                    ))
+                }
+            };
+
+            match &event {
+                Event::Spawn(label) | Event::Inline(label) => replay
+                    .decisions
+                    .entry(label.clone())
+                    .or_default()
+                    .push_back(matches!(event, Event::Spawn(_))),
+                Event::Lock(label) => replay.locks.push(label.clone()),
+            }
+
+            events.push(event);
+        }
+
+        Ok(Schedule {
+            events: Mutex::new(events),
+            replay: Some(Mutex::new(replay)),
+            turn: Condvar::new(),
+        })
+    }
+
+    /// Return `true` if the schedule enforces its decisions instead of
+    /// recording new ones
+    pub fn is_replay(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Return the number of decisions recorded or read
+    pub fn len(&self) -> usize {
+        lock(&self.events).len()
+    }
+
+    /// Return `true` if the schedule does not contain any decision
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the decisions, one per line, in the order they were taken
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        for event in lock(&self.events).iter() {
+            match event {
+                Event::Spawn(label) => writeln!(out, "spawn {}", label)?,
+                Event::Inline(label) => writeln!(out, "inline {}", label)?,
+                Event::Lock(label) => writeln!(out, "lock {}", label)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the next recorded decision for a statement: `true` if it must
+    /// be executed on a worker thread. `None` when recording, or when the run
+    /// diverged from the schedule
+    pub(crate) fn replayed_spawn(&self, label: &str) -> Option<bool> {
+        lock(self.replay.as_ref()?)
+            .decisions
+            .get_mut(label)?
+            .pop_front()
+    }
+
+    /// Record whether a statement was executed on a worker thread
+    pub(crate) fn record_spawn(&self, label: &str, spawned: bool) {
+        if self.is_replay() {
+            return;
+        }
+
+        let label = String::from(label);
+        lock(&self.events).push(match spawned {
+            true => Event::Spawn(label),
+            false => Event::Inline(label),
+        });
+    }
+
+    /// Block until it is the turn of a critical block to get the lock, or
+    /// until `stopped` returns `true`. Does not block when recording
+    pub(crate) fn wait_turn(&self, label: &str, stopped: impl Fn() -> bool) {
+        let replay = match &self.replay {
+            Some(replay) => replay,
+            None => return,
+        };
+
+        let mut state = lock(replay);
+        while !state.is_turn(label) && !stopped() {
+            state = self
+                .turn
+                .wait_timeout(state, POLL_INTERVAL)
+                .map(|(state, _)| state)
+                .unwrap_or_else(|e| e.into_inner().0);
+        }
+    }
+
+    /// Record that a critical block got the lock, or let the next one in
+    /// the replayed order take its turn
+    pub(crate) fn locked(&self, label: &str) {
+        let replay = match &self.replay {
+            Some(replay) => replay,
+            None => {
+                lock(&self.events).push(Event::Lock(String::from(label)));
+                return;
+            }
+        };
+
+        let mut state = lock(replay);
+        if state
+            .locks
+            .get(state.next_lock)
+            .is_some_and(|next| next == label)
+        {
+            state.next_lock += 1;
+            self.turn.notify_all();
+        }
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule::new()
+    }
+}
+
+/// Lock a mutex, ignoring poisoning: the data it protects stays consistent
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_write() {
+        let text = "spawn __loop_1\ninline __bool_2\nlock __critical_3\nspawn __loop_1\n";
+        let schedule = Schedule::read(text.as_bytes()).unwrap();
+
+        assert!(schedule.is_replay());
+        assert_eq!(schedule.len(), 4);
+
+        let mut out = Vec::new();
+        schedule.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), text);
+
+        assert_eq!(schedule.replayed_spawn("__loop_1"), Some(true));
+        assert_eq!(schedule.replayed_spawn("__bool_2"), Some(false));
+        assert_eq!(schedule.replayed_spawn("__loop_1"), Some(true));
+        assert_eq!(schedule.replayed_spawn("__loop_1"), None);
+    }
+
+    #[test]
+    fn invalid_event() {
+        let err = Schedule::read("fork __loop_1\n".as_bytes()).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn lock_turns() {
+        let schedule = Schedule::read("lock a\nlock b\n".as_bytes()).unwrap();
+        let replay = schedule.replay.as_ref().unwrap();
+
+        assert!(!lock(replay).is_turn("b"));
+        assert!(lock(replay).is_turn("a"));
+
+        // Blocks which are not part of the order do not wait
+        assert!(lock(replay).is_turn("c"));
+
+        schedule.locked("a");
+        assert!(lock(replay).is_turn("b"));
+    }
+}
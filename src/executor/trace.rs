@@ -0,0 +1,234 @@
+//! A `Tracer` records when each block is entered and exited, and on which
+//! thread. The timeline can be exported to the Chrome trace-event format and
+//! opened in `chrome://tracing` or Perfetto.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+/// Kind of a trace event, using the Chrome trace-event phases
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Enter,
+    Exit,
+}
+
+#[derive(Debug)]
+struct Event {
+    label: String,
+    phase: Phase,
+
+    /// Nanoseconds elapsed since the creation of the tracer
+    timestamp: u128,
+
+    /// Index of the thread, in order of first appearance in the trace
+    thread: usize,
+}
+
+/// Records the execution timeline of the blocks run by an `Executor`
+#[derive(Debug)]
+pub struct Tracer {
+    start: Instant,
+    events: Mutex<Vec<Event>>,
+    threads: Mutex<HashMap<ThreadId, usize>>,
+}
+
+impl Tracer {
+    /// Create a new, empty Tracer. Timestamps are relative to its creation
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{Boolean, IfElse};
+    /// use stir::executor::{Executor, FryOptions, Tracer};
+    ///
+    /// let c = Boolean::new(true);
+    /// let t = Boolean::new(false);
+    /// let ie = IfElse::new(&c, &t, None);
+    ///
+    /// let tracer = Tracer::new();
+    /// let mut options = FryOptions::new();
+    /// options.set_tracer(&tracer);
+    ///
+    /// Executor::with_options(options).run(&ie).unwrap();
+    ///
+    /// let mut json = Vec::new();
+    /// tracer.write_chrome_trace(&mut json).unwrap();
+    /// ```
+    pub fn new() -> Tracer {
+        Tracer {
+            start: Instant::now(),
+            events: Mutex::new(Vec::new()),
+            threads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record that the current thread started executing a block
+    pub fn enter(&self, label: &str) {
+        self.record(label, Phase::Enter);
+    }
+
+    /// Record that the current thread is done executing a block
+    pub fn exit(&self, label: &str) {
+        self.record(label, Phase::Exit);
+    }
+
+    /// Return the number of events recorded so far
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    /// Return `true` if no event was recorded
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the recorded events as Chrome trace-event JSON
+    pub fn write_chrome_trace<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let events = self.events.lock().unwrap();
+
+        writeln!(out, "{{\"traceEvents\":[")?;
+
+        for (idx, event) in events.iter().enumerate() {
+            let phase = match event.phase {
+                Phase::Enter => 'B',
+                Phase::Exit => 'E',
+            };
+
+            write!(
+                out,
+                "{{\"name\":\"{}\",\"cat\":\"stir\",\"ph\":\"{}\",\"ts\":{}.{:03},\"pid\":1,\"tid\":{}}}",
+                escape(&event.label),
+                phase,
+                event.timestamp / 1000,
+                event.timestamp % 1000,
+                event.thread
+            )?;
+
+            if idx + 1 != events.len() {
+                write!(out, ",")?;
+            }
+            writeln!(out)?;
+        }
+
+        writeln!(out, "],\"displayTimeUnit\":\"ns\"}}")
+    }
+
+    fn record(&self, label: &str, phase: Phase) {
+        let timestamp = self.start.elapsed().as_nanos();
+
+        let thread = {
+            let mut threads = self.threads.lock().unwrap();
+            let next = threads.len();
+
+            *threads.entry(thread::current().id()).or_insert(next)
+        };
+
+        self.events.lock().unwrap().push(Event {
+            label: String::from(label),
+            phase,
+            timestamp,
+            thread,
+        });
+    }
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Tracer::new()
+    }
+}
+
+/// Escape a string to be used inside a JSON string literal
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{BasicBlock, Boolean, Function, IfElse};
+    use crate::executor::{Executor, FryOptions};
+
+    #[test]
+    fn records_nested_blocks() {
+        let c = Boolean::new(false);
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+        let ie = IfElse::new(&c, &t, Some(&f));
+
+        let tracer = Tracer::new();
+        let mut options = FryOptions::new();
+        options.set_tracer(&tracer);
+
+        Executor::with_options(options).run(&ie).unwrap();
+
+        let events = tracer.events.lock().unwrap();
+        let timeline: Vec<(&str, Phase)> =
+            events.iter().map(|e| (e.label.as_str(), e.phase)).collect();
+
+        assert_eq!(
+            timeline,
+            vec![
+                (ie.label().as_str(), Phase::Enter),
+                (c.label().as_str(), Phase::Enter),
+                (c.label().as_str(), Phase::Exit),
+                (f.label().as_str(), Phase::Enter),
+                (f.label().as_str(), Phase::Exit),
+                (ie.label().as_str(), Phase::Exit),
+            ]
+        );
+        assert!(events.iter().all(|e| e.thread == 0));
+        assert!(events.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+    }
+
+    #[test]
+    fn chrome_trace_layout() {
+        let b = Boolean::new(true);
+        let stmts: Vec<&dyn BasicBlock> = vec![&b];
+        let func = Function::new(None, &stmts);
+
+        let tracer = Tracer::new();
+        let mut options = FryOptions::new();
+        options.set_tracer(&tracer);
+
+        Executor::with_options(options).run(&func).unwrap();
+
+        let mut out = Vec::new();
+        tracer.write_chrome_trace(&mut out).unwrap();
+        let json = String::from_utf8(out).unwrap();
+
+        assert_eq!(tracer.len(), 4);
+        assert!(json.starts_with("{\"traceEvents\":["));
+        assert!(json.contains(&format!(
+            "\"name\":\"{}\",\"cat\":\"stir\",\"ph\":\"B\"",
+            func.label()
+        )));
+        assert!(json.contains(&format!(
+            "\"name\":\"{}\",\"cat\":\"stir\",\"ph\":\"E\"",
+            b.label()
+        )));
+        assert_eq!(json.matches("\"tid\":0").count(), 4);
+        assert!(json.trim_end().ends_with("}"));
+    }
+
+    #[test]
+    fn escape_json() {
+        assert_eq!(escape("__bool_1"), "__bool_1");
+        assert_eq!(escape("a\"b\\c\n"), "a\\\"b\\\\c\\u000a");
+    }
+}
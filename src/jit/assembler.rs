@@ -0,0 +1,270 @@
+//! Minimal x86-64 assembler, encoding the few instructions used by the JIT
+
+/// Condition of a conditional jump or set. `Zero` and `NotZero` also test
+/// the equality of the operands of a comparison
+#[derive(Clone, Copy)]
+pub enum Cond {
+    Zero,
+    NotZero,
+    Less,
+    GreaterEqual,
+    LessEqual,
+    Greater,
+}
+
+impl Cond {
+    /// Condition code, added to the base opcode of `jcc` and `setcc`
+    fn code(self) -> u8 {
+        match self {
+            Cond::Zero => 0x4,
+            Cond::NotZero => 0x5,
+            Cond::Less => 0xc,
+            Cond::GreaterEqual => 0xd,
+            Cond::LessEqual => 0xe,
+            Cond::Greater => 0xf,
+        }
+    }
+}
+
+/// Position in the code, bound once its address is known
+#[derive(Clone, Copy)]
+pub struct Label(usize);
+
+/// Encodes instructions into a buffer. Jumps to labels are patched once all
+/// the code has been generated
+pub struct Assembler {
+    code: Vec<u8>,
+
+    /// Offset of each label, once bound
+    labels: Vec<Option<usize>>,
+
+    /// Offset of the 32-bit displacement of each jump, and its target
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler {
+            code: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    /// Return the number of bytes generated so far
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Create a new unbound label
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Bind a label to the current position
+    pub fn bind(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    /// Patch the jumps and return the code
+    pub fn finish(mut self) -> Vec<u8> {
+        for (at, label) in self.fixups.iter() {
+            let target = self.labels[label.0].expect("jump to an unbound label");
+            let rel = target as i64 - (*at as i64 + 4);
+
+            self.code[*at..*at + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+        }
+
+        self.code
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn imm32(&mut self, imm: i32) {
+        self.bytes(&imm.to_le_bytes());
+    }
+
+    /// Emit an instruction addressing `[rbp + disp]` as a 64-bit operand
+    fn rbp_op(&mut self, opcode: u8, reg: u8, disp: i32) {
+        // REX.W, opcode, ModRM with mod = 10 (disp32) and rm = rbp
+        self.bytes(&[0x48, opcode, 0x80 | (reg << 3) | 0x05]);
+        self.imm32(disp);
+    }
+
+    fn rel32(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.imm32(0);
+    }
+
+    /// `push rbp; mov rbp, rsp; push rbx; sub rsp, frame; mov rbx, rdi`
+    pub fn prologue(&mut self, frame: i32) {
+        self.bytes(&[0x55, 0x48, 0x89, 0xe5, 0x53, 0x48, 0x81, 0xec]);
+        self.imm32(frame);
+        self.bytes(&[0x48, 0x89, 0xfb]);
+    }
+
+    /// `lea rsp, [rbp - 8]; pop rbx; pop rbp; ret`
+    pub fn epilogue(&mut self) {
+        self.bytes(&[0x48, 0x8d, 0x65, 0xf8, 0x5b, 0x5d, 0xc3]);
+    }
+
+    /// `mov eax, imm`
+    pub fn mov_eax_imm(&mut self, imm: i32) {
+        self.bytes(&[0xb8]);
+        self.imm32(imm);
+    }
+
+    /// `mov rax, imm`
+    pub fn mov_rax_imm(&mut self, imm: i64) {
+        self.bytes(&[0x48, 0xb8]);
+        self.bytes(&imm.to_le_bytes());
+    }
+
+    /// `test eax, eax`
+    pub fn test_eax(&mut self) {
+        self.bytes(&[0x85, 0xc0]);
+    }
+
+    /// `test al, al`
+    pub fn test_al(&mut self) {
+        self.bytes(&[0x84, 0xc0]);
+    }
+
+    /// `mov [rbp + disp], rax`
+    pub fn store_rax(&mut self, disp: i32) {
+        self.rbp_op(0x89, 0, disp);
+    }
+
+    /// `mov rax, [rbp + disp]`
+    pub fn load_rax(&mut self, disp: i32) {
+        self.rbp_op(0x8b, 0, disp);
+    }
+
+    /// `cmp rax, [rbp + disp]`
+    pub fn cmp_rax(&mut self, disp: i32) {
+        self.rbp_op(0x3b, 0, disp);
+    }
+
+    /// `and [rbp + disp], rax`
+    pub fn and_rax(&mut self, disp: i32) {
+        self.rbp_op(0x21, 0, disp);
+    }
+
+    /// `mov qword [rbp + disp], imm`
+    pub fn store_imm(&mut self, disp: i32, imm: i32) {
+        self.rbp_op(0xc7, 0, disp);
+        self.imm32(imm);
+    }
+
+    /// `inc qword [rbp + disp]`
+    pub fn inc(&mut self, disp: i32) {
+        self.rbp_op(0xff, 0, disp);
+    }
+
+    /// `mov rcx, rax`
+    pub fn mov_rcx_rax(&mut self) {
+        self.bytes(&[0x48, 0x89, 0xc1]);
+    }
+
+    /// `add rax, rcx`
+    pub fn add_rax_rcx(&mut self) {
+        self.bytes(&[0x48, 0x01, 0xc8]);
+    }
+
+    /// `sub rax, rcx`
+    pub fn sub_rax_rcx(&mut self) {
+        self.bytes(&[0x48, 0x29, 0xc8]);
+    }
+
+    /// `imul rax, rcx`
+    pub fn imul_rax_rcx(&mut self) {
+        self.bytes(&[0x48, 0x0f, 0xaf, 0xc1]);
+    }
+
+    /// `cmp rax, rcx`
+    pub fn cmp_rax_rcx(&mut self) {
+        self.bytes(&[0x48, 0x39, 0xc8]);
+    }
+
+    /// `set<cond> al; movzx eax, al`
+    pub fn set_eax(&mut self, cond: Cond) {
+        self.bytes(&[0x0f, 0x90 | cond.code(), 0xc0, 0x0f, 0xb6, 0xc0]);
+    }
+
+    /// `mov rdi, rbx; mov rax, function; call rax`
+    pub fn call_with_rbx(&mut self, function: usize) {
+        self.bytes(&[0x48, 0x89, 0xdf]);
+        self.mov_rax_imm(function as i64);
+        self.bytes(&[0xff, 0xd0]);
+    }
+
+    /// `jmp label`
+    pub fn jmp(&mut self, label: Label) {
+        self.bytes(&[0xe9]);
+        self.rel32(label);
+    }
+
+    /// `j<cond> label`
+    pub fn jcc(&mut self, cond: Cond, label: Label) {
+        self.bytes(&[0x0f, 0x80 | cond.code()]);
+        self.rel32(label);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let mut asm = Assembler::new();
+
+        asm.load_rax(-16);
+        asm.inc(-24);
+        asm.store_imm(-32, 1);
+
+        let mut expected = Vec::new();
+        // mov rax, [rbp - 16]
+        expected.extend_from_slice(&[0x48, 0x8b, 0x85, 0xf0, 0xff, 0xff, 0xff]);
+        // inc qword [rbp - 24]
+        expected.extend_from_slice(&[0x48, 0xff, 0x85, 0xe8, 0xff, 0xff, 0xff]);
+        // mov qword [rbp - 32], 1
+        expected.extend_from_slice(&[0x48, 0xc7, 0x85, 0xe0, 0xff, 0xff, 0xff, 1, 0, 0, 0]);
+
+        assert_eq!(asm.finish(), expected);
+    }
+
+    #[test]
+    fn integers() {
+        let mut asm = Assembler::new();
+
+        asm.imul_rax_rcx();
+        asm.cmp_rax_rcx();
+        asm.set_eax(Cond::LessEqual);
+
+        assert_eq!(
+            asm.finish(),
+            [0x48, 0x0f, 0xaf, 0xc1, 0x48, 0x39, 0xc8, 0x0f, 0x9e, 0xc0, 0x0f, 0xb6, 0xc0]
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        let mut asm = Assembler::new();
+        let back = asm.label();
+        let forward = asm.label();
+
+        asm.bind(back);
+        asm.jcc(Cond::Zero, forward);
+        asm.jmp(back);
+        asm.bind(forward);
+
+        assert_eq!(
+            asm.finish(),
+            [0x0f, 0x84, 5, 0, 0, 0, 0xe9, 0xf5, 0xff, 0xff, 0xff]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Executable memory, allocated using `mmap`
+
+use std::ffi::c_void;
+use std::ptr;
+
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const PROT_EXEC: i32 = 0x4;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, off: i64)
+        -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+/// Read-only and executable copy of some machine code
+pub struct ExecutableBuffer {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl ExecutableBuffer {
+    /// Copy the code to a new mapping, then make it executable. Returns
+    /// `None` if the mapping cannot be created
+    pub fn new(code: &[u8]) -> Option<ExecutableBuffer> {
+        let len = code.len().max(1);
+
+        // SAFETY: Anonymous private mapping, not aliased by anything else
+        let ptr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+
+        // MAP_FAILED
+        if ptr as isize == -1 {
+            return None;
+        }
+
+        let buffer = ExecutableBuffer { ptr, len };
+
+        // SAFETY: The mapping is writable and at least `code.len()` bytes long
+        unsafe {
+            ptr::copy_nonoverlapping(code.as_ptr(), ptr as *mut u8, code.len());
+
+            if mprotect(ptr, len, PROT_READ | PROT_EXEC) != 0 {
+                return None;
+            }
+        }
+
+        Some(buffer)
+    }
+
+    /// Return the address of the first instruction
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr as *const u8
+    }
+}
+
+impl Drop for ExecutableBuffer {
+    fn drop(&mut self) {
+        // SAFETY: The mapping was created by `mmap` with the same length
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn execute() {
+        // mov eax, 42; ret
+        let buffer = ExecutableBuffer::new(&[0xb8, 42, 0, 0, 0, 0xc3]).unwrap();
+
+        // SAFETY: The buffer contains a complete function
+        let f: extern "C" fn() -> u32 = unsafe { std::mem::transmute(buffer.as_ptr()) };
+
+        assert_eq!(f(), 42);
+    }
+}
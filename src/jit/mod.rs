@@ -0,0 +1,575 @@
+//! The JIT compiles hot `Function` blocks to x86-64 machine code. It is used
+//! by the virtual machine when running with `Engine::Jit`: once a function
+//! has been called often enough, it gets compiled and its next calls execute
+//! native code.
+//!
+//! Compiled functions support primitives, `IfElse`, finite `Loop` blocks,
+//! `Function` and `Call` blocks, as well as integer arithmetic and
+//! comparisons. Callees are compiled inline. Functions containing any other
+//! block, such as `Critical` blocks, infinite loops or blocks which are not
+//! part of `stir`, keep running on the virtual machine.
+//!
+//! Native code checks for cancellation at every loop iteration and function
+//! call, but does not record its execution in a `Tracer`.
+
+mod assembler;
+mod memory;
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+
+use assembler::{Assembler, Cond, Label};
+use memory::ExecutableBuffer;
+
+use crate::blocks::{ArithOp, BasicBlock, BlockKind, CompareOp, Function, Loop, Primitive};
+use crate::executor::{ExecResult, Executor, InterpreterError};
+use crate::vm::DEFAULT_JIT_THRESHOLD;
+
+/// Native code returns this value when the run got cancelled
+const CANCELLED: u32 = 2;
+
+/// Maximum size of the code of a compiled function. Callees are compiled
+/// inline, so functions calling other functions many times can grow large
+const MAX_CODE_SIZE: usize = 1 << 20;
+
+/// Function compiled to native code
+pub struct JitFunction {
+    buffer: ExecutableBuffer,
+}
+
+impl JitFunction {
+    /// Compile a function. Returns `None` if the function contains blocks the
+    /// JIT does not support
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{BasicBlock, Boolean, Critical, Function};
+    /// use stir::executor::Executor;
+    /// use stir::jit::JitFunction;
+    ///
+    /// let b = Boolean::new(true);
+    /// let crit = Critical::new(&b);
+    ///
+    /// let stmts: Vec<&dyn BasicBlock> = vec![&b];
+    /// let mut f = Function::new(None, &stmts);
+    /// f.set_retval(&b);
+    ///
+    /// let native = JitFunction::compile(&f).unwrap();
+    /// assert_eq!(native.call(&Executor::new()), Ok(true));
+    ///
+    /// let unsupported: Vec<&dyn BasicBlock> = vec![&crit];
+    /// assert!(JitFunction::compile(&Function::new(None, &unsupported)).is_none());
+    /// ```
+    pub fn compile(f: &Function) -> Option<JitFunction> {
+        let mut codegen = Codegen::new();
+        let exit = codegen.asm.label();
+        let cancelled = codegen.asm.label();
+
+        // The size of the frame is only known once the body is generated
+        codegen.asm.prologue(0);
+        let frame_at = codegen.asm.len() - 7;
+
+        codegen.function(f, cancelled)?;
+        codegen.asm.jmp(exit);
+
+        codegen.asm.bind(cancelled);
+        codegen.asm.mov_eax_imm(CANCELLED as i32);
+
+        codegen.asm.bind(exit);
+        codegen.asm.epilogue();
+
+        if codegen.asm.len() > MAX_CODE_SIZE {
+            return None;
+        }
+
+        let frame = codegen.frame();
+        let mut code = codegen.asm.finish();
+        code[frame_at..frame_at + 4].copy_from_slice(&frame.to_le_bytes());
+
+        Some(JitFunction {
+            buffer: ExecutableBuffer::new(&code)?,
+        })
+    }
+
+    /// Execute the native code
+    pub fn call(&self, exec: &Executor) -> ExecResult {
+        // SAFETY: The buffer contains a complete function generated by
+        // `compile`, following the System V calling convention
+        let f: extern "C" fn(*const c_void) -> u32 =
+            unsafe { std::mem::transmute(self.buffer.as_ptr()) };
+
+        match f(exec as *const Executor as *const c_void) {
+            CANCELLED => Err(InterpreterError::Cancelled),
+            value => Ok(value != 0),
+        }
+    }
+}
+
+/// Called from native code. Returns `1` if the run was cancelled
+extern "C" fn check_cancelled(exec: *const c_void) -> u8 {
+    // SAFETY: Native code is only called from `JitFunction::call`, which
+    // passes a valid `Executor` that outlives the call
+    let exec = unsafe { &*(exec as *const Executor) };
+
+    exec.check_cancelled().is_err() as u8
+}
+
+/// Generates the native code of a function. The value of each block is
+/// computed in `eax`, the executor is kept in `rbx`, and loop variables are
+/// stored in the frame
+struct Codegen {
+    asm: Assembler,
+
+    /// Number of 8-byte slots used in the frame
+    slots: i32,
+}
+
+impl Codegen {
+    fn new() -> Codegen {
+        Codegen {
+            asm: Assembler::new(),
+            slots: 0,
+        }
+    }
+
+    /// Reserve a slot in the frame and return its offset from `rbp`. The
+    /// first slot below `rbp` holds the saved `rbx`
+    fn slot(&mut self) -> i32 {
+        self.slots += 1;
+        -8 * (self.slots + 1)
+    }
+
+    /// Size of the frame, keeping the stack aligned on 16 bytes at calls
+    fn frame(&self) -> i32 {
+        // The return address, `rbp` and `rbx` take 24 bytes
+        let size = 8 * self.slots;
+        size + (16 - (24 + size) % 16) % 16
+    }
+
+    fn check(&mut self, cancelled: Label) {
+        self.asm
+            .call_with_rbx(check_cancelled as *const () as usize);
+        self.asm.test_al();
+        self.asm.jcc(Cond::NotZero, cancelled);
+    }
+
+    fn expr(&mut self, block: &dyn BasicBlock, cancelled: Label) -> Option<()> {
+        // Stop early instead of generating huge functions
+        if self.asm.len() > MAX_CODE_SIZE {
+            return None;
+        }
+
+        match block.kind() {
+            kind if kind.is_primitive() => self.asm.mov_eax_imm(block.interpret() as i32),
+            BlockKind::IfElse(ie) => {
+                let f_branch = self.asm.label();
+                let end = self.asm.label();
+
+                self.expr(ie.cond_block(), cancelled)?;
+                self.asm.test_eax();
+                self.asm.jcc(Cond::Zero, f_branch);
+
+                self.expr(ie.t_block(), cancelled)?;
+                self.asm.jmp(end);
+
+                self.asm.bind(f_branch);
+                match ie.f_block() {
+                    Some(f_block) => self.expr(f_block, cancelled)?,
+                    None => self.asm.mov_eax_imm(0),
+                }
+
+                self.asm.bind(end);
+            }
+            BlockKind::Loop(l) => self.r#loop(l, cancelled)?,
+            BlockKind::Function(f) => self.function(f, cancelled)?,
+            BlockKind::Call(c) => {
+                self.check(cancelled);
+                self.function(c.function(), cancelled)?;
+            }
+            BlockKind::Arithmetic(_) => {
+                // The result is only used as an integer: the block is `true`
+                self.integer(block, cancelled)?;
+                self.asm.mov_eax_imm(1);
+            }
+            BlockKind::Compare(c) => {
+                self.operands(c.lhs(), c.rhs(), cancelled)?;
+                self.asm.cmp_rax_rcx();
+                self.asm.set_eax(match c.op() {
+                    CompareOp::Eq => Cond::Zero,
+                    CompareOp::Ne => Cond::NotZero,
+                    CompareOp::Lt => Cond::Less,
+                    CompareOp::Le => Cond::LessEqual,
+                    CompareOp::Gt => Cond::Greater,
+                    CompareOp::Ge => Cond::GreaterEqual,
+                });
+            }
+            _ => return None,
+        }
+
+        Some(())
+    }
+
+    fn function(&mut self, f: &Function, cancelled: Label) -> Option<()> {
+        for stmt in f.stmts().iter() {
+            self.expr(*stmt, cancelled)?;
+        }
+
+        match f.retval() {
+            Some(retval) => self.expr(retval, cancelled),
+            None => {
+                self.asm.mov_eax_imm(0);
+                Some(())
+            }
+        }
+    }
+
+    /// Load the value of a loop bound or an operand in `rax`: `Number` and
+    /// `Arithmetic` blocks count as their value, other blocks as `1` if they
+    /// evaluate to `true`
+    fn integer(&mut self, block: &dyn BasicBlock, cancelled: Label) -> Option<()> {
+        match block.kind() {
+            BlockKind::Number(n) => {
+                self.asm.mov_rax_imm(n.get() as i64);
+                Some(())
+            }
+            BlockKind::Arithmetic(a) => {
+                self.operands(a.lhs(), a.rhs(), cancelled)?;
+                match a.op() {
+                    ArithOp::Add => self.asm.add_rax_rcx(),
+                    ArithOp::Sub => self.asm.sub_rax_rcx(),
+                    ArithOp::Mul => self.asm.imul_rax_rcx(),
+                }
+                Some(())
+            }
+            // Writing `eax` clears the upper half of `rax`
+            _ => self.expr(block, cancelled),
+        }
+    }
+
+    /// Load the integer values of two operands in `rax` and `rcx`. The left
+    /// one is kept in the frame meanwhile
+    fn operands(
+        &mut self,
+        lhs: &dyn BasicBlock,
+        rhs: &dyn BasicBlock,
+        cancelled: Label,
+    ) -> Option<()> {
+        let tmp = self.slot();
+
+        self.integer(lhs, cancelled)?;
+        self.asm.store_rax(tmp);
+        self.integer(rhs, cancelled)?;
+        self.asm.mov_rcx_rax();
+        self.asm.load_rax(tmp);
+
+        Some(())
+    }
+
+    fn r#loop(&mut self, l: &Loop, cancelled: Label) -> Option<()> {
+        // Infinite loops would only stop when cancelled: leave them to the
+        // virtual machine
+        let lo = l.lo_bound()?;
+        let hi = l.hi_bound()?;
+
+        let idx = self.slot();
+        let end = self.slot();
+        let acc = self.slot();
+
+        let head = self.asm.label();
+        let exit = self.asm.label();
+
+        self.integer(lo, cancelled)?;
+        self.asm.store_rax(idx);
+        self.integer(hi, cancelled)?;
+        self.asm.store_rax(end);
+        self.asm.store_imm(acc, 1);
+
+        self.asm.load_rax(idx);
+        self.asm.cmp_rax(end);
+        self.asm.jcc(Cond::GreaterEqual, exit);
+
+        self.asm.bind(head);
+        self.check(cancelled);
+
+        if let Some(body) = l.body() {
+            self.expr(body, cancelled)?;
+            self.asm.and_rax(acc);
+        }
+
+        self.asm.inc(idx);
+        self.asm.load_rax(idx);
+        self.asm.cmp_rax(end);
+        self.asm.jcc(Cond::Less, head);
+
+        self.asm.bind(exit);
+        self.asm.load_rax(acc);
+
+        Some(())
+    }
+}
+
+/// State of a function in the `Jit`
+enum Entry {
+    /// Number of calls so far
+    Cold(u64),
+    Compiled(JitFunction),
+    Unsupported,
+}
+
+/// Counts the calls of functions, and compiles them once they are hot
+pub struct Jit {
+    threshold: u64,
+    functions: HashMap<String, Entry>,
+}
+
+impl Jit {
+    /// Number of calls after which a function gets compiled by default
+    pub const DEFAULT_THRESHOLD: u64 = DEFAULT_JIT_THRESHOLD;
+
+    /// Create a new Jit compiling functions after `DEFAULT_THRESHOLD` calls
+    pub fn new() -> Jit {
+        Jit {
+            threshold: Jit::DEFAULT_THRESHOLD,
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Set the number of calls after which a function gets compiled
+    pub fn set_threshold(&mut self, threshold: u64) {
+        self.threshold = threshold;
+    }
+
+    /// Execute the native code of a function if it is compiled. Returns
+    /// `None` if the function must be executed by the caller
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{BasicBlock, Boolean, Function};
+    /// use stir::executor::Executor;
+    /// use stir::jit::Jit;
+    ///
+    /// let b = Boolean::new(true);
+    /// let stmts: Vec<&dyn BasicBlock> = vec![&b];
+    /// let f = Function::new(None, &stmts);
+    ///
+    /// let mut jit = Jit::new();
+    /// jit.set_threshold(1);
+    ///
+    /// let exec = Executor::new();
+    ///
+    /// assert_eq!(jit.call(&f, &exec), None);
+    /// assert_eq!(jit.call(&f, &exec), Some(Ok(false)));
+    /// assert!(jit.is_compiled(&f));
+    /// ```
+    pub fn call(&mut self, f: &Function, exec: &Executor) -> Option<ExecResult> {
+        let entry = self
+            .functions
+            .entry(f.label().clone())
+            .or_insert(Entry::Cold(0));
+
+        if let Entry::Cold(calls) = entry {
+            if *calls < self.threshold {
+                *calls += 1;
+                return None;
+            }
+
+            *entry = match JitFunction::compile(f) {
+                Some(native) => Entry::Compiled(native),
+                None => Entry::Unsupported,
+            };
+        }
+
+        match entry {
+            Entry::Compiled(native) => Some(native.call(exec)),
+            _ => None,
+        }
+    }
+
+    /// Return `true` if the function has been compiled to native code
+    pub fn is_compiled(&self, f: &Function) -> bool {
+        matches!(self.functions.get(f.label()), Some(Entry::Compiled(_)))
+    }
+}
+
+impl Default for Jit {
+    fn default() -> Self {
+        Jit::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use crate::blocks::{Arithmetic, Boolean, Call, Compare, Critical, IfElse, Number, Str};
+    use crate::executor::{CancellationToken, FryOptions};
+
+    fn compiled(stmts: &Vec<&dyn BasicBlock>, retval: &dyn BasicBlock) -> ExecResult {
+        let mut f = Function::new(None, stmts);
+        f.set_retval(retval);
+
+        JitFunction::compile(&f).unwrap().call(&Executor::new())
+    }
+
+    #[test]
+    fn same_as_interpreter() {
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+        let nan = Number::new(f64::NAN);
+        let s = Str::new(String::from("stir"));
+        let lo = Number::new(-3.0);
+        let hi = Number::new(4.0);
+
+        let ie = IfElse::new(&s, &nan, Some(&t));
+        let no_else = IfElse::new(&f, &t, None);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&ie));
+        let empty = Loop::new(Some(&hi), Some(&lo), Some(&f));
+        let block_bound = Loop::new(Some(&f), Some(&ie), Some(&no_else));
+        let nested = Loop::new(Some(&lo), Some(&hi), Some(&empty));
+
+        let stmts: Vec<&dyn BasicBlock> = vec![&l, &block_bound];
+        let mut callee = Function::new(None, &stmts);
+        callee.set_retval(&nested);
+        let call = Call::new(&callee, None);
+        let outer = IfElse::new(&call, &block_bound, Some(&t));
+
+        let blocks: Vec<&dyn BasicBlock> = vec![
+            &t,
+            &nan,
+            &ie,
+            &no_else,
+            &l,
+            &empty,
+            &block_bound,
+            &nested,
+            &callee,
+            &call,
+            &outer,
+        ];
+
+        for block in blocks {
+            let stmts: Vec<&dyn BasicBlock> = vec![&l, block];
+
+            assert_eq!(
+                compiled(&stmts, block),
+                Ok(block.interpret()),
+                "{}",
+                block.label()
+            );
+        }
+    }
+
+    #[test]
+    fn integers() {
+        let t = Boolean::new(true);
+        let lo = Number::new(-3.0);
+        let hi = Number::new(4.0);
+        let big = Number::new(i64::MAX as f64);
+
+        let diff = Arithmetic::new(ArithOp::Sub, &lo, &hi);
+        let product = Arithmetic::new(ArithOp::Mul, &diff, &diff);
+        let wrapped = Arithmetic::new(ArithOp::Add, &big, &big);
+        let sum = Arithmetic::new(ArithOp::Add, &product, &t);
+        let l = Loop::new(Some(&diff), Some(&sum), Some(&t));
+
+        let mut blocks: Vec<&dyn BasicBlock> = vec![&sum, &l];
+        let comparisons: Vec<Compare> = [
+            CompareOp::Eq,
+            CompareOp::Ne,
+            CompareOp::Lt,
+            CompareOp::Le,
+            CompareOp::Gt,
+            CompareOp::Ge,
+        ]
+        .iter()
+        .flat_map(|op| {
+            vec![
+                Compare::new(*op, &product, &sum),
+                Compare::new(*op, &diff, &lo),
+                Compare::new(*op, &wrapped, &t),
+            ]
+        })
+        .collect();
+        blocks.extend(comparisons.iter().map(|c| c as &dyn BasicBlock));
+
+        for block in blocks {
+            let stmts: Vec<&dyn BasicBlock> = vec![&l, block];
+
+            assert_eq!(
+                compiled(&stmts, block),
+                Ok(block.interpret()),
+                "{}",
+                block.output()
+            );
+        }
+    }
+
+    #[test]
+    fn no_retval() {
+        let t = Boolean::new(true);
+        let stmts: Vec<&dyn BasicBlock> = vec![&t];
+        let f = Function::new(None, &stmts);
+
+        assert_eq!(
+            JitFunction::compile(&f).unwrap().call(&Executor::new()),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let t = Boolean::new(true);
+        let crit = Critical::new(&t);
+        let infinite = Loop::new(None, None, Some(&t));
+
+        for block in [&crit as &dyn BasicBlock, &infinite] {
+            let stmts: Vec<&dyn BasicBlock> = vec![block];
+            let f = Function::new(None, &stmts);
+
+            assert!(JitFunction::compile(&f).is_none());
+
+            let mut jit = Jit::new();
+            jit.set_threshold(0);
+            assert_eq!(jit.call(&f, &Executor::new()), None);
+            assert!(!jit.is_compiled(&f));
+        }
+    }
+
+    #[test]
+    fn cancelled() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(1e15);
+        let t = Boolean::new(true);
+        let long = Loop::new(Some(&lo), Some(&hi), Some(&t));
+
+        let stmts: Vec<&dyn BasicBlock> = vec![&long];
+        let native = JitFunction::compile(&Function::new(None, &stmts)).unwrap();
+
+        let token = CancellationToken::new();
+        let mut options = FryOptions::new();
+        options.set_cancellation(token.clone());
+
+        let canceller = thread::spawn(move || token.cancel());
+
+        assert_eq!(
+            native.call(&Executor::with_options(options)),
+            Err(InterpreterError::Cancelled)
+        );
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn frame_alignment() {
+        let mut codegen = Codegen::new();
+
+        assert_eq!(codegen.frame(), 8);
+        codegen.slot();
+        assert_eq!(codegen.frame(), 8);
+        codegen.slot();
+        assert_eq!(codegen.frame(), 24);
+    }
+}
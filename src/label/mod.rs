@@ -1,7 +1,9 @@
 //! `Labels` are unique names associated with each block.
 
+use std::sync::atomic::{AtomicU64, Ordering};
+
 /// Last ID set by the Label framework
-static mut LAST_ID: u128 = 0;
+static LAST_ID: AtomicU64 = AtomicU64::new(0);
 
 /// Labels are a unique identifier attributed to a block. It represents this
 /// block and is unique.
@@ -34,14 +36,12 @@ impl Label {
     fn unique_identifier(prefix: &str) -> String {
         let mut unique = String::from("__");
         unique.push_str(prefix);
-        unique.push_str("_");
+        unique.push('_');
 
         // Get the last ID given and increment it. Then, append it to the
         // unique identifier
-        unsafe {
-            LAST_ID += 1;
-            unique.push_str(&LAST_ID.to_string());
-        }
+        let id = LAST_ID.fetch_add(1, Ordering::SeqCst) + 1;
+        unique.push_str(&id.to_string());
 
         unique
     }
@@ -5,7 +5,15 @@
 //! `stir` is organized in blocks. The smaller the block, the easier to
 //! multithread !
 
+pub mod analysis;
+pub mod backend;
 #[allow(dead_code)]
 pub mod blocks;
+pub mod cost;
+pub mod executor;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub mod jit;
 pub mod label;
+pub mod opt;
 pub mod recipe;
+pub mod vm;
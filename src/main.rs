@@ -1,6 +1,100 @@
+use std::env;
+use std::io;
+use std::process;
+
+use stir::backend::Registry;
 use stir::blocks::*;
+use stir::opt::{OptLevel, PassManager, Remark, RemarkKind};
+use stir::recipe::{Arena, Recipe};
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: stir-bin [-O<level>] [--passes <pass,...>] [--time-passes] [--remarks[=missed]] \
+         [--emit <backend>]"
+    );
+    process::exit(2);
+}
+
+/// Remarks printed after running the pipeline
+#[derive(Clone, Copy, PartialEq)]
+enum Remarks {
+    All,
+    Missed,
+}
+
+/// Command line options
+#[derive(Default)]
+struct Options {
+    level: Option<OptLevel>,
+    passes: Option<Vec<String>>,
+    time_passes: bool,
+    remarks: Option<Remarks>,
+    emit: Option<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Options {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--emit" => options.emit = Some(args.next().unwrap_or_else(|| usage())),
+                "--passes" => {
+                    let passes = args.next().unwrap_or_else(|| usage());
+                    options.passes = Some(passes.split(',').map(String::from).collect());
+                }
+                "--time-passes" => options.time_passes = true,
+                "--remarks" => options.remarks = Some(Remarks::All),
+                "--remarks=missed" => options.remarks = Some(Remarks::Missed),
+                level if level.starts_with("-O") => {
+                    options.level = Some(OptLevel::parse(level).unwrap_or_else(|| usage()))
+                }
+                _ => usage(),
+            }
+        }
+
+        // Remarks come from the pipeline and the backend
+        let reported =
+            options.level.is_some() || options.passes.is_some() || options.emit.is_some();
+        if options.remarks.is_some() && !reported {
+            usage();
+        }
+
+        options
+    }
+
+    /// Print the remarks selected by the options
+    fn print_remarks(&self, remarks: &[Remark]) {
+        if let Some(selected) = self.remarks {
+            for remark in remarks {
+                if selected == Remarks::All || remark.kind() == RemarkKind::Missed {
+                    eprintln!("{}", remark);
+                }
+            }
+        }
+    }
+
+    /// Return the pipeline selected by the options, if any
+    fn pass_manager(&self) -> Option<PassManager> {
+        if let Some(passes) = &self.passes {
+            let names: Vec<&str> = passes.iter().map(String::as_str).collect();
+
+            return match PassManager::with_names(&names) {
+                Ok(pm) => Some(pm),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(2);
+                }
+            };
+        }
+
+        self.level.map(PassManager::with_level)
+    }
+}
 
 fn main() {
+    let options = Options::parse(env::args().skip(1));
+
     let c = Boolean::new(true);
     let t = Boolean::new(true);
     let f = Boolean::new(false);
@@ -12,14 +106,57 @@ fn main() {
 
     let mega_l = Loop::new(None, Some(&b), Some(&ie));
 
-    let mut vec: Vec<&dyn BasicBlock> = Vec::new();
-    vec.push(&mega_l);
-    vec.push(&l);
-    vec.push(&b);
-    vec.push(&ie);
+    let vec: Vec<&dyn BasicBlock> = vec![&mega_l, &l, &b, &ie];
 
     let func = Function::new(None, &vec);
 
+    let arena = Arena::new();
+    let mut recipe = Recipe::new();
+    recipe.add_entry(&func);
+
+    let pass_manager = options.pass_manager();
+
+    if let Some(mut pm) = pass_manager {
+        if let Err(e) = pm.run(&mut recipe, &arena) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+
+        if options.time_passes {
+            for timing in pm.timings() {
+                eprintln!("{:>12?}  {}", timing.duration(), timing.name());
+            }
+        }
+
+        options.print_remarks(pm.remarks());
+    }
+
+    if let Some(name) = &options.emit {
+        let registry = Registry::new();
+
+        let backend = match registry.get(name) {
+            Some(backend) => backend,
+            None => {
+                eprintln!(
+                    "unknown backend {}, available: {}",
+                    name,
+                    registry.names().join(", ")
+                );
+                process::exit(2);
+            }
+        };
+
+        match backend.emit_with_remarks(&recipe, &mut io::stdout().lock()) {
+            Ok(remarks) => options.print_remarks(&remarks),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        return;
+    }
+
     dbg!(func);
     dbg!(l);
     dbg!(mega_l);
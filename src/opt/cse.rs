@@ -0,0 +1,281 @@
+//! Common subexpression elimination merges the structurally equal blocks of a
+//! function, so that they are represented by a single block.
+
+use std::collections::HashMap;
+
+use super::rewrite::{function, same, Rewriter};
+use super::{Pass, Remark};
+
+use crate::analysis::{is_pure, structural_eq, StructuralHasher};
+use crate::blocks::{BasicBlock, BlockKind};
+use crate::recipe::{Arena, Recipe};
+
+/// Blocks of a function, indexed by their structural hash
+struct Table<'block> {
+    hasher: StructuralHasher,
+    blocks: HashMap<u64, Vec<&'block dyn BasicBlock>>,
+}
+
+impl<'block> Table<'block> {
+    /// Return the block of the table structurally equal to `block`, adding
+    /// `block` to the table if there is none
+    fn canonical(&mut self, block: &'block dyn BasicBlock) -> &'block dyn BasicBlock {
+        let candidates = self.blocks.entry(self.hasher.hash(block)).or_default();
+
+        match candidates.iter().find(|c| structural_eq(**c, block)) {
+            Some(canonical) => *canonical,
+            None => {
+                candidates.push(block);
+                block
+            }
+        }
+    }
+}
+
+/// Replaces the subtrees of a function which are free of side effects by the
+/// first structurally equal subtree of the same function. Blocks are compared
+/// ignoring their labels, so two `Boolean::new(true)` are merged.
+///
+/// Functions called from a function are not part of it, but a function
+/// defined inside of another one is.
+///
+/// Subtrees are merged bottom-up: once the children of two blocks are merged,
+/// comparing the blocks does not need to go further down.
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{BasicBlock, BlockKind, Boolean, Function, IfElse};
+/// use stir::opt::{CommonSubexpressionElimination, Pass};
+/// use stir::recipe::{Arena, Recipe};
+///
+/// let c0 = Boolean::new(true);
+/// let t0 = Boolean::new(false);
+/// let c1 = Boolean::new(true);
+/// let t1 = Boolean::new(false);
+/// let ie0 = IfElse::new(&c0, &t0, None);
+/// let ie1 = IfElse::new(&c1, &t1, None);
+///
+/// let stmts: Vec<&dyn BasicBlock> = vec![&ie0];
+/// let mut func = Function::new(None, &stmts);
+/// func.set_retval(&ie1);
+///
+/// let arena = Arena::new();
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&func);
+///
+/// let mut cse = CommonSubexpressionElimination::new();
+///
+/// assert!(cse.run(&mut recipe, &arena));
+/// assert_eq!(cse.merged().len(), 3);
+///
+/// match recipe.entry().unwrap().kind() {
+///     BlockKind::Function(f) => assert_eq!(f.retval().unwrap().label(), ie0.label()),
+///     _ => unreachable!(),
+/// }
+/// ```
+// FIXME: Perf: Blocks shared by several parents are still evaluated once per
+// parent. Cache the value of shared pure blocks in the executors
+pub struct CommonSubexpressionElimination {
+    merged: Vec<String>,
+    remarks: Vec<Remark>,
+}
+
+impl CommonSubexpressionElimination {
+    /// Create a new CommonSubexpressionElimination pass
+    pub fn new() -> CommonSubexpressionElimination {
+        CommonSubexpressionElimination {
+            merged: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Return the labels of the blocks merged into a structurally equal block
+    /// during the last run
+    pub fn merged(&self) -> Vec<&str> {
+        self.merged.iter().map(String::as_str).collect()
+    }
+}
+
+impl Default for CommonSubexpressionElimination {
+    fn default() -> Self {
+        CommonSubexpressionElimination::new()
+    }
+}
+
+impl Pass for CommonSubexpressionElimination {
+    fn name(&self) -> &str {
+        "cse"
+    }
+
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+        let name = self.name().to_string();
+        let mut merged = Vec::new();
+        let mut remarks = Vec::new();
+
+        let mut f = |_, block: &'block dyn BasicBlock| -> &'block dyn BasicBlock {
+            let func = match block.kind() {
+                BlockKind::Function(func) => func,
+                _ => return block,
+            };
+
+            // Each function has its own table, so that blocks are only merged
+            // within a function
+            let mut table = Table {
+                hasher: StructuralHasher::new(),
+                blocks: HashMap::new(),
+            };
+            let mut g = |original: &'block dyn BasicBlock, block: &'block dyn BasicBlock| {
+                if !is_pure(block) {
+                    return block;
+                }
+
+                let canonical = table.canonical(block);
+                if !same(canonical, block) {
+                    merged.push(original.label().clone());
+                    remarks.push(Remark::applied(
+                        &name,
+                        original.label(),
+                        format!("structurally equal to {}", canonical.label()),
+                    ));
+                }
+
+                canonical
+            };
+
+            // Called functions are merged on their own
+            let mut rewriter = Rewriter::new(arena);
+            rewriter.set_calls(false);
+            let stmts: Vec<&dyn BasicBlock> = func
+                .stmts()
+                .iter()
+                .map(|stmt| rewriter.rewrite(*stmt, &mut g))
+                .collect();
+            let retval = func.retval().map(|retval| rewriter.rewrite(retval, &mut g));
+
+            let unchanged = stmts.iter().zip(func.stmts()).all(|(a, b)| same(*a, *b))
+                && match (retval, func.retval()) {
+                    (Some(a), Some(b)) => same(a, b),
+                    _ => true,
+                };
+
+            match unchanged {
+                true => block,
+                false => function(arena, func, stmts, retval),
+            }
+        };
+
+        let changed = Rewriter::new(arena).rewrite_recipe(recipe, &mut f);
+        self.merged = merged;
+        self.remarks = remarks;
+
+        changed
+    }
+
+    fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Call, Critical, Function, IfElse, Loop, Number};
+
+    #[test]
+    fn nested_subtrees() {
+        let arena = Arena::new();
+
+        let lo0 = Number::new(0.0);
+        let hi0 = Number::new(4.0);
+        let b0 = Boolean::new(true);
+        let ie0 = IfElse::new(&b0, &b0, None);
+        let l0 = Loop::new(Some(&lo0), Some(&hi0), Some(&ie0));
+
+        let lo1 = Number::new(0.0);
+        let hi1 = Number::new(4.0);
+        let b1 = Boolean::new(true);
+        let ie1 = IfElse::new(&b1, &b1, None);
+        let l1 = Loop::new(Some(&lo1), Some(&hi1), Some(&ie1));
+
+        let crit = Critical::new(&l1);
+        let stmts: Vec<&dyn BasicBlock> = vec![&l0, &crit];
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&l1);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+
+        let expected = recipe.fry();
+
+        let mut cse = CommonSubexpressionElimination::new();
+
+        assert!(cse.run(&mut recipe, &arena));
+        assert_eq!(recipe.fry(), expected);
+
+        // The loop is merged as a whole, once its children are merged
+        assert!(cse.merged().contains(&l1.label().as_str()));
+
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Function(f) => {
+                assert!(same(f.retval().unwrap(), &l0));
+
+                match f.stmts()[1].kind() {
+                    BlockKind::Critical(c) => assert!(same(c.block(), &l0)),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        // Merging again does not change anything
+        assert!(!cse.run(&mut recipe, &arena));
+    }
+
+    #[test]
+    fn impure_kept() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit0 = Critical::new(&b);
+        let crit1 = Critical::new(&b);
+        let stmts: Vec<&dyn BasicBlock> = vec![&crit0, &crit1];
+        let func = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+
+        let mut cse = CommonSubexpressionElimination::new();
+
+        assert!(!cse.run(&mut recipe, &arena));
+        assert!(cse.merged().is_empty());
+    }
+
+    #[test]
+    fn within_functions() {
+        let arena = Arena::new();
+
+        // Each function holds one of the equal blocks
+        let b0 = Boolean::new(true);
+        let b1 = Boolean::new(true);
+        let no_stmts: Vec<&dyn BasicBlock> = vec![];
+        let mut callee = Function::new(None, &no_stmts);
+        callee.set_retval(&b1);
+
+        let call = Call::new(&callee, None);
+        let ie = IfElse::new(&b0, &call, None);
+        let stmts: Vec<&dyn BasicBlock> = vec![&ie];
+        let caller = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add(&caller);
+        recipe.add(&b0);
+        recipe.add(&b1);
+
+        assert!(!CommonSubexpressionElimination::new().run(&mut recipe, &arena));
+
+        // Blocks outside of functions are not merged
+        assert_eq!(recipe.len(), 3);
+    }
+}
@@ -0,0 +1,255 @@
+//! Dead code elimination removes the blocks which cannot affect the result
+//! of a recipe.
+
+use std::collections::HashSet;
+
+use super::rewrite::{address, function, Rewriter};
+use super::{Pass, Remark};
+
+use crate::analysis::is_pure;
+use crate::blocks::{BasicBlock, BlockKind};
+use crate::recipe::{Arena, Recipe};
+
+/// Removes the statements of functions which are free of side effects, since
+/// their value is discarded, and the blocks of the recipe which cannot be
+/// reached from its entry
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{BasicBlock, Boolean, Critical, Function};
+/// use stir::opt::{DeadCodeElimination, Pass};
+/// use stir::recipe::{Arena, Recipe};
+///
+/// let dead = Boolean::new(false);
+/// let b = Boolean::new(true);
+/// let live = Critical::new(&b);
+/// let stmts: Vec<&dyn BasicBlock> = vec![&dead, &live];
+/// let func = Function::new(None, &stmts);
+///
+/// let unreachable = Boolean::new(true);
+///
+/// let arena = Arena::new();
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&func);
+/// recipe.add(&unreachable);
+///
+/// let mut dce = DeadCodeElimination::new();
+/// assert!(dce.run(&mut recipe, &arena));
+///
+/// assert_eq!(dce.removed_stmts(), [dead.label().as_str()]);
+/// assert_eq!(dce.pruned_blocks(), [unreachable.label().as_str()]);
+/// assert_eq!(recipe.len(), 1);
+/// ```
+pub struct DeadCodeElimination {
+    removed_stmts: Vec<String>,
+    pruned_blocks: Vec<String>,
+    remarks: Vec<Remark>,
+}
+
+impl DeadCodeElimination {
+    /// Create a new DeadCodeElimination pass
+    pub fn new() -> DeadCodeElimination {
+        DeadCodeElimination {
+            removed_stmts: Vec::new(),
+            pruned_blocks: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Return the labels of the statements removed during the last run
+    pub fn removed_stmts(&self) -> Vec<&str> {
+        self.removed_stmts.iter().map(String::as_str).collect()
+    }
+
+    /// Return the labels of the recipe blocks pruned during the last run
+    pub fn pruned_blocks(&self) -> Vec<&str> {
+        self.pruned_blocks.iter().map(String::as_str).collect()
+    }
+
+    /// Remove the blocks of the recipe unreachable from its entry
+    fn prune(&mut self, recipe: &mut Recipe) -> bool {
+        let entry = match recipe.entry() {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        let mut reachable = HashSet::new();
+        let mut worklist = vec![entry];
+
+        while let Some(block) = worklist.pop() {
+            if reachable.insert(address(block)) {
+                worklist.extend(block.kind().children());
+            }
+        }
+
+        for block in recipe.blocks() {
+            if !reachable.contains(&address(block)) {
+                recipe.remove(block.label());
+                self.pruned_blocks.push(block.label().clone());
+                self.remarks.push(Remark::applied(
+                    self.name(),
+                    block.label(),
+                    "unreachable from the entry of the recipe",
+                ));
+            }
+        }
+
+        !self.pruned_blocks.is_empty()
+    }
+}
+
+impl Default for DeadCodeElimination {
+    fn default() -> Self {
+        DeadCodeElimination::new()
+    }
+}
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &str {
+        "dce"
+    }
+
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+        self.removed_stmts.clear();
+        self.pruned_blocks.clear();
+        self.remarks.clear();
+
+        let mut removed = Vec::new();
+        // Statements shared by several functions are only reported once
+        let mut seen = HashSet::new();
+
+        let mut f = |_, block: &'block dyn BasicBlock| -> &'block dyn BasicBlock {
+            let func = match block.kind() {
+                BlockKind::Function(func) => func,
+                _ => return block,
+            };
+
+            let (dead, live): (Vec<&dyn BasicBlock>, Vec<&dyn BasicBlock>) =
+                func.stmts().iter().partition(|stmt| is_pure(**stmt));
+            if dead.is_empty() {
+                return block;
+            }
+
+            removed.extend(
+                dead.iter()
+                    .filter(|stmt| seen.insert(address(**stmt)))
+                    .map(|stmt| stmt.label().clone()),
+            );
+
+            function(arena, func, live, func.retval())
+        };
+
+        let changed = Rewriter::new(arena).rewrite_recipe(recipe, &mut f);
+        let name = self.name().to_string();
+        self.remarks.extend(
+            removed.iter().map(|label| {
+                Remark::applied(&name, label, "the statement is free of side effects")
+            }),
+        );
+        self.removed_stmts = removed;
+
+        self.prune(recipe) || changed
+    }
+
+    fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Call, Critical, Function, Loop, Number};
+
+    #[test]
+    fn impure_stmts_kept() {
+        let arena = Arena::new();
+
+        let lo = Number::new(0.0);
+        let infinite = Loop::new(Some(&lo), None, None);
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let stmts: Vec<&dyn BasicBlock> = vec![&infinite, &crit];
+        let func = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+
+        let mut dce = DeadCodeElimination::new();
+
+        assert!(!dce.run(&mut recipe, &arena));
+        assert!(dce.removed_stmts().is_empty());
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn called_function() {
+        let arena = Arena::new();
+
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+        let stmts: Vec<&dyn BasicBlock> = vec![&f, &t];
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&t);
+
+        let call = Call::new(&func, None);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&call);
+        recipe.add(&func);
+
+        let mut dce = DeadCodeElimination::new();
+
+        assert!(dce.run(&mut recipe, &arena));
+        assert_eq!(dce.removed_stmts().len(), 2);
+        assert!(dce.pruned_blocks().is_empty());
+        assert_eq!(recipe.fry(), Ok(true));
+
+        // The function is replaced, and still called
+        assert!(!recipe.contains(func.label()));
+        assert_eq!(recipe.len(), 2);
+    }
+
+    #[test]
+    fn shared_stmt_reported_once() {
+        let arena = Arena::new();
+
+        let dead = Boolean::new(false);
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let stmts: Vec<&dyn BasicBlock> = vec![&dead, &crit, &dead];
+        let inner = Function::new(None, &stmts);
+        let outer_stmts: Vec<&dyn BasicBlock> = vec![&dead, &inner];
+        let outer = Function::new(None, &outer_stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&outer);
+
+        let mut dce = DeadCodeElimination::new();
+
+        assert!(dce.run(&mut recipe, &arena));
+        assert_eq!(dce.removed_stmts(), [dead.label().as_str()]);
+        assert_eq!(
+            dce.remarks(),
+            [Remark::applied(
+                "dce",
+                dead.label(),
+                "the statement is free of side effects"
+            )]
+        );
+    }
+
+    #[test]
+    fn no_entry() {
+        let arena = Arena::new();
+        let b = Boolean::new(true);
+
+        let mut recipe = Recipe::new();
+        recipe.add(&b);
+
+        assert!(!DeadCodeElimination::new().run(&mut recipe, &arena));
+        assert_eq!(recipe.len(), 1);
+    }
+}
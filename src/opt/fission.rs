@@ -0,0 +1,406 @@
+//! Loop fission splits the body of a loop into several loops, so that the
+//! parts of the body which can be executed in parallel are not held back by
+//! the ones which cannot.
+
+use super::rewrite::{function, Rewriter};
+use super::{Pass, Remark};
+
+use crate::analysis::{has_critical, is_parallel};
+use crate::blocks::{BasicBlock, BlockKind, Function, Loop};
+use crate::cost::CostModel;
+use crate::recipe::{Arena, Recipe};
+
+/// Return the loop and the body of a loop statement which may be split, or
+/// why it may not. Statements which are not loops are `Ok(None)`
+fn fissionable<'block>(
+    stmt: &'block dyn BasicBlock,
+) -> Result<Option<(&'block Loop<'block>, &'block Function<'block>)>, &'static str> {
+    let l = match stmt.kind() {
+        BlockKind::Loop(l) => l,
+        _ => return Ok(None),
+    };
+
+    if l.lo_bound().is_none() || l.hi_bound().is_none() {
+        return Err("the loop is infinite");
+    }
+
+    if !is_parallel(l) {
+        return Err("the body contains blocks unknown to stir, which may depend on each other");
+    }
+
+    if l.trip_count().is_none() {
+        return Err("the bounds are not primitives, the trip count is unknown");
+    }
+
+    match l.body().map(|body| body.kind()) {
+        Some(BlockKind::Function(body)) => Ok(Some((l, body))),
+        Some(_) => Err("the body is not a function"),
+        None => Ok(None),
+    }
+}
+
+/// Splits the loops of a function whose body is a function with both critical
+/// and non-critical statements into two loops: one iterating over the
+/// non-critical statements, which can be executed in parallel, and one
+/// iterating over the critical statements. The return value of the body goes
+/// with the statements of its kind.
+///
+/// Non-critical statements which the cost model estimates are worth a
+/// parallel loop of their own are split further, each into its own loop,
+/// whether the body has critical statements or not. The other non-critical
+/// statements stay together.
+///
+/// The statements of a function are independent: they may already be
+/// executed in parallel, in any order. Loops are only split when they are
+/// statements, since the value of each loop is not the one of the original
+/// loop, and when their bodies are made of blocks provided by `stir`, which
+/// cannot hide dependencies.
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{BasicBlock, BlockKind, Boolean, Critical, Function, Loop, Number};
+/// use stir::opt::{LoopFission, Pass};
+/// use stir::recipe::{Arena, Recipe};
+///
+/// let b = Boolean::new(true);
+/// let crit = Critical::new(&b);
+/// let body_stmts: Vec<&dyn BasicBlock> = vec![&b, &crit];
+/// let body = Function::new(None, &body_stmts);
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(100.0);
+/// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+///
+/// let stmts: Vec<&dyn BasicBlock> = vec![&l];
+/// let func = Function::new(None, &stmts);
+///
+/// let arena = Arena::new();
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&func);
+///
+/// let mut fission = LoopFission::new();
+///
+/// assert!(fission.run(&mut recipe, &arena));
+/// assert_eq!(fission.split(), [l.label().as_str()]);
+///
+/// match recipe.entry().unwrap().kind() {
+///     BlockKind::Function(f) => assert_eq!(f.stmts().len(), 2),
+///     _ => unreachable!(),
+/// }
+/// ```
+pub struct LoopFission {
+    cost_model: CostModel,
+    split: Vec<String>,
+    remarks: Vec<Remark>,
+}
+
+impl LoopFission {
+    /// Create a new LoopFission pass, using the default cost model
+    pub fn new() -> LoopFission {
+        LoopFission {
+            cost_model: CostModel::new(),
+            split: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Set the cost model used to decide which statements are worth a
+    /// parallel loop of their own
+    pub fn set_cost_model(&mut self, cost_model: CostModel) {
+        self.cost_model = cost_model;
+    }
+
+    /// Return the labels of the loops split during the last run
+    pub fn split(&self) -> Vec<&str> {
+        self.split.iter().map(String::as_str).collect()
+    }
+}
+
+impl Default for LoopFission {
+    fn default() -> Self {
+        LoopFission::new()
+    }
+}
+
+impl Pass for LoopFission {
+    fn name(&self) -> &str {
+        "loop-fission"
+    }
+
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+        let name = self.name().to_string();
+        let cost_model = &self.cost_model;
+        let mut split = Vec::new();
+        let mut remarks = Vec::new();
+
+        let mut f = |original: &'block dyn BasicBlock, block: &'block dyn BasicBlock| {
+            let (o, func) = match (original.kind(), block.kind()) {
+                (BlockKind::Function(o), BlockKind::Function(func)) => (o, func),
+                _ => return block,
+            };
+
+            let mut stmts = Vec::new();
+            let mut changed = false;
+
+            for (original, stmt) in o.stmts().iter().zip(func.stmts()) {
+                let (l, body) = match fissionable(*stmt) {
+                    Ok(Some(fissionable)) => fissionable,
+                    Ok(None) => {
+                        stmts.push(*stmt);
+                        continue;
+                    }
+                    Err(reason) => {
+                        remarks.push(Remark::missed(&name, original.label(), reason));
+                        stmts.push(*stmt);
+                        continue;
+                    }
+                };
+
+                let (critical, parallel): (Vec<&dyn BasicBlock>, Vec<&dyn BasicBlock>) =
+                    body.stmts().iter().partition(|stmt| has_critical(**stmt));
+                let retval_critical = body.retval().map(has_critical);
+
+                // Statements worth a parallel loop of their own
+                let (own, rest): (Vec<&dyn BasicBlock>, Vec<&dyn BasicBlock>) =
+                    parallel.into_iter().partition(|stmt| {
+                        let stmts = vec![*stmt];
+                        let part = Function::new(None, &stmts);
+
+                        cost_model.should_parallelize(&Loop::new(
+                            l.lo_bound(),
+                            l.hi_bound(),
+                            Some(&part),
+                        ))
+                    });
+
+                let mut parts: Vec<_> = own.iter().map(|stmt| (vec![*stmt], false)).collect();
+                if !rest.is_empty() || retval_critical == Some(false) {
+                    parts.push((rest, retval_critical == Some(false)));
+                }
+
+                let critical_part = !critical.is_empty() || retval_critical == Some(true);
+                let reason = match (critical_part, parts.len()) {
+                    (false, 0 | 1) => {
+                        Err("no critical part, and no statement worth a loop of its own")
+                    }
+                    (false, _) => Ok("statements worth parallelizing run in loops of their own"),
+                    (true, 0) => Err("every part of the body is critical"),
+                    (true, _) if own.is_empty() => {
+                        Ok("the critical part of the body runs in a loop of its own")
+                    }
+                    (true, _) => {
+                        Ok("the critical part and costly statements run in loops of their own")
+                    }
+                };
+
+                let reason = match reason {
+                    Ok(reason) => reason,
+                    Err(reason) => {
+                        remarks.push(Remark::missed(&name, original.label(), reason));
+                        stmts.push(*stmt);
+                        continue;
+                    }
+                };
+
+                if critical_part {
+                    parts.push((critical, retval_critical == Some(true)));
+                }
+
+                for (part, with_retval) in parts {
+                    let retval = body.retval().filter(|_| with_retval);
+                    let part = function(arena, body, part, retval);
+
+                    stmts.push(arena.alloc(Loop::new(l.lo_bound(), l.hi_bound(), Some(part))));
+                }
+
+                split.push(original.label().clone());
+                remarks.push(Remark::applied(&name, original.label(), reason));
+                changed = true;
+            }
+
+            match changed {
+                true => function(arena, func, stmts, func.retval()),
+                false => block,
+            }
+        };
+
+        let changed = Rewriter::new(arena).rewrite_recipe(recipe, &mut f);
+        self.split = split;
+        self.remarks = remarks;
+
+        changed
+    }
+
+    fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Critical, IfElse, Number};
+
+    fn split<'block>(l: &'block dyn BasicBlock, arena: &'block Arena) -> bool {
+        let func = arena.alloc(Function::new(None, arena.alloc(vec![l])));
+        let mut recipe = Recipe::new();
+        recipe.add_entry(func);
+
+        let expected = recipe.fry();
+
+        let changed = LoopFission::new().run(&mut recipe, arena);
+        assert_eq!(recipe.fry(), expected);
+
+        changed
+    }
+
+    #[test]
+    fn critical_retval() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let body_stmts: Vec<&dyn BasicBlock> = vec![&b, &b];
+        let mut body = Function::new(None, &body_stmts);
+        body.set_retval(&crit);
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        assert!(split(&l, &arena));
+    }
+
+    #[test]
+    fn costly_statements() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let lo = Number::new(0.0);
+        let hi = Number::new(100.0);
+        let inner = Loop::new(Some(&lo), Some(&hi), Some(&b));
+        let body_stmts: Vec<&dyn BasicBlock> = vec![&inner, &b, &inner, &b];
+        let body = Function::new(None, &body_stmts);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let stmts: Vec<&dyn BasicBlock> = vec![&l];
+        let func = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+        let expected = recipe.fry();
+
+        // Every statement is too cheap for a high threshold
+        let mut model = CostModel::new();
+        model.set_threshold(1_000_000);
+
+        let mut fission = LoopFission::new();
+        fission.set_cost_model(model);
+        assert!(!fission.run(&mut recipe, &arena));
+
+        fission.set_cost_model(CostModel::new());
+        assert!(fission.run(&mut recipe, &arena));
+        assert_eq!(fission.split(), [l.label().as_str()]);
+        assert_eq!(recipe.fry(), expected);
+
+        // One loop per costly statement, and one for the others
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Function(f) => assert_eq!(f.stmts().len(), 3),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn remarks() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let ie = IfElse::new(&b, &crit, None);
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&ie));
+
+        let stmts: Vec<&dyn BasicBlock> = vec![&l];
+        let func = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+
+        let mut fission = LoopFission::new();
+
+        assert!(!fission.run(&mut recipe, &arena));
+        assert_eq!(
+            fission.remarks(),
+            [Remark::missed(
+                "loop-fission",
+                l.label(),
+                "the body is not a function"
+            )]
+        );
+    }
+
+    #[test]
+    fn kept() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+
+        // Only critical statements
+        let crit_stmts: Vec<&dyn BasicBlock> = vec![&crit, &crit];
+        let mut crit_body = Function::new(None, &crit_stmts);
+        crit_body.set_retval(&crit);
+
+        // Only parallel statements
+        let stmts: Vec<&dyn BasicBlock> = vec![&b, &b];
+        let body = Function::new(None, &stmts);
+
+        // Not a function
+        let ie = IfElse::new(&b, &crit, None);
+
+        assert!(!split(
+            &Loop::new(Some(&lo), Some(&hi), Some(&crit_body)),
+            &arena
+        ));
+        assert!(!split(
+            &Loop::new(Some(&lo), Some(&hi), Some(&body)),
+            &arena
+        ));
+        assert!(!split(&Loop::new(Some(&lo), Some(&hi), Some(&ie)), &arena));
+
+        // Infinite loops are not split. Frying them would never return
+        assert_eq!(
+            fissionable(&Loop::new(Some(&lo), None, Some(&crit_body))).err(),
+            Some("the loop is infinite")
+        );
+    }
+
+    #[test]
+    fn retval_kept() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let body_stmts: Vec<&dyn BasicBlock> = vec![&b, &crit];
+        let body = Function::new(None, &body_stmts);
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        // The loop is the return value of the function, not a statement
+        let stmts: Vec<&dyn BasicBlock> = vec![];
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&l);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+
+        assert!(!LoopFission::new().run(&mut recipe, &arena));
+    }
+}
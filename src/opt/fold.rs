@@ -0,0 +1,399 @@
+//! Constant folding evaluates blocks whose value is known before running the
+//! recipe, and replaces them with that value.
+
+use super::rewrite::Rewriter;
+use super::{Pass, Remark};
+
+use crate::analysis::is_pure;
+use crate::blocks::{BasicBlock, BlockKind, Boolean, Number, Primitive};
+use crate::recipe::{Arena, Recipe};
+
+/// Largest magnitude of the integers a `Number` represents exactly
+const MAX_EXACT: i64 = 1 << f64::MANTISSA_DIGITS;
+
+/// Return the value of a block if it is a primitive
+fn constant(block: &dyn BasicBlock) -> Option<bool> {
+    match block.kind().is_primitive() {
+        true => Some(block.interpret()),
+        false => None,
+    }
+}
+
+/// Return the integer value of a block if it is a primitive: a `Number`
+/// counts as its value, any other primitive as `0` or `1`
+fn integer(block: &dyn BasicBlock) -> Option<i64> {
+    match block.kind() {
+        BlockKind::Number(n) => Some(n.get() as i64),
+        kind if kind.is_primitive() => Some(block.interpret() as i64),
+        _ => None,
+    }
+}
+
+/// Replaces `IfElse` blocks whose condition is a primitive by the branch taken,
+/// `Arithmetic` blocks whose operands are primitives by a `Number`, and
+/// comparisons, side-effect-free loops and calls whose value is known by a
+/// `Boolean`. Blocks are folded children first, so that folded operands and
+/// conditions let their parents be folded in turn.
+///
+/// The IR has no variables: a value is only shared by referencing the same
+/// block from several parents. Such a block is folded once, and every parent
+/// sees the folded value, which is all the propagation there is to do.
+/// Results of `Arithmetic` blocks which a `Number` cannot represent exactly
+/// are kept as they are.
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{BasicBlock, Boolean, IfElse};
+/// use stir::opt::{ConstantFolding, Pass};
+/// use stir::recipe::{Arena, Recipe};
+///
+/// let c = Boolean::new(false);
+/// let t = Boolean::new(true);
+/// let f = Boolean::new(false);
+/// let ie = IfElse::new(&c, &t, Some(&f));
+///
+/// let arena = Arena::new();
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&ie);
+///
+/// assert!(ConstantFolding::new().run(&mut recipe, &arena));
+/// assert_eq!(recipe.entry().unwrap().label(), f.label());
+/// ```
+pub struct ConstantFolding {
+    folded: usize,
+    remarks: Vec<Remark>,
+}
+
+impl ConstantFolding {
+    /// Create a new ConstantFolding pass
+    pub fn new() -> ConstantFolding {
+        ConstantFolding {
+            folded: 0,
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Return the number of blocks folded during the last run
+    pub fn folded(&self) -> usize {
+        self.folded
+    }
+}
+
+impl Default for ConstantFolding {
+    fn default() -> Self {
+        ConstantFolding::new()
+    }
+}
+
+/// Return the value of a block built from primitives, if known. The block
+/// must be free of side effects to be replaced by its value
+fn fold(block: &dyn BasicBlock) -> Option<bool> {
+    match block.kind() {
+        BlockKind::Loop(l) => {
+            let trip_count = l.trip_count()?;
+
+            match l.body() {
+                _ if trip_count == 0 => Some(true),
+                None => Some(true),
+                Some(body) => constant(body),
+            }
+        }
+        BlockKind::Call(call) => {
+            let function = call.function();
+            if !is_pure(function) {
+                return None;
+            }
+
+            match function.retval() {
+                Some(retval) => constant(retval),
+                None => Some(false),
+            }
+        }
+        BlockKind::Compare(c) => Some(c.op().apply(integer(c.lhs())?, integer(c.rhs())?)),
+        _ => None,
+    }
+}
+
+/// Return the result of an `Arithmetic` block whose operands are primitives
+fn arithmetic(block: &dyn BasicBlock) -> Option<i64> {
+    match block.kind() {
+        BlockKind::Arithmetic(a) => Some(a.op().apply(integer(a.lhs())?, integer(a.rhs())?)),
+        _ => None,
+    }
+}
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &str {
+        "const-fold"
+    }
+
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+        let name = self.name().to_string();
+        let mut booleans: [Option<&'block Boolean>; 2] = [None, None];
+        let mut folded = 0;
+        let mut remarks = Vec::new();
+
+        let mut f = |original: &'block dyn BasicBlock,
+                     block: &'block dyn BasicBlock|
+         -> &'block dyn BasicBlock {
+            if let BlockKind::IfElse(ie) = block.kind() {
+                let value = constant(ie.cond_block());
+                if let Some(value) = value {
+                    folded += 1;
+                    remarks.push(Remark::applied(
+                        &name,
+                        original.label(),
+                        format!("the condition is always {}", value),
+                    ));
+                }
+
+                return match value {
+                    Some(true) => ie.t_block(),
+                    Some(false) => match ie.f_block() {
+                        Some(e) => e,
+                        None => {
+                            *booleans[0].get_or_insert_with(|| arena.alloc(Boolean::new(false)))
+                        }
+                    },
+                    None => block,
+                };
+            }
+
+            match arithmetic(block) {
+                Some(value) if (-MAX_EXACT..=MAX_EXACT).contains(&value) => {
+                    folded += 1;
+                    remarks.push(Remark::applied(
+                        &name,
+                        original.label(),
+                        format!("the result is always {}", value),
+                    ));
+
+                    return arena.alloc(Number::new(value as f64));
+                }
+                Some(_) => {
+                    remarks.push(Remark::missed(
+                        &name,
+                        original.label(),
+                        "the result cannot be represented exactly by a number",
+                    ));
+
+                    return block;
+                }
+                None => (),
+            }
+
+            match fold(block) {
+                Some(value) => {
+                    folded += 1;
+                    remarks.push(Remark::applied(
+                        &name,
+                        original.label(),
+                        format!("the value is always {}", value),
+                    ));
+
+                    *booleans[value as usize]
+                        .get_or_insert_with(|| arena.alloc(Boolean::new(value)))
+                }
+                None => block,
+            }
+        };
+
+        let changed = Rewriter::new(arena).rewrite_recipe(recipe, &mut f);
+        self.folded = folded;
+        self.remarks = remarks;
+
+        changed
+    }
+
+    fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{
+        ArithOp, Arithmetic, Call, Compare, CompareOp, Critical, Function, IfElse, Loop,
+    };
+
+    fn fold_entry<'block>(entry: &'block dyn BasicBlock, arena: &'block Arena) -> Recipe<'block> {
+        let mut recipe = Recipe::new();
+        recipe.add_entry(entry);
+
+        let expected = recipe.fry();
+
+        ConstantFolding::new().run(&mut recipe, arena);
+        assert_eq!(recipe.fry(), expected);
+
+        recipe
+    }
+
+    #[test]
+    fn if_else_without_else() {
+        let arena = Arena::new();
+
+        let c = Number::new(f64::NAN);
+        let t = Boolean::new(true);
+        let ie = IfElse::new(&c, &t, None);
+
+        let recipe = fold_entry(&ie, &arena);
+
+        assert!(matches!(
+            recipe.entry().unwrap().kind(),
+            BlockKind::Boolean(_)
+        ));
+    }
+
+    #[test]
+    fn nested() {
+        let arena = Arena::new();
+
+        // if (loop 0..3 { true }) { call f } where f returns false
+        let lo = Number::new(0.0);
+        let hi = Number::new(3.0);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let ret = Boolean::new(false);
+        let stmts: Vec<&dyn BasicBlock> = vec![&ret];
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&ret);
+        let call = Call::new(&func, None);
+
+        let ie = IfElse::new(&l, &call, None);
+
+        let mut recipe = fold_entry(&ie, &arena);
+
+        assert!(matches!(
+            recipe.entry().unwrap().kind(),
+            BlockKind::Boolean(_)
+        ));
+
+        let mut pass = ConstantFolding::new();
+        pass.run(&mut recipe, &arena);
+        assert_eq!(pass.folded(), 0);
+    }
+
+    #[test]
+    fn side_effects_kept() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let stmts: Vec<&dyn BasicBlock> = vec![&crit];
+        let func = Function::new(None, &stmts);
+        let call = Call::new(&func, None);
+
+        let recipe = fold_entry(&call, &arena);
+
+        assert_eq!(recipe.entry().unwrap().label(), call.label());
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn folded_bound() {
+        let arena = Arena::new();
+
+        // loop 0..(if true { 5 }) runs once
+        let lo = Number::new(0.0);
+        let c = Boolean::new(true);
+        let five = Number::new(5.0);
+        let hi = IfElse::new(&c, &five, None);
+        let crit = Critical::new(&c);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&crit));
+
+        let recipe = fold_entry(&l, &arena);
+
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Loop(l) => assert_eq!(l.trip_count(), Some(1)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn arithmetic_folded() {
+        let arena = Arena::new();
+
+        // loop 0..(2 * 3 - true) { if 5 >= 5 { critical } }
+        let zero = Number::new(0.0);
+        let two = Number::new(2.0);
+        let three = Number::new(3.0);
+        let t = Boolean::new(true);
+        let product = Arithmetic::new(ArithOp::Mul, &two, &three);
+        let hi = Arithmetic::new(ArithOp::Sub, &product, &t);
+        let cmp = Compare::new(CompareOp::Ge, &hi, &hi);
+        let crit = Critical::new(&t);
+        let ie = IfElse::new(&cmp, &crit, None);
+        let l = Loop::new(Some(&zero), Some(&hi), Some(&ie));
+
+        let recipe = fold_entry(&l, &arena);
+
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Loop(l) => {
+                assert_eq!(l.trip_count(), Some(5));
+                assert_eq!(l.body().unwrap().label(), crit.label());
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn folded_arithmetic_bound() {
+        let arena = Arena::new();
+
+        // loop 0..(if true { call f + 5 }) runs once, not six times
+        let zero = Number::new(0.0);
+        let five = Number::new(5.0);
+        let t = Boolean::new(true);
+        let crit = Critical::new(&t);
+        let stmts: Vec<&dyn BasicBlock> = vec![&crit];
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&t);
+        let call = Call::new(&func, None);
+        let sum = Arithmetic::new(ArithOp::Add, &call, &five);
+        let hi = IfElse::new(&t, &sum, None);
+        let l = Loop::new(Some(&zero), Some(&hi), Some(&crit));
+        let cmp = Compare::new(CompareOp::Lt, &hi, &five);
+
+        let recipe = fold_entry(&l, &arena);
+
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Loop(l) => assert!(!matches!(
+                l.hi_bound().unwrap().kind(),
+                BlockKind::Arithmetic(_)
+            )),
+            _ => unreachable!(),
+        }
+
+        let recipe = fold_entry(&cmp, &arena);
+        assert_eq!(recipe.fry(), Ok(true));
+    }
+
+    #[test]
+    fn inexact_kept() {
+        let arena = Arena::new();
+
+        let big = Number::new(MAX_EXACT as f64);
+        let t = Boolean::new(true);
+        let sum = Arithmetic::new(ArithOp::Add, &big, &t);
+        let cmp = Compare::new(CompareOp::Gt, &sum, &big);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&cmp);
+
+        let mut pass = ConstantFolding::new();
+        assert!(!pass.run(&mut recipe, &arena));
+        assert_eq!(
+            pass.remarks(),
+            [Remark::missed(
+                "const-fold",
+                sum.label(),
+                "the result cannot be represented exactly by a number"
+            )]
+        );
+        assert_eq!(recipe.fry(), Ok(true));
+    }
+}
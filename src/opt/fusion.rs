@@ -0,0 +1,279 @@
+//! Loop fusion merges adjacent loops iterating the same number of times into
+//! a single loop, saving the bookkeeping and the scheduling of each loop.
+
+use super::rewrite::{function, Rewriter};
+use super::{Pass, Remark};
+
+use crate::analysis::{has_critical, is_parallel};
+use crate::blocks::{BasicBlock, BlockKind, Function, Loop};
+use crate::recipe::{Arena, Recipe};
+
+/// Return the trip count of a loop statement which may be fused, and whether
+/// its body has a critical part
+fn fusible(stmt: &dyn BasicBlock) -> Option<(u64, bool)> {
+    match stmt.kind() {
+        BlockKind::Loop(l) if is_parallel(l) => {
+            Some((l.trip_count()?, l.body().is_some_and(has_critical)))
+        }
+        _ => None,
+    }
+}
+
+/// Fuses the adjacent loops of a function whose bounds are primitives and
+/// which iterate the same number of times. The body of the fused loop is a
+/// function whose statements are the bodies of the loops.
+///
+/// The statements of a function are independent: they may already be
+/// executed in parallel, in any order. Loops are only fused when they are
+/// statements, since the value of the fused loop is not the one of the
+/// original loops, and when their bodies are made of blocks provided by
+/// `stir`, which cannot hide dependencies. Loops with a critical part are not
+/// fused with loops without one, which could be executed in parallel.
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{BasicBlock, BlockKind, Boolean, Critical, Function, Loop, Number};
+/// use stir::opt::{LoopFusion, Pass};
+/// use stir::recipe::{Arena, Recipe};
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(100.0);
+/// let b = Boolean::new(true);
+/// let crit = Critical::new(&b);
+/// let l0 = Loop::new(Some(&lo), Some(&hi), Some(&crit));
+/// let l1 = Loop::new(Some(&lo), Some(&hi), Some(&crit));
+///
+/// let stmts: Vec<&dyn BasicBlock> = vec![&l0, &l1];
+/// let func = Function::new(None, &stmts);
+///
+/// let arena = Arena::new();
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&func);
+///
+/// let mut fusion = LoopFusion::new();
+///
+/// assert!(fusion.run(&mut recipe, &arena));
+/// assert_eq!(fusion.fused(), [l1.label().as_str()]);
+///
+/// match recipe.entry().unwrap().kind() {
+///     BlockKind::Function(f) => assert_eq!(f.stmts().len(), 1),
+///     _ => unreachable!(),
+/// }
+/// ```
+pub struct LoopFusion {
+    fused: Vec<String>,
+    remarks: Vec<Remark>,
+}
+
+impl LoopFusion {
+    /// Create a new LoopFusion pass
+    pub fn new() -> LoopFusion {
+        LoopFusion {
+            fused: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Return the labels of the loops fused into the loop preceding them
+    /// during the last run
+    pub fn fused(&self) -> Vec<&str> {
+        self.fused.iter().map(String::as_str).collect()
+    }
+}
+
+impl Default for LoopFusion {
+    fn default() -> Self {
+        LoopFusion::new()
+    }
+}
+
+impl Pass for LoopFusion {
+    fn name(&self) -> &str {
+        "loop-fusion"
+    }
+
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+        let name = self.name().to_string();
+        let mut fused = Vec::new();
+        let mut remarks = Vec::new();
+
+        let mut f = |original: &'block dyn BasicBlock, block: &'block dyn BasicBlock| {
+            let (o, func) = match (original.kind(), block.kind()) {
+                (BlockKind::Function(o), BlockKind::Function(func)) => (o, func),
+                _ => return block,
+            };
+
+            let keys: Vec<_> = func.stmts().iter().map(|stmt| fusible(*stmt)).collect();
+            let mut stmts = Vec::new();
+            let mut changed = false;
+            let mut i = 0;
+
+            while i < keys.len() {
+                let (run, trip_count) = match keys[i] {
+                    Some(key) => (
+                        keys[i..].iter().take_while(|k| **k == Some(key)).count(),
+                        key.0,
+                    ),
+                    None => (1, 0),
+                };
+
+                if run == 1 {
+                    stmts.push(func.stmts()[i]);
+                    i += 1;
+                    continue;
+                }
+
+                let loops: Vec<&Loop> = func.stmts()[i..i + run]
+                    .iter()
+                    .filter_map(|stmt| match stmt.kind() {
+                        BlockKind::Loop(l) => Some(l),
+                        _ => None,
+                    })
+                    .collect();
+                let bodies: Vec<&dyn BasicBlock> = loops.iter().filter_map(|l| l.body()).collect();
+
+                // The value of the fused body is discarded with the value of
+                // the loop
+                let body: Option<&dyn BasicBlock> = match bodies.is_empty() {
+                    true => None,
+                    false => Some(arena.alloc(Function::new(None, arena.alloc(bodies)))),
+                };
+
+                let first = loops[0];
+                stmts.push(arena.alloc(Loop::new(first.lo_bound(), first.hi_bound(), body)));
+
+                for stmt in &o.stmts()[i + 1..i + run] {
+                    fused.push(stmt.label().clone());
+                    remarks.push(Remark::applied(
+                        &name,
+                        stmt.label(),
+                        format!(
+                            "fused with the previous loop, iterating {} times",
+                            trip_count
+                        ),
+                    ));
+                }
+
+                changed = true;
+                i += run;
+            }
+
+            match changed {
+                true => function(arena, func, stmts, func.retval()),
+                false => block,
+            }
+        };
+
+        let changed = Rewriter::new(arena).rewrite_recipe(recipe, &mut f);
+        self.fused = fused;
+        self.remarks = remarks;
+
+        changed
+    }
+
+    fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Critical, IfElse, Number};
+
+    fn fuse<'block>(stmts: Vec<&'block dyn BasicBlock>, arena: &'block Arena) -> Vec<usize> {
+        let func = arena.alloc(Function::new(None, arena.alloc(stmts)));
+        let mut recipe = Recipe::new();
+        recipe.add_entry(func);
+
+        let expected = recipe.fry();
+
+        let mut fusion = LoopFusion::new();
+        fusion.run(&mut recipe, arena);
+
+        assert_eq!(recipe.fry(), expected);
+
+        // Number of statements of each fused loop body
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Function(f) => f
+                .stmts()
+                .iter()
+                .map(|stmt| match stmt.kind() {
+                    BlockKind::Loop(l) => match l.body().map(|body| body.kind()) {
+                        Some(BlockKind::Function(body)) => body.stmts().len(),
+                        Some(_) => 1,
+                        None => 0,
+                    },
+                    _ => 1,
+                })
+                .collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn same_trip_count() {
+        let arena = Arena::new();
+
+        let lo0 = Number::new(0.0);
+        let hi0 = Number::new(10.0);
+        let lo1 = Number::new(5.0);
+        let hi1 = Number::new(15.0);
+        let hi2 = Number::new(20.0);
+        let b = Boolean::new(true);
+
+        let l0 = Loop::new(Some(&lo0), Some(&hi0), Some(&b));
+        let l1 = Loop::new(Some(&lo1), Some(&hi1), Some(&b));
+        let l2 = Loop::new(Some(&lo0), Some(&hi0), None);
+        let l3 = Loop::new(Some(&lo0), Some(&hi2), Some(&b));
+        let l4 = Loop::new(Some(&lo0), Some(&hi2), Some(&b));
+
+        let stmts: Vec<&dyn BasicBlock> = vec![&l0, &l1, &l2, &l3, &l4, &b];
+
+        assert_eq!(fuse(stmts, &arena), [2, 2, 1]);
+    }
+
+    #[test]
+    fn critical_kept_apart() {
+        let arena = Arena::new();
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+
+        let l0 = Loop::new(Some(&lo), Some(&hi), Some(&b));
+        let l1 = Loop::new(Some(&lo), Some(&hi), Some(&crit));
+        let l2 = Loop::new(Some(&lo), Some(&hi), Some(&b));
+
+        // The trip count of a loop whose bounds are not primitives is unknown
+        let bound = IfElse::new(&b, &b, None);
+        let unknown = Loop::new(Some(&lo), Some(&bound), Some(&b));
+
+        let stmts: Vec<&dyn BasicBlock> = vec![&l0, &l1, &l2, &unknown];
+
+        assert_eq!(fuse(stmts, &arena), [1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn retval_kept() {
+        let arena = Arena::new();
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(3.0);
+        let b = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&b));
+
+        let stmts: Vec<&dyn BasicBlock> = vec![&l];
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&l);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&func);
+
+        assert!(!LoopFusion::new().run(&mut recipe, &arena));
+        assert_eq!(recipe.fry(), Ok(true));
+    }
+}
@@ -0,0 +1,597 @@
+//! The inliner replaces calls with the body of the function they call,
+//! saving a frame per call and exposing the statements of the callee to the
+//! scheduler of the caller.
+
+use std::collections::{HashMap, HashSet};
+
+use super::rewrite::{address, duplicate, function, Rewriter};
+use super::{Pass, Remark};
+
+use crate::analysis::is_pure;
+use crate::blocks::{BasicBlock, BlockKind, Boolean, Function, Inline};
+use crate::recipe::{Arena, Recipe};
+
+/// Maximum number of blocks of a function inlined at every call site
+pub const DEFAULT_INLINE_THRESHOLD: usize = 16;
+
+/// Return the number of distinct blocks of a function, without counting the
+/// blocks of the functions it calls
+fn size(f: &Function) -> usize {
+    let mut seen = HashSet::new();
+    let mut worklist: Vec<&dyn BasicBlock> = f.stmts().iter().copied().chain(f.retval()).collect();
+
+    while let Some(block) = worklist.pop() {
+        if !seen.insert(address(block)) {
+            continue;
+        }
+
+        match block.kind() {
+            BlockKind::Call(_) => (),
+            kind => worklist.extend(kind.children()),
+        }
+    }
+
+    seen.len()
+}
+
+/// Return the number of call sites of each function reachable from the
+/// recipe
+fn call_sites(recipe: &Recipe) -> HashMap<*const (), usize> {
+    let mut sites = HashMap::new();
+    let mut seen = HashSet::new();
+    let mut worklist = recipe.blocks();
+
+    while let Some(block) = worklist.pop() {
+        if !seen.insert(address(block)) {
+            continue;
+        }
+
+        let kind = block.kind();
+        if let BlockKind::Call(call) = kind {
+            *sites.entry(address(call.function())).or_insert(0) += 1;
+        }
+
+        worklist.extend(kind.children());
+    }
+
+    sites
+}
+
+/// Return the addresses of the calls whose value is used: calls which are the
+/// child of a block other than a function, and the blocks of the recipe.
+/// Statements and return values of functions are not part of them
+fn value_calls(recipe: &Recipe) -> HashSet<*const ()> {
+    let mut calls = HashSet::new();
+    let mut seen = HashSet::new();
+    let mut worklist = recipe.blocks();
+
+    calls.extend(recipe.blocks().into_iter().map(address));
+
+    while let Some(block) = worklist.pop() {
+        if !seen.insert(address(block)) {
+            continue;
+        }
+
+        let kind = block.kind();
+        let children = kind.children();
+        if !matches!(kind, BlockKind::Function(_)) {
+            calls.extend(children.iter().map(|child| address(*child)));
+        }
+
+        worklist.extend(children);
+    }
+
+    calls
+}
+
+/// Copy the function called at a call site, so that its blocks get labels of
+/// their own
+fn copy<'block>(
+    arena: &'block Arena,
+    callee: &'block Function<'block>,
+) -> &'block Function<'block> {
+    match duplicate(arena, callee).kind() {
+        BlockKind::Function(copy) => copy,
+        _ => unreachable!("a function is copied into a function"),
+    }
+}
+
+/// Replaces calls with the body of the function they call. Calls used as a
+/// statement or as the return value of a function are replaced by the
+/// statements of the callee, and calls to functions without statements by
+/// their return value. Other calls are replaced by a copy of the callee,
+/// which executes its statements then its return value, without a call.
+///
+/// Functions are inlined if they are marked `Inline::Always`, or if they are
+/// marked `Inline::Auto` and are either small or called only once. Every call
+/// site gets its own copy of the blocks of the callee, with new labels.
+///
+/// Calls do not bind their arguments to the parameters of the function they
+/// call: the inlined blocks keep the parameters the callee refers to, which
+/// is what executing the call evaluates.
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{BasicBlock, Boolean, Call, Critical, Function};
+/// use stir::opt::{Inliner, Pass};
+/// use stir::recipe::{Arena, Recipe};
+///
+/// let b = Boolean::new(true);
+/// let crit = Critical::new(&b);
+/// let callee_stmts: Vec<&dyn BasicBlock> = vec![&crit];
+/// let mut callee = Function::new(None, &callee_stmts);
+/// callee.set_retval(&b);
+///
+/// let call = Call::new(&callee, None);
+/// let stmts: Vec<&dyn BasicBlock> = vec![&call];
+/// let mut caller = Function::new(None, &stmts);
+/// caller.set_retval(&call);
+///
+/// let arena = Arena::new();
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&caller);
+///
+/// let mut inliner = Inliner::new();
+///
+/// assert!(inliner.run(&mut recipe, &arena));
+/// assert_eq!(inliner.inlined().len(), 2);
+/// assert_eq!(recipe.fry(), Ok(true));
+/// ```
+pub struct Inliner {
+    threshold: usize,
+    inlined: Vec<String>,
+    remarks: Vec<Remark>,
+}
+
+impl Inliner {
+    /// Create a new Inliner, inlining functions of up to
+    /// `DEFAULT_INLINE_THRESHOLD` blocks
+    pub fn new() -> Inliner {
+        Inliner {
+            threshold: DEFAULT_INLINE_THRESHOLD,
+            inlined: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Set the maximum number of blocks of the functions inlined at every call
+    /// site
+    pub fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold;
+    }
+
+    /// Return the labels of the calls inlined during the last run
+    pub fn inlined(&self) -> Vec<&str> {
+        self.inlined.iter().map(String::as_str).collect()
+    }
+}
+
+impl Default for Inliner {
+    fn default() -> Self {
+        Inliner::new()
+    }
+}
+
+/// Return why the function may not replace the calls to it, if it may not
+fn not_inlined(
+    callee: &Function,
+    threshold: usize,
+    sites: &HashMap<*const (), usize>,
+) -> Option<String> {
+    match callee.inline() {
+        Inline::Never => Some(String::from("the function is marked Inline::Never")),
+        Inline::Always => None,
+        Inline::Auto => {
+            let size = size(callee);
+            let sites = sites.get(&address(callee)).copied().unwrap_or(0);
+
+            match size <= threshold || sites == 1 {
+                true => None,
+                false => Some(format!(
+                    "the function has {} blocks, above the threshold of {}, and {} call sites",
+                    size, threshold, sites
+                )),
+            }
+        }
+    }
+}
+
+/// Return `true` if the function may replace the calls to it
+fn should_inline(callee: &Function, threshold: usize, sites: &HashMap<*const (), usize>) -> bool {
+    not_inlined(callee, threshold, sites).is_none()
+}
+
+/// Return the function called by `block` if it should be inlined, `block`
+/// being the call rewritten from `original`
+fn callee<'block>(
+    original: &'block dyn BasicBlock,
+    block: &'block dyn BasicBlock,
+    threshold: usize,
+    sites: &HashMap<*const (), usize>,
+) -> Option<&'block Function<'block>> {
+    match (original.kind(), block.kind()) {
+        (BlockKind::Call(o), BlockKind::Call(c))
+            if should_inline(o.function(), threshold, sites) =>
+        {
+            Some(c.function())
+        }
+        _ => None,
+    }
+}
+
+impl Pass for Inliner {
+    fn name(&self) -> &str {
+        "inline"
+    }
+
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+        let name = self.name().to_string();
+        let sites = call_sites(recipe);
+        let values = value_calls(recipe);
+        let threshold = self.threshold;
+
+        let mut inlined = Vec::new();
+        let mut remarks = Vec::new();
+        let mut false_block: Option<&'block dyn BasicBlock> = None;
+
+        let mut f = |original: &'block dyn BasicBlock, block: &'block dyn BasicBlock| {
+            match (original.kind(), block.kind()) {
+                (BlockKind::Call(o), BlockKind::Call(c)) => {
+                    if let Some(reason) = not_inlined(o.function(), threshold, &sites) {
+                        remarks.push(Remark::missed(&name, original.label(), reason));
+                        return block;
+                    }
+
+                    if c.function().stmts().is_empty() {
+                        inlined.push(original.label().clone());
+                        remarks.push(Remark::applied(
+                            &name,
+                            original.label(),
+                            "replaced by the return value of the function",
+                        ));
+
+                        return match c.function().retval() {
+                            Some(retval) => duplicate(arena, retval),
+                            None => {
+                                *false_block.get_or_insert_with(|| arena.alloc(Boolean::new(false)))
+                            }
+                        };
+                    }
+
+                    // Other calls to functions with statements are inlined
+                    // into the function they are a statement or the return
+                    // value of
+                    if !values.contains(&address(original)) {
+                        return block;
+                    }
+
+                    inlined.push(original.label().clone());
+                    remarks.push(Remark::applied(
+                        &name,
+                        original.label(),
+                        "the call is used as a value, replaced by a copy of the function",
+                    ));
+
+                    copy(arena, c.function())
+                }
+                (BlockKind::Function(o), BlockKind::Function(func)) => {
+                    let mut stmts = Vec::new();
+                    let mut changed = false;
+
+                    // The value of a statement is discarded: the return value
+                    // of the callee can only be dropped if it is pure
+                    for (original, stmt) in o.stmts().iter().zip(func.stmts()) {
+                        match callee(*original, *stmt, threshold, &sites) {
+                            Some(c) if c.retval().is_none_or(is_pure) => {
+                                let c = copy(arena, c);
+                                inlined.push(original.label().clone());
+                                remarks.push(Remark::applied(
+                                    &name,
+                                    original.label(),
+                                    "statements of the function inlined into the caller",
+                                ));
+                                stmts.extend(c.stmts());
+                                changed = true;
+                            }
+                            Some(_) => {
+                                remarks.push(Remark::missed(
+                                    &name,
+                                    original.label(),
+                                    "the return value of the function has side effects",
+                                ));
+                                stmts.push(*stmt);
+                            }
+                            None => stmts.push(*stmt),
+                        }
+                    }
+
+                    let mut retval = func.retval();
+                    if let (Some(original), Some(block)) = (o.retval(), func.retval()) {
+                        if let Some(c) = callee(original, block, threshold, &sites) {
+                            let c = copy(arena, c);
+                            inlined.push(original.label().clone());
+                            remarks.push(Remark::applied(
+                                &name,
+                                original.label(),
+                                "body of the function inlined into the caller",
+                            ));
+                            stmts.extend(c.stmts());
+                            retval = c.retval();
+                            changed = true;
+                        }
+                    }
+
+                    match changed {
+                        true => function(arena, func, stmts, retval),
+                        false => block,
+                    }
+                }
+                _ => block,
+            }
+        };
+
+        let changed = Rewriter::new(arena).rewrite_recipe(recipe, &mut f);
+
+        self.inlined = inlined;
+        self.remarks = remarks;
+
+        changed
+    }
+
+    fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{
+        ArithOp, Arithmetic, Call, Compare, CompareOp, Critical, IfElse, Loop, Number,
+    };
+    use crate::opt::RemarkKind;
+
+    #[test]
+    fn attributes() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let callee_stmts: Vec<&dyn BasicBlock> = vec![&crit];
+        let mut never = Function::new(None, &callee_stmts);
+        never.set_inline(Inline::Never);
+        let mut always = Function::new(None, &callee_stmts);
+        always.set_inline(Inline::Always);
+
+        let c0 = Call::new(&never, None);
+        let c1 = Call::new(&always, None);
+        let stmts: Vec<&dyn BasicBlock> = vec![&c0, &c1];
+        let caller = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&caller);
+
+        let mut inliner = Inliner::new();
+        inliner.set_threshold(0);
+
+        assert!(inliner.run(&mut recipe, &arena));
+        assert_eq!(inliner.inlined(), [c1.label().as_str()]);
+
+        let missed: Vec<&str> = inliner
+            .remarks()
+            .iter()
+            .filter(|remark| remark.kind() == RemarkKind::Missed)
+            .map(|remark| remark.label())
+            .collect();
+        assert_eq!(missed, [c0.label().as_str()]);
+
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Function(f) => {
+                assert_eq!(f.stmts().len(), 2);
+                assert_eq!(f.stmts()[0].label(), c0.label());
+                // The statement of the callee is copied with a new label
+                assert_ne!(f.stmts()[1].label(), crit.label());
+                assert!(matches!(f.stmts()[1].kind(), BlockKind::Critical(_)));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn threshold_and_single_caller() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let callee_stmts: Vec<&dyn BasicBlock> = vec![&crit];
+        let callee = Function::new(None, &callee_stmts);
+
+        let c0 = Call::new(&callee, None);
+        let c1 = Call::new(&callee, None);
+        let stmts: Vec<&dyn BasicBlock> = vec![&c0, &c1];
+        let caller = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&caller);
+
+        // Called twice, with two blocks
+        let mut inliner = Inliner::new();
+        inliner.set_threshold(1);
+
+        assert!(!inliner.run(&mut recipe, &arena));
+
+        inliner.set_threshold(2);
+
+        assert!(inliner.run(&mut recipe, &arena));
+        assert_eq!(inliner.inlined().len(), 2);
+    }
+
+    #[test]
+    fn expression() {
+        let arena = Arena::new();
+
+        // loop 0..4 { call f } where f returns (if true { false })
+        let c = Boolean::new(true);
+        let t = Boolean::new(false);
+        let ie = IfElse::new(&c, &t, None);
+        let no_stmts: Vec<&dyn BasicBlock> = vec![];
+        let mut callee = Function::new(None, &no_stmts);
+        callee.set_retval(&ie);
+
+        let nothing = Function::new(None, &no_stmts);
+
+        let call = Call::new(&callee, None);
+        let call_nothing = Call::new(&nothing, None);
+        let cond = IfElse::new(&call_nothing, &call, Some(&call_nothing));
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(4.0);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&cond));
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&l);
+
+        let expected = recipe.fry();
+
+        let mut inliner = Inliner::new();
+
+        assert!(inliner.run(&mut recipe, &arena));
+        assert_eq!(inliner.inlined().len(), 2);
+        assert_eq!(recipe.fry(), expected);
+    }
+
+    #[test]
+    fn arithmetic_value_kept() {
+        let arena = Arena::new();
+
+        // call f < 5 where f returns 5 + 5: the call counts as 1
+        let five = Number::new(5.0);
+        let sum = Arithmetic::new(ArithOp::Add, &five, &five);
+        let no_stmts: Vec<&dyn BasicBlock> = vec![];
+        let mut callee = Function::new(None, &no_stmts);
+        callee.set_retval(&sum);
+
+        let call = Call::new(&callee, None);
+        let cmp = Compare::new(CompareOp::Lt, &call, &five);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&cmp);
+
+        assert!(Inliner::new().run(&mut recipe, &arena));
+        assert_eq!(recipe.fry(), Ok(true));
+
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Compare(c) => {
+                assert!(!matches!(c.lhs().kind(), BlockKind::Arithmetic(_)))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn impure_retval_kept() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let callee_stmts: Vec<&dyn BasicBlock> = vec![&b];
+        let mut callee = Function::new(None, &callee_stmts);
+        callee.set_retval(&crit);
+
+        let call = Call::new(&callee, None);
+        let stmts: Vec<&dyn BasicBlock> = vec![&call];
+        let caller = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&caller);
+
+        let mut inliner = Inliner::new();
+
+        assert!(!inliner.run(&mut recipe, &arena));
+        assert_eq!(inliner.remarks().len(), 1);
+        assert_eq!(inliner.remarks()[0].label(), call.label());
+        assert_eq!(
+            inliner.remarks()[0].reason(),
+            "the return value of the function has side effects"
+        );
+    }
+
+    #[test]
+    fn sites_get_own_copies() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let callee_stmts: Vec<&dyn BasicBlock> = vec![&crit];
+        let mut callee = Function::new(None, &callee_stmts);
+        callee.set_inline(Inline::Always);
+
+        let c0 = Call::new(&callee, None);
+        let c1 = Call::new(&callee, None);
+        let stmts: Vec<&dyn BasicBlock> = vec![&c0, &c1];
+        let caller = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&caller);
+
+        assert!(Inliner::new().run(&mut recipe, &arena));
+
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Function(f) => {
+                let labels: Vec<&str> =
+                    f.stmts().iter().map(|stmt| stmt.label().as_str()).collect();
+
+                assert_eq!(labels.len(), 2);
+                assert_ne!(labels[0], labels[1]);
+                assert!(!labels.contains(&crit.label().as_str()));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn value_wrapped() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let callee_stmts: Vec<&dyn BasicBlock> = vec![&crit];
+        let mut callee = Function::new(None, &callee_stmts);
+        callee.set_retval(&b);
+
+        let call = Call::new(&callee, None);
+        let ie = IfElse::new(&call, &b, None);
+        let stmts: Vec<&dyn BasicBlock> = vec![&ie];
+        let caller = Function::new(None, &stmts);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&caller);
+
+        let mut inliner = Inliner::new();
+
+        assert!(inliner.run(&mut recipe, &arena));
+        assert_eq!(inliner.inlined(), [call.label().as_str()]);
+        assert!(inliner
+            .remarks()
+            .iter()
+            .all(|remark| remark.kind() == RemarkKind::Applied));
+
+        match recipe.entry().unwrap().kind() {
+            BlockKind::Function(f) => match f.stmts()[0].kind() {
+                BlockKind::IfElse(ie) => match ie.cond_block().kind() {
+                    BlockKind::Function(copy) => {
+                        assert_ne!(copy.label(), callee.label());
+                        assert_eq!(copy.stmts().len(), 1);
+                        assert_ne!(copy.stmts()[0].label(), crit.label());
+                        assert!(copy.interpret());
+                    }
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
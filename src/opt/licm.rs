@@ -0,0 +1,339 @@
+//! Loop-invariant code motion evaluates the blocks of a loop body which do
+//! not change from one iteration to the next once, before the loop.
+
+use super::rewrite::{same, Rewriter};
+use super::{Pass, Remark};
+
+use crate::analysis::is_pure;
+use crate::blocks::{BasicBlock, BlockKind, Boolean, IfElse, Loop};
+use crate::recipe::{Arena, Recipe};
+
+/// Moves the invariant blocks out of finite loops. Blocks cannot refer to the
+/// induction variable of a loop, so every block free of side effects is
+/// invariant:
+///
+/// * A loop whose body is free of side effects evaluates to the value of its
+///   body, or to `true` if it does not iterate: the body is evaluated once,
+///   before looping over nothing.
+/// * A loop whose body is an `IfElse` with a condition free of side effects
+///   always takes the same branch: the condition is evaluated once, before
+///   looping over the branch taken.
+/// * Otherwise, a block of the body free of side effects, like the bound of
+///   a nested loop or the operand of a statement, has the same value at
+///   every iteration: it is evaluated once, before looping over a copy of the
+///   body where it is replaced by its value. A single block is hoisted from a
+///   loop per run, as every block hoisted doubles the size of the loop.
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{BasicBlock, BlockKind, Boolean, Critical, IfElse, Loop, Number};
+/// use stir::opt::{LoopInvariantCodeMotion, Pass};
+/// use stir::recipe::{Arena, Recipe};
+///
+/// let inner_lo = Number::new(0.0);
+/// let inner_hi = Number::new(1000.0);
+/// let inner_body = Boolean::new(true);
+/// let invariant = Loop::new(Some(&inner_lo), Some(&inner_hi), Some(&inner_body));
+///
+/// let b = Boolean::new(false);
+/// let crit = Critical::new(&b);
+/// let body = IfElse::new(&invariant, &crit, None);
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(1000.0);
+/// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+///
+/// let arena = Arena::new();
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&l);
+///
+/// assert!(LoopInvariantCodeMotion::new().run(&mut recipe, &arena));
+/// assert!(matches!(recipe.entry().unwrap().kind(), BlockKind::IfElse(_)));
+/// assert_eq!(recipe.fry(), Ok(false));
+/// ```
+// FIXME: Logic: Hoist invariant blocks with side effects executed at every
+// iteration, and arithmetic blocks, once the IR has variables to store their
+// value
+pub struct LoopInvariantCodeMotion {
+    hoisted: Vec<String>,
+    remarks: Vec<Remark>,
+}
+
+impl LoopInvariantCodeMotion {
+    /// Create a new LoopInvariantCodeMotion pass
+    pub fn new() -> LoopInvariantCodeMotion {
+        LoopInvariantCodeMotion {
+            hoisted: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Return the labels of the loops blocks were hoisted from during the last
+    /// run
+    pub fn hoisted(&self) -> Vec<&str> {
+        self.hoisted.iter().map(String::as_str).collect()
+    }
+}
+
+impl Default for LoopInvariantCodeMotion {
+    fn default() -> Self {
+        LoopInvariantCodeMotion::new()
+    }
+}
+
+/// Return the first block nested in a loop body which is free of side effects
+/// and whose value is a boolean, so that it can be replaced by its value
+fn invariant(body: &dyn BasicBlock) -> Option<&dyn BasicBlock> {
+    let mut worklist = vec![body];
+
+    while let Some(block) = worklist.pop() {
+        let kind = block.kind();
+
+        // The value of an arithmetic block is not a boolean
+        let hoistable = !kind.is_primitive() && !matches!(kind, BlockKind::Arithmetic(_));
+        if !same(block, body) && hoistable && is_pure(block) {
+            return Some(block);
+        }
+
+        // The blocks of a called function are not part of the body
+        if !matches!(kind, BlockKind::Call(_)) {
+            worklist.extend(kind.children().into_iter().rev());
+        }
+    }
+
+    None
+}
+
+impl Pass for LoopInvariantCodeMotion {
+    fn name(&self) -> &str {
+        "licm"
+    }
+
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+        let name = self.name().to_string();
+        let mut hoisted = Vec::new();
+        let mut remarks = Vec::new();
+        let mut false_block: Option<&'block dyn BasicBlock> = None;
+
+        let mut f = |original: &'block dyn BasicBlock, block: &'block dyn BasicBlock| {
+            let (l, body) = match block.kind() {
+                BlockKind::Loop(l) => match (l.lo_bound(), l.hi_bound(), l.body()) {
+                    (Some(_), Some(_), Some(body)) => (l, body),
+                    _ => return block,
+                },
+                _ => return block,
+            };
+
+            // Primitives are folded, not hoisted
+            if body.kind().is_primitive() {
+                return block;
+            }
+
+            let mut false_block =
+                || *false_block.get_or_insert_with(|| arena.alloc(Boolean::new(false)));
+            let with_body = |body| -> &'block dyn BasicBlock {
+                arena.alloc(Loop::new(l.lo_bound(), l.hi_bound(), body))
+            };
+
+            let (cond, t, e, reason) = if is_pure(body) {
+                // A loop iterating over a `false` body evaluates to `true` if
+                // it does not iterate, like the original loop
+                let reason = "the body is free of side effects";
+                (
+                    body,
+                    with_body(None),
+                    with_body(Some(false_block())),
+                    reason,
+                )
+            } else {
+                match body.kind() {
+                    BlockKind::IfElse(ie)
+                        if is_pure(ie.cond_block()) && !ie.cond_block().kind().is_primitive() =>
+                    {
+                        let e = ie.f_block().unwrap_or_else(false_block);
+                        (
+                            ie.cond_block(),
+                            with_body(Some(ie.t_block())),
+                            with_body(Some(e)),
+                            "the condition of the body is free of side effects",
+                        )
+                    }
+                    _ => match invariant(body) {
+                        Some(cond) => {
+                            let with_value = |value| {
+                                let value: &'block dyn BasicBlock =
+                                    arena.alloc(Boolean::new(value));
+                                let body =
+                                    Rewriter::new(arena).rewrite(body, &mut |original, block| {
+                                        match same(original, cond) {
+                                            true => value,
+                                            false => block,
+                                        }
+                                    });
+
+                                with_body(Some(body))
+                            };
+
+                            (
+                                cond,
+                                with_value(true),
+                                with_value(false),
+                                "a block of the body is free of side effects",
+                            )
+                        }
+                        None => {
+                            remarks.push(Remark::missed(
+                                &name,
+                                original.label(),
+                                "the body has side effects",
+                            ));
+
+                            return block;
+                        }
+                    },
+                }
+            };
+
+            hoisted.push(original.label().clone());
+            remarks.push(Remark::applied(&name, original.label(), reason));
+
+            arena.alloc(IfElse::new(cond, t, Some(e)))
+        };
+
+        let changed = Rewriter::new(arena).rewrite_recipe(recipe, &mut f);
+        self.hoisted = hoisted;
+        self.remarks = remarks;
+
+        changed
+    }
+
+    fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Compare, CompareOp, Critical, Function, Number};
+
+    fn hoist<'block>(l: &'block dyn BasicBlock, arena: &'block Arena) -> bool {
+        let mut recipe = Recipe::new();
+        recipe.add_entry(l);
+
+        let expected = recipe.fry();
+
+        let changed = LoopInvariantCodeMotion::new().run(&mut recipe, arena);
+        assert_eq!(recipe.fry(), expected);
+
+        changed
+    }
+
+    #[test]
+    fn pure_body() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(false);
+        let stmts: Vec<&dyn BasicBlock> = vec![&b];
+        let mut body = Function::new(None, &stmts);
+        body.set_retval(&b);
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+        let zero = Loop::new(Some(&hi), Some(&lo), Some(&body));
+
+        assert!(hoist(&l, &arena));
+        assert!(hoist(&zero, &arena));
+    }
+
+    #[test]
+    fn unswitch_without_else() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let stmts: Vec<&dyn BasicBlock> = vec![];
+        let mut cond = Function::new(None, &stmts);
+        cond.set_retval(&b);
+        let crit = Critical::new(&b);
+
+        let t_body = IfElse::new(&cond, &crit, None);
+        let f_body = IfElse::new(&crit, &crit, None);
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let zero = Number::new(0.0);
+
+        assert!(hoist(
+            &Loop::new(Some(&lo), Some(&hi), Some(&t_body)),
+            &arena
+        ));
+        assert!(hoist(
+            &Loop::new(Some(&zero), Some(&lo), Some(&t_body)),
+            &arena
+        ));
+        assert!(!hoist(
+            &Loop::new(Some(&lo), Some(&hi), Some(&f_body)),
+            &arena
+        ));
+    }
+
+    #[test]
+    fn nested() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+
+        // The bound of a nested loop
+        let inner_body = Boolean::new(false);
+        let bound = Loop::new(Some(&lo), Some(&hi), Some(&inner_body));
+        let inner = Loop::new(Some(&lo), Some(&bound), Some(&crit));
+        assert!(hoist(
+            &Loop::new(Some(&lo), Some(&hi), Some(&inner)),
+            &arena
+        ));
+
+        // The operand of a statement
+        let cmp = Compare::new(CompareOp::Lt, &bound, &hi);
+        let stmt = IfElse::new(&cmp, &crit, Some(&crit));
+        let stmts: Vec<&dyn BasicBlock> = vec![&crit, &stmt];
+        let body = Function::new(None, &stmts);
+
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&l);
+        let expected = recipe.fry();
+
+        let mut licm = LoopInvariantCodeMotion::new();
+        assert!(licm.run(&mut recipe, &arena));
+        assert_eq!(licm.hoisted(), [l.label().as_str()]);
+        assert_eq!(recipe.fry(), expected);
+
+        match recipe.entry().unwrap().kind() {
+            BlockKind::IfElse(ie) => assert_eq!(ie.cond_block().label(), cmp.label()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn kept() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+
+        assert!(!hoist(&Loop::new(Some(&lo), Some(&hi), Some(&b)), &arena));
+        assert!(!hoist(
+            &Loop::new(Some(&lo), Some(&hi), Some(&crit)),
+            &arena
+        ));
+        assert!(!hoist(&Loop::new(Some(&lo), Some(&hi), None), &arena));
+    }
+}
@@ -0,0 +1,481 @@
+//! Optimization passes transform a `Recipe` into an equivalent, faster one.
+//!
+//! Every pass implements the `Pass` trait. A `PassManager` runs a pipeline of
+//! passes, either one of the `OptLevel` presets or a list of pass names,
+//! timing each of them. Blocks created by the passes are owned by an `Arena`
+//! living as long as the recipe.
+
+mod cse;
+mod dce;
+mod fission;
+mod fold;
+mod fusion;
+mod inline;
+mod licm;
+mod remark;
+mod rewrite;
+mod specialize;
+mod unroll;
+mod verify;
+
+pub use cse::CommonSubexpressionElimination;
+pub use dce::DeadCodeElimination;
+pub use fission::LoopFission;
+pub use fold::ConstantFolding;
+pub use fusion::LoopFusion;
+pub use inline::{Inliner, DEFAULT_INLINE_THRESHOLD};
+pub use licm::LoopInvariantCodeMotion;
+pub use remark::{Remark, RemarkKind};
+pub use specialize::FunctionSpecialization;
+pub use unroll::{LoopUnrolling, DEFAULT_FULL_UNROLL, DEFAULT_UNROLL_FACTOR};
+pub use verify::{verify, VerifyError};
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::recipe::{Arena, Recipe};
+
+/// Transformation of a `Recipe`
+pub trait Pass {
+    /// Return the name used to select the pass
+    fn name(&self) -> &str;
+
+    /// Transform the recipe, allocating the blocks created in `arena`. Return
+    /// `true` if the recipe changed
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool;
+
+    /// Return the remarks emitted during the last run, explaining which
+    /// transformations were applied or missed, and why
+    fn remarks(&self) -> &[Remark] {
+        &[]
+    }
+}
+
+/// Constructor of a pass provided by `stir`
+type Constructor = fn() -> Box<dyn Pass>;
+
+/// Passes provided by `stir`, selectable by name
+const PASSES: &[(&str, Constructor)] = &[
+    ("const-fold", || Box::new(ConstantFolding::new())),
+    ("cse", || Box::new(CommonSubexpressionElimination::new())),
+    ("dce", || Box::new(DeadCodeElimination::new())),
+    ("inline", || Box::new(Inliner::new())),
+    ("licm", || Box::new(LoopInvariantCodeMotion::new())),
+    ("loop-fission", || Box::new(LoopFission::new())),
+    ("loop-fusion", || Box::new(LoopFusion::new())),
+    ("specialize", || Box::new(FunctionSpecialization::new())),
+    ("unroll", || Box::new(LoopUnrolling::new())),
+];
+
+/// Create the pass provided by `stir` with the given name
+fn create(name: &str) -> Option<Box<dyn Pass>> {
+    PASSES
+        .iter()
+        .find(|(pass, _)| *pass == name)
+        .map(|(_, constructor)| constructor())
+}
+
+/// Return the names of the passes provided by `stir`
+///
+/// # Example
+///
+/// ```
+/// use stir::opt::{pass_names, PassManager};
+///
+/// let pm = PassManager::with_names(&pass_names()).unwrap();
+///
+/// assert_eq!(pm.names(), pass_names());
+/// ```
+pub fn pass_names() -> Vec<&'static str> {
+    PASSES.iter().map(|(name, _)| *name).collect()
+}
+
+/// Optimization presets, from `O0` (no optimization) to `O3` (aggressive)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    O3,
+}
+
+impl OptLevel {
+    /// Return the names of the passes run at this level, in order
+    pub fn pipeline(self) -> &'static [&'static str] {
+        match self {
+            OptLevel::O0 => &[],
+            OptLevel::O1 => &["const-fold", "dce"],
+            OptLevel::O2 => &["const-fold", "inline", "licm", "const-fold", "cse", "dce"],
+            OptLevel::O3 => &[
+                "const-fold",
+                "inline",
+                "licm",
+                "loop-fission",
+                "loop-fusion",
+                "unroll",
+                "const-fold",
+                "cse",
+                "dce",
+            ],
+        }
+    }
+
+    /// Parse a level given as `0` to `3`, optionally prefixed with `O` or
+    /// `-O`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::opt::OptLevel;
+    ///
+    /// assert_eq!(OptLevel::parse("-O2"), Some(OptLevel::O2));
+    /// assert_eq!(OptLevel::parse("3"), Some(OptLevel::O3));
+    /// assert_eq!(OptLevel::parse("O4"), None);
+    /// ```
+    pub fn parse(level: &str) -> Option<OptLevel> {
+        let level = level.strip_prefix('-').unwrap_or(level);
+
+        match level.strip_prefix('O').unwrap_or(level) {
+            "0" => Some(OptLevel::O0),
+            "1" => Some(OptLevel::O1),
+            "2" => Some(OptLevel::O2),
+            "3" => Some(OptLevel::O3),
+            _ => None,
+        }
+    }
+}
+
+/// Reason why a pipeline could not be built or run
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassError {
+    /// No pass has the given name
+    UnknownPass(String),
+
+    /// The recipe produced by the pass with the given name is malformed
+    Verify(String, VerifyError),
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassError::UnknownPass(name) => write!(f, "unknown pass {}", name),
+            PassError::Verify(name, e) => write!(f, "after pass {}: {}", name, e),
+        }
+    }
+}
+
+impl std::error::Error for PassError {}
+
+/// Time spent in a pass during the last run of a `PassManager`
+#[derive(Debug, Clone)]
+pub struct PassTiming {
+    name: String,
+    duration: Duration,
+    changed: bool,
+}
+
+impl PassTiming {
+    /// Return the name of the pass
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the time spent running the pass, excluding verification
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Return `true` if the pass changed the recipe
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+}
+
+/// Runs a pipeline of passes on a `Recipe`
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    verify: bool,
+    timings: Vec<PassTiming>,
+    remarks: Vec<Remark>,
+}
+
+impl PassManager {
+    /// Create a new PassManager with an empty pipeline. The recipe is verified
+    /// after each pass in debug builds
+    pub fn new() -> PassManager {
+        PassManager {
+            passes: Vec::new(),
+            verify: cfg!(debug_assertions),
+            timings: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Create a new PassManager running the pipeline of an `OptLevel`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::Boolean;
+    /// use stir::opt::{OptLevel, PassManager};
+    /// use stir::recipe::{Arena, Recipe};
+    ///
+    /// let arena = Arena::new();
+    /// let b = Boolean::new(true);
+    ///
+    /// let mut recipe = Recipe::new();
+    /// recipe.add_entry(&b);
+    ///
+    /// let mut pm = PassManager::with_level(OptLevel::O2);
+    ///
+    /// assert!(pm.run(&mut recipe, &arena).is_ok());
+    /// assert_eq!(recipe.fry(), Ok(true));
+    /// ```
+    pub fn with_level(level: OptLevel) -> PassManager {
+        let mut pm = PassManager::new();
+
+        for name in level.pipeline() {
+            pm.add(create(name).expect("presets only contain known passes"));
+        }
+
+        pm
+    }
+
+    /// Create a new PassManager running the passes with the given names, in
+    /// order
+    pub fn with_names(names: &[&str]) -> Result<PassManager, PassError> {
+        let mut pm = PassManager::new();
+
+        for name in names {
+            match create(name) {
+                Some(pass) => pm.add(pass),
+                None => return Err(PassError::UnknownPass(name.to_string())),
+            }
+        }
+
+        Ok(pm)
+    }
+
+    /// Append a pass to the pipeline
+    pub fn add(&mut self, pass: Box<dyn Pass>) {
+        self.passes.push(pass);
+    }
+
+    /// Verify the recipe after each pass, or not
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Return the names of the passes of the pipeline, in order
+    pub fn names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Run the pipeline on the recipe. Return `true` if any pass changed it
+    pub fn run<'block>(
+        &mut self,
+        recipe: &mut Recipe<'block>,
+        arena: &'block Arena,
+    ) -> Result<bool, PassError> {
+        self.timings.clear();
+        self.remarks.clear();
+
+        let mut changed = false;
+
+        for pass in self.passes.iter_mut() {
+            let start = Instant::now();
+            let pass_changed = pass.run(recipe, arena);
+
+            self.timings.push(PassTiming {
+                name: pass.name().to_string(),
+                duration: start.elapsed(),
+                changed: pass_changed,
+            });
+            self.remarks.extend_from_slice(pass.remarks());
+
+            if self.verify {
+                verify(recipe).map_err(|e| PassError::Verify(pass.name().to_string(), e))?;
+            }
+
+            changed |= pass_changed;
+        }
+
+        Ok(changed)
+    }
+
+    /// Return the time spent in each pass during the last run, in order
+    pub fn timings(&self) -> &[PassTiming] {
+        &self.timings
+    }
+
+    /// Return the remarks emitted by the passes during the last run, in order
+    pub fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{BasicBlock, Boolean, Critical, IfElse, Loop, Number};
+
+    /// Pass replacing the entry of the recipe with a `Boolean`
+    struct Replace(bool);
+
+    impl Pass for Replace {
+        fn name(&self) -> &str {
+            "replace"
+        }
+
+        fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+            recipe.set_entry(arena.alloc(Boolean::new(self.0)));
+
+            true
+        }
+    }
+
+    /// Block reusing the label of another block
+    #[derive(Debug)]
+    struct Impostor(String);
+
+    impl BasicBlock for Impostor {
+        fn label(&self) -> &String {
+            &self.0
+        }
+
+        fn output(&self) -> String {
+            String::from("impostor")
+        }
+
+        fn interpret(&self) -> bool {
+            false
+        }
+    }
+
+    /// Pass replacing the entry with an impostor, breaking the recipe
+    struct Break;
+
+    impl Pass for Break {
+        fn name(&self) -> &str {
+            "break"
+        }
+
+        fn run<'block>(&mut self, recipe: &mut Recipe<'block>, _: &'block Arena) -> bool {
+            if let Some(entry) = recipe.entry() {
+                recipe.add(Box::leak(Box::new(Impostor(entry.label().clone()))));
+            }
+
+            true
+        }
+    }
+
+    #[test]
+    fn pipeline() {
+        let arena = Arena::new();
+        let b = Boolean::new(false);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&b);
+
+        let mut pm = PassManager::new();
+        pm.add(Box::new(Replace(false)));
+        pm.add(Box::new(Replace(true)));
+
+        assert_eq!(pm.run(&mut recipe, &arena), Ok(true));
+        assert_eq!(recipe.fry(), Ok(true));
+        assert_eq!(recipe.len(), 3);
+
+        assert_eq!(pm.names(), ["replace", "replace"]);
+        assert_eq!(pm.timings().len(), 2);
+        assert!(pm.timings().iter().all(|timing| timing.changed()));
+    }
+
+    #[test]
+    fn verification() {
+        let arena = Arena::new();
+        let b = Boolean::new(false);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&b);
+
+        let mut pm = PassManager::new();
+        pm.add(Box::new(Break));
+        pm.add(Box::new(Replace(true)));
+        pm.set_verify(true);
+
+        assert_eq!(
+            pm.run(&mut recipe, &arena),
+            Err(PassError::Verify(
+                String::from("break"),
+                VerifyError::EntryNotInRecipe(b.label().clone())
+            ))
+        );
+        assert_eq!(pm.timings().len(), 1);
+
+        pm.set_verify(false);
+        assert_eq!(pm.run(&mut recipe, &arena), Ok(true));
+    }
+
+    #[test]
+    fn unknown_pass() {
+        assert_eq!(
+            PassManager::with_names(&["nope"]).err(),
+            Some(PassError::UnknownPass(String::from("nope")))
+        );
+    }
+
+    #[test]
+    fn remarks() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let lo = Number::new(0.0);
+        let hi = Number::new(3.0);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&crit));
+        let ie = IfElse::new(&b, &l, None);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&ie);
+
+        let mut pm = PassManager::with_names(&["const-fold", "unroll"]).unwrap();
+
+        assert_eq!(pm.run(&mut recipe, &arena), Ok(true));
+
+        let remarks: Vec<(&str, &str, RemarkKind)> = pm
+            .remarks()
+            .iter()
+            .map(|remark| (remark.pass(), remark.label(), remark.kind()))
+            .collect();
+        assert_eq!(
+            remarks,
+            [
+                ("const-fold", ie.label().as_str(), RemarkKind::Applied),
+                ("unroll", l.label().as_str(), RemarkKind::Applied),
+            ]
+        );
+
+        // Remarks are only kept for the last run
+        assert_eq!(pm.run(&mut recipe, &arena), Ok(false));
+        assert!(pm.remarks().is_empty());
+    }
+
+    #[test]
+    fn presets() {
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3] {
+            assert_eq!(PassManager::with_level(level).names(), level.pipeline());
+        }
+
+        assert!(OptLevel::O0.pipeline().is_empty());
+        // Specialization is a no-op after constant folding
+        assert!(!OptLevel::O3.pipeline().contains(&"specialize"));
+    }
+}
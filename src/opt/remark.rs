@@ -0,0 +1,119 @@
+//! Remarks explain the decisions of the passes: which transformations were
+//! applied to a block, and why the other ones were missed.
+
+use std::fmt;
+
+/// Whether a transformation was applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemarkKind {
+    /// The transformation was applied to the block
+    Applied,
+
+    /// The transformation could not be applied to the block
+    Missed,
+}
+
+impl fmt::Display for RemarkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemarkKind::Applied => write!(f, "applied"),
+            RemarkKind::Missed => write!(f, "missed"),
+        }
+    }
+}
+
+/// Decision of a pass about a block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remark {
+    pass: String,
+    label: String,
+    kind: RemarkKind,
+    reason: String,
+}
+
+impl Remark {
+    /// Create a new remark about a transformation applied to a block
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::opt::{Remark, RemarkKind};
+    ///
+    /// let remark = Remark::applied("inline", "__call_0", "inlined into the caller");
+    ///
+    /// assert_eq!(remark.kind(), RemarkKind::Applied);
+    /// assert_eq!(remark.to_string(), "inline: applied to __call_0: inlined into the caller");
+    /// ```
+    pub fn applied(pass: &str, label: &str, reason: impl Into<String>) -> Remark {
+        Remark::new(pass, label, RemarkKind::Applied, reason.into())
+    }
+
+    /// Create a new remark about a transformation which could not be applied
+    /// to a block
+    pub fn missed(pass: &str, label: &str, reason: impl Into<String>) -> Remark {
+        Remark::new(pass, label, RemarkKind::Missed, reason.into())
+    }
+
+    fn new(pass: &str, label: &str, kind: RemarkKind, reason: String) -> Remark {
+        Remark {
+            pass: pass.to_string(),
+            label: label.to_string(),
+            kind,
+            reason,
+        }
+    }
+
+    /// Return the name of the pass which emitted the remark
+    pub fn pass(&self) -> &str {
+        &self.pass
+    }
+
+    /// Return the label of the block the remark is about
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Return whether the transformation was applied
+    pub fn kind(&self) -> RemarkKind {
+        self.kind
+    }
+
+    /// Return why the transformation was applied or missed
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for Remark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let preposition = match self.kind {
+            RemarkKind::Applied => "to",
+            RemarkKind::Missed => "on",
+        };
+
+        write!(
+            f,
+            "{}: {} {} {}: {}",
+            self.pass, self.kind, preposition, self.label, self.reason
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missed() {
+        let remark = Remark::missed("unroll", "__loop_3", "the trip count is unknown");
+
+        assert_eq!(remark.pass(), "unroll");
+        assert_eq!(remark.label(), "__loop_3");
+        assert_eq!(remark.kind(), RemarkKind::Missed);
+        assert_eq!(remark.reason(), "the trip count is unknown");
+        assert_eq!(
+            remark.to_string(),
+            "unroll: missed on __loop_3: the trip count is unknown"
+        );
+    }
+}
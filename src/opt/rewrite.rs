@@ -0,0 +1,417 @@
+//! The `Rewriter` rebuilds trees of blocks bottom-up. Blocks are immutable:
+//! a block whose children changed is copied into the `Arena` with its new
+//! children, and the blocks referencing it are copied in turn.
+
+use std::collections::HashMap;
+
+use crate::blocks::{
+    Arithmetic, BasicBlock, BlockKind, Boolean, Call, Compare, Critical, Function, IfElse, Loop,
+    Number, Primitive, Str,
+};
+use crate::recipe::{Arena, Recipe};
+
+/// Return the address of a block, identifying it regardless of its label
+pub(crate) fn address(block: &dyn BasicBlock) -> *const () {
+    block as *const dyn BasicBlock as *const ()
+}
+
+/// Return `true` if both references point to the same block
+pub(crate) fn same(a: &dyn BasicBlock, b: &dyn BasicBlock) -> bool {
+    address(a) == address(b)
+}
+
+/// Applies a transformation to every block of a tree, children first. Blocks
+/// shared by several parents are only transformed once
+pub(crate) struct Rewriter<'block> {
+    arena: &'block Arena,
+    memo: HashMap<*const (), &'block dyn BasicBlock>,
+    calls: bool,
+}
+
+impl<'block> Rewriter<'block> {
+    pub(crate) fn new(arena: &'block Arena) -> Rewriter<'block> {
+        Rewriter {
+            arena,
+            memo: HashMap::new(),
+            calls: true,
+        }
+    }
+
+    /// Set whether the functions called by the blocks are rewritten. When
+    /// they are not, calls are kept as they are
+    pub(crate) fn set_calls(&mut self, calls: bool) {
+        self.calls = calls;
+    }
+
+    /// Rewrite every block of the recipe, replacing the blocks which changed.
+    /// Return `true` if the recipe changed
+    pub(crate) fn rewrite_recipe<F>(&mut self, recipe: &mut Recipe<'block>, f: &mut F) -> bool
+    where
+        F: FnMut(&'block dyn BasicBlock, &'block dyn BasicBlock) -> &'block dyn BasicBlock,
+    {
+        let mut changed = false;
+
+        for block in recipe.blocks() {
+            let new = self.rewrite(block, f);
+            if same(block, new) {
+                continue;
+            }
+
+            let is_entry = recipe.entry().is_some_and(|entry| same(entry, block));
+
+            recipe.remove(block.label());
+            match is_entry {
+                true => recipe.set_entry(new),
+                false => {
+                    recipe.add(new);
+                }
+            }
+
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Rewrite the children of the block, then apply `f` to the block and to
+    /// its copy rebuilt with the new children, which is the block itself if
+    /// none of its children changed. `f` returns the rebuilt block to keep it.
+    ///
+    /// `f` must transform a `Function` into a `Function`, since it may be the
+    /// target of a `Call`, and keep `Number`s as they are, since they may be
+    /// loop bounds
+    pub(crate) fn rewrite<F>(
+        &mut self,
+        block: &'block dyn BasicBlock,
+        f: &mut F,
+    ) -> &'block dyn BasicBlock
+    where
+        F: FnMut(&'block dyn BasicBlock, &'block dyn BasicBlock) -> &'block dyn BasicBlock,
+    {
+        if let Some(done) = self.memo.get(&address(block)) {
+            return *done;
+        }
+
+        let rebuilt = self.rebuild(block, f);
+        let result = f(block, rebuilt);
+
+        self.memo.insert(address(block), result);
+
+        result
+    }
+
+    /// Copy the block if any of its children changed
+    fn rebuild<F>(&mut self, block: &'block dyn BasicBlock, f: &mut F) -> &'block dyn BasicBlock
+    where
+        F: FnMut(&'block dyn BasicBlock, &'block dyn BasicBlock) -> &'block dyn BasicBlock,
+    {
+        match block.kind() {
+            BlockKind::IfElse(ie) => {
+                let cond = self.rewrite(ie.cond_block(), f);
+                let t = self.rewrite(ie.t_block(), f);
+                let e = ie.f_block().map(|e| self.rewrite(e, f));
+
+                if same(cond, ie.cond_block())
+                    && same(t, ie.t_block())
+                    && unchanged(e, ie.f_block())
+                {
+                    return block;
+                }
+
+                self.arena.alloc(IfElse::new(cond, t, e))
+            }
+            BlockKind::Loop(l) => {
+                let lo = l.lo_bound().map(|lo| self.integer(lo, f));
+                let hi = l.hi_bound().map(|hi| self.integer(hi, f));
+                let body = l.body().map(|body| self.rewrite(body, f));
+
+                if unchanged(lo, l.lo_bound())
+                    && unchanged(hi, l.hi_bound())
+                    && unchanged(body, l.body())
+                {
+                    return block;
+                }
+
+                self.arena.alloc(Loop::new(lo, hi, body))
+            }
+            BlockKind::Function(func) => {
+                let stmts: Vec<&dyn BasicBlock> = func
+                    .stmts()
+                    .iter()
+                    .map(|stmt| self.rewrite(*stmt, f))
+                    .collect();
+                let retval = func.retval().map(|retval| self.rewrite(retval, f));
+
+                if stmts.iter().zip(func.stmts()).all(|(a, b)| same(*a, *b))
+                    && unchanged(retval, func.retval())
+                {
+                    return block;
+                }
+
+                function(self.arena, func, stmts, retval)
+            }
+            BlockKind::Call(_) if !self.calls => block,
+            BlockKind::Call(call) => {
+                let target = self.rewrite(call.function(), f);
+
+                match target.kind() {
+                    BlockKind::Function(new) if !same(new, call.function()) => {
+                        self.arena.alloc(Call::new(new, call.args()))
+                    }
+                    _ => block,
+                }
+            }
+            BlockKind::Critical(c) => {
+                let inner = self.rewrite(c.block(), f);
+
+                if same(inner, c.block()) {
+                    return block;
+                }
+
+                self.arena.alloc(Critical::new(inner))
+            }
+            BlockKind::Arithmetic(a) => {
+                let lhs = self.integer(a.lhs(), f);
+                let rhs = self.integer(a.rhs(), f);
+
+                if same(lhs, a.lhs()) && same(rhs, a.rhs()) {
+                    return block;
+                }
+
+                self.arena.alloc(Arithmetic::new(a.op(), lhs, rhs))
+            }
+            BlockKind::Compare(c) => {
+                let lhs = self.integer(c.lhs(), f);
+                let rhs = self.integer(c.rhs(), f);
+
+                if same(lhs, c.lhs()) && same(rhs, c.rhs()) {
+                    return block;
+                }
+
+                self.arena.alloc(Compare::new(c.op(), lhs, rhs))
+            }
+            BlockKind::Boolean(_) | BlockKind::Number(_) | BlockKind::Str(_) | BlockKind::Other => {
+                block
+            }
+        }
+    }
+
+    /// Rewrite a block whose integer value is used: a loop bound or an
+    /// operand. `Number` and `Arithmetic` blocks count as their value, while
+    /// any other block counts as `0` or `1`: such a block rewritten to a
+    /// `Number` is replaced by the `Boolean` it evaluates to, and rewritten to
+    /// an `Arithmetic` block is still executed, but counts as `1`
+    fn integer<F>(&mut self, block: &'block dyn BasicBlock, f: &mut F) -> &'block dyn BasicBlock
+    where
+        F: FnMut(&'block dyn BasicBlock, &'block dyn BasicBlock) -> &'block dyn BasicBlock,
+    {
+        let new = self.rewrite(block, f);
+
+        match (block.kind(), new.kind()) {
+            (BlockKind::Number(_) | BlockKind::Arithmetic(_), _) => new,
+            (_, BlockKind::Number(_)) => self.arena.alloc(Boolean::new(new.interpret())),
+            (_, BlockKind::Arithmetic(_)) => {
+                let t = self.arena.alloc(Boolean::new(true));
+
+                self.arena.alloc(IfElse::new(new, t, None))
+            }
+            _ => new,
+        }
+    }
+}
+
+/// Allocate a new function in the arena, with the arguments and attributes
+/// of `template`
+pub(crate) fn function<'block>(
+    arena: &'block Arena,
+    template: &Function<'block>,
+    stmts: Vec<&'block dyn BasicBlock>,
+    retval: Option<&'block dyn BasicBlock>,
+) -> &'block Function<'block> {
+    let function = arena.alloc(Function::new(template.args(), arena.alloc(stmts)));
+    function.set_inline(template.inline());
+
+    if let Some(retval) = retval {
+        function.set_retval(retval);
+    }
+
+    function
+}
+
+/// Copy a tree of blocks into the arena, giving each block of the copy a new
+/// label. Blocks shared in the tree are shared in the copy. Calls in the tree
+/// are copied but keep calling the same functions, and blocks which are not
+/// part of `stir` are kept as they are
+pub(crate) fn duplicate<'block>(
+    arena: &'block Arena,
+    block: &'block dyn BasicBlock,
+) -> &'block dyn BasicBlock {
+    let mut rewriter = Rewriter::new(arena);
+    rewriter.set_calls(false);
+
+    // Blocks whose children changed are already copies
+    rewriter.rewrite(
+        block,
+        &mut |original, rebuilt| match same(original, rebuilt) {
+            true => copy(arena, rebuilt),
+            false => rebuilt,
+        },
+    )
+}
+
+/// Copy a block into the arena, with the same children
+fn copy<'block>(arena: &'block Arena, block: &'block dyn BasicBlock) -> &'block dyn BasicBlock {
+    match block.kind() {
+        BlockKind::Boolean(b) => arena.alloc(Boolean::new(b.get())),
+        BlockKind::Number(n) => arena.alloc(Number::new(n.get())),
+        BlockKind::Str(s) => arena.alloc(Str::new(s.get())),
+        BlockKind::IfElse(ie) => {
+            arena.alloc(IfElse::new(ie.cond_block(), ie.t_block(), ie.f_block()))
+        }
+        BlockKind::Loop(l) => arena.alloc(Loop::new(l.lo_bound(), l.hi_bound(), l.body())),
+        BlockKind::Function(f) => function(arena, f, f.stmts().clone(), f.retval()),
+        BlockKind::Call(c) => arena.alloc(Call::new(c.function(), c.args())),
+        BlockKind::Critical(c) => arena.alloc(Critical::new(c.block())),
+        BlockKind::Arithmetic(a) => arena.alloc(Arithmetic::new(a.op(), a.lhs(), a.rhs())),
+        BlockKind::Compare(c) => arena.alloc(Compare::new(c.op(), c.lhs(), c.rhs())),
+        BlockKind::Other => block,
+    }
+}
+
+/// Return `true` if an optional child was not rewritten
+fn unchanged(new: Option<&dyn BasicBlock>, old: Option<&dyn BasicBlock>) -> bool {
+    match (new, old) {
+        (Some(new), Some(old)) => same(new, old),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{ArithOp, CompareOp, Number};
+
+    #[test]
+    fn identity() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let stmts: Vec<&dyn BasicBlock> = vec![&b];
+        let func = Function::new(None, &stmts);
+        let call = Call::new(&func, None);
+        let crit = Critical::new(&call);
+
+        let new = Rewriter::new(&arena).rewrite(&crit, &mut |_, block| block);
+
+        assert!(same(new, &crit));
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn shared_blocks_rewritten_once() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let c0 = Critical::new(&b);
+        let c1 = Critical::new(&b);
+        let ie = IfElse::new(&c0, &c1, None);
+
+        let f = Boolean::new(false);
+        let mut calls = 0;
+
+        let new = Rewriter::new(&arena).rewrite(&ie, &mut |_, block| match block.kind() {
+            BlockKind::Boolean(_) => {
+                calls += 1;
+                &f
+            }
+            _ => block,
+        });
+
+        // IfElse and both Critical blocks are copied
+        assert_eq!(calls, 1);
+        assert_eq!(arena.len(), 3);
+        assert!(!new.interpret());
+    }
+
+    #[test]
+    fn duplicated() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let stmts: Vec<&dyn BasicBlock> = vec![&crit, &crit];
+        let mut callee = Function::new(None, &stmts);
+        callee.set_retval(&b);
+        let call = Call::new(&callee, None);
+        let ie = IfElse::new(&call, &crit, None);
+
+        let copy = duplicate(&arena, &ie);
+        let copy = match copy.kind() {
+            BlockKind::IfElse(copy) => copy,
+            _ => unreachable!(),
+        };
+
+        assert_ne!(copy.label(), ie.label());
+        assert_ne!(copy.t_block().label(), crit.label());
+        match (copy.cond_block().kind(), copy.t_block().kind()) {
+            (BlockKind::Call(c), BlockKind::Critical(t)) => {
+                assert_ne!(c.label(), call.label());
+                assert!(same(c.function(), &callee));
+                assert_ne!(t.block().label(), b.label());
+            }
+            _ => unreachable!(),
+        }
+
+        // IfElse, Call, Critical and Boolean
+        assert_eq!(arena.len(), 4);
+        assert!(copy.interpret());
+    }
+
+    #[test]
+    fn bounds_stay_booleans() {
+        let arena = Arena::new();
+
+        let lo = Number::new(0.0);
+        let c = Boolean::new(true);
+        let t = Number::new(5.0);
+        let hi = IfElse::new(&c, &t, None);
+        let l = Loop::new(Some(&lo), Some(&hi), None);
+
+        let new = Rewriter::new(&arena).rewrite(&l, &mut |_, block| match block.kind() {
+            BlockKind::IfElse(ie) => ie.t_block(),
+            _ => block,
+        });
+
+        match new.kind() {
+            BlockKind::Loop(l) => assert_eq!(l.trip_count(), Some(1)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn operands_stay_booleans() {
+        let arena = Arena::new();
+
+        let c = Boolean::new(true);
+        let two = Number::new(2.0);
+        let sum = Arithmetic::new(ArithOp::Add, &two, &two);
+        let lhs = IfElse::new(&c, &sum, None);
+        let cmp = Compare::new(CompareOp::Lt, &lhs, &two);
+
+        assert!(cmp.interpret());
+
+        let new = Rewriter::new(&arena).rewrite(&cmp, &mut |_, block| match block.kind() {
+            BlockKind::IfElse(ie) => ie.t_block(),
+            _ => block,
+        });
+
+        // The sum is still executed, but counts as `1`
+        match new.kind() {
+            BlockKind::Compare(c) => assert!(matches!(c.lhs().kind(), BlockKind::IfElse(_))),
+            _ => unreachable!(),
+        }
+        assert!(new.interpret());
+    }
+}
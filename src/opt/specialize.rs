@@ -0,0 +1,267 @@
+//! Function specialization clones the functions called with constant
+//! arguments, and folds the clone once for all of these calls.
+
+use std::collections::HashMap;
+
+use super::rewrite::{address, Rewriter};
+use super::{ConstantFolding, Pass, Remark};
+
+use crate::blocks::{BasicBlock, BlockKind, Call, Function};
+use crate::recipe::{Arena, Recipe};
+
+/// Return a copy of `f` specialized for constant arguments, or `None` if
+/// specializing it does not change anything
+fn specialize<'block>(
+    f: &'block Function<'block>,
+    arena: &'block Arena,
+) -> Option<&'block Function<'block>> {
+    let mut recipe = Recipe::new();
+    recipe.add_entry(f);
+
+    if !ConstantFolding::new().run(&mut recipe, arena) {
+        return None;
+    }
+
+    let folded = match recipe.entry()?.kind() {
+        BlockKind::Function(folded) => folded,
+        _ => return None,
+    };
+
+    // The arguments are bound to the constants: the clone has no parameter
+    let clone = arena.alloc(Function::new(None, folded.stmts()));
+    clone.set_inline(folded.inline());
+
+    if let Some(retval) = folded.retval() {
+        clone.set_retval(retval);
+    }
+
+    Some(clone)
+}
+
+/// Redirects the calls whose arguments are all primitives to a copy of the
+/// function they call, specialized for these arguments and folded.
+///
+/// Calls do not bind their arguments to the parameters of the function they
+/// call, so the function has nothing to be specialized for but its own
+/// constants: every call to the same function with primitive arguments
+/// shares a single specialization, whatever the values of the arguments.
+/// Once `ConstantFolding` ran, the pass is therefore a no-op: it is not part
+/// of any `OptLevel` preset until calls pass their arguments.
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{BasicBlock, BlockKind, Boolean, Call, Critical, Function, IfElse};
+/// use stir::opt::{FunctionSpecialization, Pass};
+/// use stir::recipe::{Arena, Recipe};
+///
+/// let c = Boolean::new(true);
+/// let t = Boolean::new(false);
+/// let crit = Critical::new(&t);
+/// let ie = IfElse::new(&c, &crit, None);
+/// let stmts: Vec<&dyn BasicBlock> = vec![&ie];
+/// let callee = Function::new(None, &stmts);
+///
+/// let arg = Boolean::new(true);
+/// let args: Vec<&dyn BasicBlock> = vec![&arg];
+/// let call = Call::new(&callee, Some(&args));
+///
+/// let arena = Arena::new();
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&call);
+///
+/// let mut specialization = FunctionSpecialization::new();
+///
+/// assert!(specialization.run(&mut recipe, &arena));
+/// assert_eq!(specialization.specialized(), [call.label().as_str()]);
+///
+/// match recipe.entry().unwrap().kind() {
+///     BlockKind::Call(c) => assert!(c.args().is_none()),
+///     _ => unreachable!(),
+/// }
+/// ```
+pub struct FunctionSpecialization {
+    specialized: Vec<String>,
+    remarks: Vec<Remark>,
+}
+
+impl FunctionSpecialization {
+    /// Create a new FunctionSpecialization pass
+    pub fn new() -> FunctionSpecialization {
+        FunctionSpecialization {
+            specialized: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Return the labels of the calls redirected to a specialization during
+    /// the last run
+    pub fn specialized(&self) -> Vec<&str> {
+        self.specialized.iter().map(String::as_str).collect()
+    }
+}
+
+impl Default for FunctionSpecialization {
+    fn default() -> Self {
+        FunctionSpecialization::new()
+    }
+}
+
+impl Pass for FunctionSpecialization {
+    fn name(&self) -> &str {
+        "specialize"
+    }
+
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+        let name = self.name().to_string();
+        let mut specialized = Vec::new();
+        let mut remarks = Vec::new();
+        let mut cache: HashMap<*const (), Option<&'block Function<'block>>> = HashMap::new();
+
+        let mut f = |original: &'block dyn BasicBlock, block: &'block dyn BasicBlock| {
+            let call = match block.kind() {
+                BlockKind::Call(call) => call,
+                _ => return block,
+            };
+
+            let constants = match call.args() {
+                Some(args) => !args.is_empty() && args.iter().all(|arg| arg.kind().is_primitive()),
+                None => false,
+            };
+            if !constants {
+                return block;
+            }
+
+            let specialization = *cache
+                .entry(address(call.function()))
+                .or_insert_with(|| specialize(call.function(), arena));
+
+            match specialization {
+                Some(function) => {
+                    specialized.push(original.label().clone());
+                    remarks.push(Remark::applied(
+                        &name,
+                        original.label(),
+                        "redirected to a specialization for its constant arguments",
+                    ));
+
+                    arena.alloc(Call::new(function, None))
+                }
+                None => {
+                    remarks.push(Remark::missed(
+                        &name,
+                        original.label(),
+                        "folding the function for these constant arguments changes nothing",
+                    ));
+
+                    block
+                }
+            }
+        };
+
+        let changed = Rewriter::new(arena).rewrite_recipe(recipe, &mut f);
+        self.specialized = specialized;
+        self.remarks = remarks;
+
+        changed
+    }
+
+    fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Critical, IfElse, Number, Str};
+
+    #[test]
+    fn cached_per_function() {
+        let arena = Arena::new();
+
+        let c = Boolean::new(false);
+        let t = Boolean::new(true);
+        let crit = Critical::new(&t);
+        let ie = IfElse::new(&c, &t, Some(&crit));
+        let stmts: Vec<&dyn BasicBlock> = vec![&ie];
+        let mut callee = Function::new(None, &stmts);
+        callee.set_retval(&ie);
+
+        let one = Number::new(1.0);
+        let other_one = Number::new(1.0);
+        let two = Number::new(2.0);
+        let args0: Vec<&dyn BasicBlock> = vec![&one];
+        let args1: Vec<&dyn BasicBlock> = vec![&other_one];
+        let args2: Vec<&dyn BasicBlock> = vec![&two];
+
+        let c0 = Call::new(&callee, Some(&args0));
+        let c1 = Call::new(&callee, Some(&args1));
+        let c2 = Call::new(&callee, Some(&args2));
+        let caller_stmts: Vec<&dyn BasicBlock> = vec![&c0, &c1, &c2];
+        let mut caller = Function::new(None, &caller_stmts);
+        caller.set_retval(&c2);
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&caller);
+
+        let expected = recipe.fry();
+
+        let mut specialization = FunctionSpecialization::new();
+
+        assert!(specialization.run(&mut recipe, &arena));
+        assert_eq!(specialization.specialized().len(), 3);
+        assert_eq!(recipe.fry(), expected);
+
+        let targets: Vec<*const ()> = match recipe.entry().unwrap().kind() {
+            BlockKind::Function(f) => f
+                .stmts()
+                .iter()
+                .map(|stmt| match stmt.kind() {
+                    BlockKind::Call(c) => address(c.function()),
+                    _ => unreachable!(),
+                })
+                .collect(),
+            _ => unreachable!(),
+        };
+
+        // Arguments are not bound: the specialization is the same for all
+        assert_eq!(targets[0], targets[1]);
+        assert_eq!(targets[0], targets[2]);
+        assert_ne!(targets[0], address(&callee));
+    }
+
+    #[test]
+    fn kept() {
+        let arena = Arena::new();
+
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let stmts: Vec<&dyn BasicBlock> = vec![&crit];
+        let callee = Function::new(None, &stmts);
+
+        // Nothing to fold
+        let s = Str::new(String::from("stir"));
+        let args: Vec<&dyn BasicBlock> = vec![&s];
+        let c0 = Call::new(&callee, Some(&args));
+
+        // Arguments which are not constants
+        let ie = IfElse::new(&b, &b, None);
+        let foldable_stmts: Vec<&dyn BasicBlock> = vec![&ie];
+        let foldable = Function::new(None, &foldable_stmts);
+        let dynamic: Vec<&dyn BasicBlock> = vec![&crit];
+        let c1 = Call::new(&foldable, Some(&dynamic));
+        let c2 = Call::new(&foldable, None);
+
+        let mut recipe = Recipe::new();
+        recipe.add(&c0);
+        recipe.add(&c1);
+        recipe.add(&c2);
+
+        let mut specialization = FunctionSpecialization::new();
+
+        assert!(!specialization.run(&mut recipe, &arena));
+        assert!(specialization.specialized().is_empty());
+    }
+}
@@ -0,0 +1,380 @@
+//! Loop unrolling replaces loops whose bounds are known with copies of their
+//! body, saving the bookkeeping of each iteration.
+
+use super::rewrite::Rewriter;
+use super::{Pass, Remark};
+
+use crate::analysis::is_pure;
+use crate::blocks::{BasicBlock, BlockKind, Boolean, Function, IfElse, Loop, Number};
+use crate::recipe::{Arena, Recipe};
+
+/// Maximum number of iterations of a loop fully unrolled
+pub const DEFAULT_FULL_UNROLL: u64 = 8;
+
+/// Number of iterations executed by each iteration of a partially unrolled
+/// loop
+pub const DEFAULT_UNROLL_FACTOR: u64 = 4;
+
+/// Blocks used to build unrolled loops
+struct Builder<'block> {
+    arena: &'block Arena,
+    pure: bool,
+    false_block: Option<&'block dyn BasicBlock>,
+}
+
+impl<'block> Builder<'block> {
+    /// Return the conjunction of `a` and `b`, evaluating both of them
+    fn and(
+        &mut self,
+        a: &'block dyn BasicBlock,
+        b: &'block dyn BasicBlock,
+    ) -> &'block dyn BasicBlock {
+        let arena = self.arena;
+
+        // When `a` is false, `b` is only evaluated for its side effects: a
+        // function without return value evaluates to false
+        let otherwise: &dyn BasicBlock = match self.pure {
+            true => *self
+                .false_block
+                .get_or_insert_with(|| arena.alloc(Boolean::new(false))),
+            false => arena.alloc(Function::new(None, arena.alloc(vec![b]))),
+        };
+
+        arena.alloc(IfElse::new(a, b, Some(otherwise)))
+    }
+
+    /// Return a block evaluating `body` `count` times, and evaluating to the
+    /// conjunction of its values. `count` must not be zero
+    fn repeat(&mut self, body: &'block dyn BasicBlock, count: u64) -> &'block dyn BasicBlock {
+        (1..count).fold(body, |rest, _| self.and(body, rest))
+    }
+}
+
+/// Fully unrolls loops with a few iterations, and partially unrolls the other
+/// loops whose bounds are primitives: each iteration of the unrolled loop
+/// executes several iterations of the original one.
+///
+/// Blocks do not have access to the induction variable of the loop, so the
+/// copies of the body are the body itself.
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{BasicBlock, BlockKind, Boolean, Critical, Loop, Number};
+/// use stir::opt::{LoopUnrolling, Pass};
+/// use stir::recipe::{Arena, Recipe};
+///
+/// let lo = Number::new(0.0);
+/// let hi = Number::new(3.0);
+/// let b = Boolean::new(true);
+/// let body = Critical::new(&b);
+/// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+///
+/// let arena = Arena::new();
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&l);
+///
+/// assert!(LoopUnrolling::new().run(&mut recipe, &arena));
+/// assert!(matches!(recipe.entry().unwrap().kind(), BlockKind::IfElse(_)));
+/// assert_eq!(recipe.fry(), Ok(true));
+/// ```
+pub struct LoopUnrolling {
+    full_unroll: u64,
+    factor: u64,
+    unrolled: Vec<String>,
+    remarks: Vec<Remark>,
+}
+
+impl LoopUnrolling {
+    /// Create a new LoopUnrolling pass, fully unrolling loops of up to
+    /// `DEFAULT_FULL_UNROLL` iterations and partially unrolling the other ones
+    /// by `DEFAULT_UNROLL_FACTOR`
+    pub fn new() -> LoopUnrolling {
+        LoopUnrolling {
+            full_unroll: DEFAULT_FULL_UNROLL,
+            factor: DEFAULT_UNROLL_FACTOR,
+            unrolled: Vec::new(),
+            remarks: Vec::new(),
+        }
+    }
+
+    /// Set the maximum number of iterations of a loop fully unrolled
+    pub fn set_full_unroll(&mut self, full_unroll: u64) {
+        self.full_unroll = full_unroll;
+    }
+
+    /// Set the number of iterations executed by each iteration of a partially
+    /// unrolled loop. A factor of `0` or `1` disables partial unrolling
+    pub fn set_factor(&mut self, factor: u64) {
+        self.factor = factor;
+    }
+
+    /// Return the labels of the loops unrolled during the last run
+    pub fn unrolled(&self) -> Vec<&str> {
+        self.unrolled.iter().map(String::as_str).collect()
+    }
+}
+
+impl Default for LoopUnrolling {
+    fn default() -> Self {
+        LoopUnrolling::new()
+    }
+}
+
+impl Pass for LoopUnrolling {
+    fn name(&self) -> &str {
+        "unroll"
+    }
+
+    fn run<'block>(&mut self, recipe: &mut Recipe<'block>, arena: &'block Arena) -> bool {
+        let name = self.name().to_string();
+        let full_unroll = self.full_unroll;
+        let factor = self.factor;
+
+        let mut unrolled = Vec::new();
+        let mut remarks = Vec::new();
+        let mut pure = Builder {
+            arena,
+            pure: true,
+            false_block: None,
+        };
+        let mut impure = Builder {
+            arena,
+            pure: false,
+            false_block: None,
+        };
+
+        let mut f = |original: &'block dyn BasicBlock, block: &'block dyn BasicBlock| {
+            let (l, body) = match block.kind() {
+                BlockKind::Loop(l) => match l.body() {
+                    Some(body) => (l, body),
+                    None => return block,
+                },
+                _ => return block,
+            };
+
+            let trip_count = match l.trip_count() {
+                Some(0) => return block,
+                Some(trip_count) => trip_count,
+                None => {
+                    let reason = match l.lo_bound().is_some() && l.hi_bound().is_some() {
+                        true => "the bounds are not primitives, the trip count is unknown",
+                        false => "the loop is infinite",
+                    };
+                    remarks.push(Remark::missed(&name, original.label(), reason));
+
+                    return block;
+                }
+            };
+
+            let builder = match is_pure(body) {
+                true => &mut pure,
+                false => &mut impure,
+            };
+
+            if trip_count <= full_unroll {
+                unrolled.push(original.label().clone());
+                remarks.push(Remark::applied(
+                    &name,
+                    original.label(),
+                    format!("fully unrolled {} iterations", trip_count),
+                ));
+
+                return builder.repeat(body, trip_count);
+            }
+
+            if factor <= 1 {
+                remarks.push(Remark::missed(
+                    &name,
+                    original.label(),
+                    format!(
+                        "{} iterations, above the full unroll limit of {}, without partial unrolling",
+                        trip_count, full_unroll
+                    ),
+                ));
+
+                return block;
+            }
+
+            unrolled.push(original.label().clone());
+            remarks.push(Remark::applied(
+                &name,
+                original.label(),
+                format!(
+                    "unrolled {} iterations by a factor of {}",
+                    trip_count, factor
+                ),
+            ));
+
+            let lo = arena.alloc(Number::new(0.0));
+            let hi = arena.alloc(Number::new((trip_count / factor) as f64));
+            let unrolled_body = builder.repeat(body, factor);
+            let unrolled_loop = arena.alloc(Loop::new(Some(lo), Some(hi), Some(unrolled_body)));
+
+            match trip_count % factor {
+                0 => unrolled_loop,
+                remainder => {
+                    let remainder = builder.repeat(body, remainder);
+                    builder.and(unrolled_loop, remainder)
+                }
+            }
+        };
+
+        let changed = Rewriter::new(arena).rewrite_recipe(recipe, &mut f);
+        self.unrolled = unrolled;
+        self.remarks = remarks;
+
+        changed
+    }
+
+    fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use crate::blocks::{ArithOp, Arithmetic, Critical};
+
+    /// Block counting its evaluations, returning `false` on the third one
+    #[derive(Debug)]
+    struct Counter(String, AtomicU64);
+
+    impl Counter {
+        fn new() -> Counter {
+            Counter(String::from("__counter_0"), AtomicU64::new(0))
+        }
+
+        fn take(&self) -> u64 {
+            self.1.swap(0, Ordering::SeqCst)
+        }
+    }
+
+    impl BasicBlock for Counter {
+        fn label(&self) -> &String {
+            &self.0
+        }
+
+        fn output(&self) -> String {
+            String::from("counter")
+        }
+
+        fn interpret(&self) -> bool {
+            self.1.fetch_add(1, Ordering::SeqCst) != 2
+        }
+    }
+
+    fn check(trip_count: u64, full_unroll: u64, factor: u64) {
+        let arena = Arena::new();
+
+        let counter = Counter::new();
+        let lo = Number::new(3.0);
+        let hi = Number::new((3 + trip_count) as f64);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&counter));
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&l);
+
+        let expected = recipe.fry();
+        assert_eq!(counter.take(), trip_count);
+
+        let mut pass = LoopUnrolling::new();
+        pass.set_full_unroll(full_unroll);
+        pass.set_factor(factor);
+
+        assert!(pass.run(&mut recipe, &arena));
+        assert_eq!(pass.unrolled(), [l.label().as_str()]);
+
+        assert_eq!(recipe.fry(), expected);
+        assert_eq!(counter.take(), trip_count);
+    }
+
+    #[test]
+    fn full() {
+        check(1, 8, 4);
+        check(2, 8, 4);
+        check(8, 8, 4);
+    }
+
+    #[test]
+    fn partial() {
+        check(9, 8, 4);
+        check(12, 8, 4);
+        check(100, 0, 3);
+    }
+
+    #[test]
+    fn pure_body() {
+        let arena = Arena::new();
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(10.0);
+        let c = Boolean::new(true);
+        let t = Boolean::new(false);
+        let body = IfElse::new(&c, &t, None);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&l);
+
+        assert!(LoopUnrolling::new().run(&mut recipe, &arena));
+        assert_eq!(recipe.fry(), Ok(false));
+    }
+
+    #[test]
+    fn arithmetic_bound() {
+        let arena = Arena::new();
+
+        // loop 0..(loop 0..1 { 5 + 5 }) { counter } runs once
+        let counter = Counter::new();
+        let zero = Number::new(0.0);
+        let one = Number::new(1.0);
+        let five = Number::new(5.0);
+        let sum = Arithmetic::new(ArithOp::Add, &five, &five);
+        let hi = Loop::new(Some(&zero), Some(&one), Some(&sum));
+        let l = Loop::new(Some(&zero), Some(&hi), Some(&counter));
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&l);
+
+        recipe.fry().unwrap();
+        assert_eq!(counter.take(), 1);
+
+        let mut pass = LoopUnrolling::new();
+
+        assert!(pass.run(&mut recipe, &arena));
+        assert_eq!(pass.unrolled(), [hi.label().as_str()]);
+
+        recipe.fry().unwrap();
+        assert_eq!(counter.take(), 1);
+    }
+
+    #[test]
+    fn kept() {
+        let arena = Arena::new();
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(100.0);
+        let b = Boolean::new(true);
+        let crit = Critical::new(&b);
+        let empty = Loop::new(Some(&lo), Some(&hi), None);
+        let infinite = Loop::new(None, None, Some(&crit));
+        let zero = Loop::new(Some(&hi), Some(&lo), Some(&crit));
+        let long = Loop::new(Some(&lo), Some(&hi), Some(&crit));
+
+        let mut recipe = Recipe::new();
+        recipe.add(&empty);
+        recipe.add(&infinite);
+        recipe.add(&zero);
+        recipe.add(&long);
+
+        let mut pass = LoopUnrolling::new();
+        pass.set_factor(1);
+
+        assert!(!pass.run(&mut recipe, &arena));
+    }
+}
@@ -0,0 +1,161 @@
+//! The verifier checks the invariants of a `Recipe`. Run it after a pass to
+//! catch transformations producing malformed recipes.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::rewrite::address;
+
+use crate::recipe::Recipe;
+
+/// Invariant of a `Recipe` broken by a transformation
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    /// The entry block with the given label is not part of the recipe
+    EntryNotInRecipe(String),
+
+    /// A block of the recipe is not registered under its own label
+    LabelMismatch(String),
+
+    /// Two different blocks reachable from the recipe share the same label
+    DuplicateLabel(String),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::EntryNotInRecipe(label) => {
+                write!(f, "entry block {} is not part of the recipe", label)
+            }
+            VerifyError::LabelMismatch(label) => {
+                write!(f, "block {} is registered under another label", label)
+            }
+            VerifyError::DuplicateLabel(label) => {
+                write!(f, "label {} is shared by different blocks", label)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Check the invariants of a recipe: its entry is part of it, its blocks are
+/// registered under their own label, and labels are unique among all the
+/// blocks reachable from the recipe
+///
+/// # Example
+///
+/// ```
+/// use stir::blocks::{Boolean, IfElse};
+/// use stir::opt::verify;
+/// use stir::recipe::Recipe;
+///
+/// let c = Boolean::new(true);
+/// let t = Boolean::new(false);
+/// let ie = IfElse::new(&c, &t, None);
+///
+/// let mut recipe = Recipe::new();
+/// recipe.add_entry(&ie);
+///
+/// assert_eq!(verify(&recipe), Ok(()));
+/// ```
+pub fn verify(recipe: &Recipe) -> Result<(), VerifyError> {
+    if let Some(entry) = recipe.entry() {
+        match recipe.get(entry.label()) {
+            Some(block) if address(block) == address(entry) => (),
+            _ => return Err(VerifyError::EntryNotInRecipe(entry.label().clone())),
+        }
+    }
+
+    let mut seen = HashMap::new();
+    let mut worklist = Vec::new();
+
+    for block in recipe.blocks() {
+        if recipe.get(block.label()).map(address) != Some(address(block)) {
+            return Err(VerifyError::LabelMismatch(block.label().clone()));
+        }
+
+        worklist.push(block);
+    }
+
+    while let Some(block) = worklist.pop() {
+        match seen.insert(block.label().as_str(), address(block)) {
+            Some(other) if other != address(block) => {
+                return Err(VerifyError::DuplicateLabel(block.label().clone()))
+            }
+            Some(_) => (),
+            None => worklist.extend(block.kind().children()),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{BasicBlock, Boolean, Critical};
+
+    /// Block reusing the label of another block
+    #[derive(Debug)]
+    struct Impostor(String);
+
+    impl BasicBlock for Impostor {
+        fn label(&self) -> &String {
+            &self.0
+        }
+
+        fn output(&self) -> String {
+            String::from("impostor")
+        }
+
+        fn interpret(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn shared_block() {
+        let b = Boolean::new(true);
+        let c0 = Critical::new(&b);
+        let c1 = Critical::new(&b);
+
+        let mut recipe = Recipe::new();
+        recipe.add(&c0);
+        recipe.add(&c1);
+
+        assert_eq!(verify(&recipe), Ok(()));
+    }
+
+    #[test]
+    fn duplicate_label() {
+        let b = Boolean::new(true);
+        let impostor = Impostor(b.label().clone());
+        let c = Critical::new(&impostor);
+
+        let mut recipe = Recipe::new();
+        recipe.add(&b);
+        recipe.add(&c);
+
+        assert_eq!(
+            verify(&recipe),
+            Err(VerifyError::DuplicateLabel(b.label().clone()))
+        );
+    }
+
+    #[test]
+    fn entry_replaced_in_recipe() {
+        let b = Boolean::new(true);
+        let impostor = Impostor(b.label().clone());
+
+        let mut recipe = Recipe::new();
+        recipe.add_entry(&b);
+        recipe.add(&impostor);
+
+        assert_eq!(
+            verify(&recipe),
+            Err(VerifyError::EntryNotInRecipe(b.label().clone()))
+        );
+    }
+}
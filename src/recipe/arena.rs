@@ -0,0 +1,164 @@
+//! An `Arena` owns the blocks created while transforming a `Recipe`. Blocks
+//! only hold references to each other: a pass creating new blocks needs them
+//! to live as long as the blocks they replace.
+
+use std::cell::RefCell;
+
+use crate::blocks::{
+    Arithmetic, BasicBlock, Boolean, Call, Compare, Critical, Function, IfElse, Loop, Number, Str,
+};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Values that can be allocated in an `Arena`: the blocks provided by `stir`
+/// and the vectors of blocks used by `Function` and `Call`. Their destructors
+/// never read the blocks they reference, which allows the arena to outlive
+/// them.
+pub trait Allocate: private::Sealed {}
+
+macro_rules! allocate {
+    ($($t:ty),*) => {
+        $(
+            impl private::Sealed for $t {}
+            impl Allocate for $t {}
+        )*
+    };
+}
+
+allocate!(
+    Boolean,
+    Number,
+    Str,
+    IfElse<'_>,
+    Loop<'_>,
+    Function<'_>,
+    Call<'_>,
+    Critical<'_>,
+    Arithmetic<'_>,
+    Compare<'_>,
+    Vec<&dyn BasicBlock>
+);
+
+/// Value owned by an arena, along with the function releasing it
+struct Allocation {
+    value: *mut (),
+    release: unsafe fn(*mut ()),
+}
+
+/// Release a value allocated by `Arena::alloc`
+///
+/// # Safety
+///
+/// `value` must come from `Box::<T>::into_raw` and must not be used again
+unsafe fn release<T>(value: *mut ()) {
+    drop(unsafe { Box::from_raw(value as *mut T) });
+}
+
+/// Owner of the blocks created by optimization passes. Allocated blocks are
+/// released all at once, when the arena is dropped
+pub struct Arena {
+    allocations: RefCell<Vec<Allocation>>,
+}
+
+impl Arena {
+    /// Create a new, empty arena
+    pub fn new() -> Arena {
+        Arena {
+            allocations: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Move a value into the arena, and return a reference to it valid as
+    /// long as the arena
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{BasicBlock, Boolean, IfElse};
+    /// use stir::recipe::{Arena, Recipe};
+    ///
+    /// let arena = Arena::new();
+    /// let mut recipe = Recipe::new();
+    ///
+    /// let c = arena.alloc(Boolean::new(true));
+    /// let t = arena.alloc(Boolean::new(false));
+    /// recipe.add_entry(arena.alloc(IfElse::new(c, t, None)));
+    ///
+    /// assert_eq!(arena.len(), 3);
+    /// assert_eq!(recipe.fry(), Ok(false));
+    /// ```
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc<T: Allocate>(&self, value: T) -> &mut T {
+        let value = Box::into_raw(Box::new(value));
+
+        self.allocations.borrow_mut().push(Allocation {
+            value: value as *mut (),
+            release: release::<T>,
+        });
+
+        // The box is only released when the arena is dropped, and its content
+        // never moves
+        unsafe { &mut *value }
+    }
+
+    /// Return the number of values allocated in the arena
+    pub fn len(&self) -> usize {
+        self.allocations.borrow().len()
+    }
+
+    /// Return `true` if nothing was allocated in the arena
+    pub fn is_empty(&self) -> bool {
+        self.allocations.borrow().is_empty()
+    }
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        for allocation in self.allocations.get_mut().drain(..) {
+            unsafe { (allocation.release)(allocation.value) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alloc() {
+        let arena = Arena::new();
+        assert!(arena.is_empty());
+
+        let b = arena.alloc(Boolean::new(true));
+        let stmts = arena.alloc(vec![b as &dyn BasicBlock, b]);
+        let f = arena.alloc(Function::new(None, stmts));
+        f.set_retval(b);
+
+        assert_eq!(arena.len(), 3);
+        assert!(f.interpret());
+    }
+
+    #[test]
+    fn outlive_blocks() {
+        let arena = Arena::new();
+
+        let value;
+        {
+            // Dropped before the arena, which still owns a block referencing it
+            let b = Boolean::new(true);
+            let c = arena.alloc(Critical::new(&b));
+
+            value = c.interpret();
+        }
+
+        assert!(value);
+    }
+}
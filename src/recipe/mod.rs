@@ -1,14 +1,20 @@
 //! A `Recipe` is a collection of blocks. Use it to build your program
 //! and run passes on it. You can also execute code from a `Recipe`.
 
-use std::collections::HashMap;
+mod arena;
+
+pub use arena::{Allocate, Arena};
+
+use std::collections::BTreeMap;
 
 use crate::blocks::BasicBlock;
+use crate::executor::{Engine, Executor, FryOptions, InterpreterError, Tracer};
+use crate::vm::{Program, Vm, DEFAULT_JIT_THRESHOLD};
 
 /// BasicBlock collection
 pub struct Recipe<'block> {
     entry: Option<&'block dyn BasicBlock>,
-    blocks: HashMap<&'block String, &'block dyn BasicBlock>,
+    blocks: BTreeMap<&'block str, &'block dyn BasicBlock>,
 }
 
 impl<'block> Recipe<'block> {
@@ -16,7 +22,7 @@ impl<'block> Recipe<'block> {
     pub fn new() -> Recipe<'block> {
         Recipe {
             entry: None,
-            blocks: HashMap::new(),
+            blocks: BTreeMap::new(),
         }
     }
 
@@ -24,7 +30,7 @@ impl<'block> Recipe<'block> {
     /// the entry point.
     ///
     /// ```
-    /// use stir::blocks::Boolean;
+    /// use stir::blocks::{BasicBlock, Boolean};
     /// use stir::recipe::Recipe;
     ///
     /// let b = Boolean::new(false);
@@ -32,14 +38,63 @@ impl<'block> Recipe<'block> {
     /// let mut recipe = Recipe::new();
     /// recipe.add(&b);
     ///
-    // FIXME: Content: add assert!(recipe.contains(b.label()));
+    /// assert!(recipe.contains(b.label()));
     /// ```
-    pub fn add(&mut self, block: &'block dyn BasicBlock) -> &Recipe {
+    pub fn add(&mut self, block: &'block dyn BasicBlock) -> &Recipe<'block> {
         self.blocks.insert(block.label(), block);
 
         self
     }
 
+    /// Replace the entry point of the recipe, adding the new entry to the
+    /// recipe. The previous entry, if any, stays in the recipe
+    ///
+    /// ```
+    /// use stir::blocks::{BasicBlock, Boolean};
+    /// use stir::recipe::Recipe;
+    ///
+    /// let f = Boolean::new(false);
+    /// let t = Boolean::new(true);
+    ///
+    /// let mut recipe = Recipe::new();
+    /// recipe.add_entry(&f);
+    /// recipe.set_entry(&t);
+    ///
+    /// assert_eq!(recipe.fry(), Ok(true));
+    /// assert!(recipe.contains(f.label()));
+    /// ```
+    pub fn set_entry(&mut self, entry: &'block dyn BasicBlock) {
+        self.entry = Some(entry);
+        self.add(entry);
+    }
+
+    /// Remove the block with the given label from the recipe, and return it.
+    /// Removing the entry leaves the recipe without entry point
+    pub fn remove(&mut self, label: &str) -> Option<&'block dyn BasicBlock> {
+        let block = self.blocks.remove(label)?;
+
+        if self.entry.is_some_and(|entry| entry.label() == label) {
+            self.entry = None;
+        }
+
+        Some(block)
+    }
+
+    /// Return the block with the given label, if it is part of the recipe
+    pub fn get(&self, label: &str) -> Option<&'block dyn BasicBlock> {
+        self.blocks.get(label).copied()
+    }
+
+    /// Return `true` if the block with the given label is part of the recipe
+    pub fn contains(&self, label: &str) -> bool {
+        self.blocks.contains_key(label)
+    }
+
+    /// Return the blocks of the recipe, ordered by label
+    pub fn blocks(&self) -> Vec<&'block dyn BasicBlock> {
+        self.blocks.values().copied().collect()
+    }
+
     /// Set the entry point of the recipe
     // FIXME: Content: Add good example as it's an important function
     pub fn add_entry(&mut self, entry: &'block dyn BasicBlock) -> bool {
@@ -54,10 +109,84 @@ impl<'block> Recipe<'block> {
     }
 
     /// Interpret and execute the recipe
-    pub fn fry(&self) -> Result<bool, ()> {
-        match self.entry {
-            Some(entry_block) => Ok(BasicBlock::interpret(entry_block)),
-            None => Err(()),
+    pub fn fry(&self) -> Result<bool, InterpreterError> {
+        self.fry_with(FryOptions::new())
+    }
+
+    /// Interpret and execute the recipe, recording the execution timeline
+    /// of every block in the given `Tracer`
+    ///
+    /// ```
+    /// use stir::blocks::Boolean;
+    /// use stir::executor::Tracer;
+    /// use stir::recipe::Recipe;
+    ///
+    /// let b = Boolean::new(true);
+    ///
+    /// let mut recipe = Recipe::new();
+    /// recipe.add_entry(&b);
+    ///
+    /// let tracer = Tracer::new();
+    ///
+    /// assert_eq!(recipe.fry_traced(&tracer), Ok(true));
+    /// assert_eq!(tracer.len(), 2);
+    /// ```
+    pub fn fry_traced(&self, tracer: &Tracer) -> Result<bool, InterpreterError> {
+        let mut options = FryOptions::new();
+        options.set_tracer(tracer);
+
+        self.fry_with(options)
+    }
+
+    /// Interpret and execute the recipe using the given options. Set the
+    /// `Engine` of the options to run the recipe on the bytecode virtual
+    /// machine, possibly compiling hot functions to native code. Options the
+    /// virtual machine does not support make the recipe run on the
+    /// interpreter instead: see `FryOptions::requires_interpreter`
+    ///
+    /// ```
+    /// use std::thread;
+    ///
+    /// use stir::blocks::{Boolean, Loop};
+    /// use stir::executor::{CancellationToken, FryOptions, InterpreterError};
+    /// use stir::recipe::Recipe;
+    ///
+    /// let body = Boolean::new(true);
+    /// let infinite = Loop::new(None, None, Some(&body));
+    ///
+    /// let mut recipe = Recipe::new();
+    /// recipe.add_entry(&infinite);
+    ///
+    /// let token = CancellationToken::new();
+    ///
+    /// let mut options = FryOptions::new();
+    /// options.set_threads(4);
+    /// options.set_cancellation(token.clone());
+    ///
+    /// // Abort the run from another thread
+    /// let canceller = thread::spawn(move || token.cancel());
+    ///
+    /// assert_eq!(recipe.fry_with(options), Err(InterpreterError::Cancelled));
+    /// # canceller.join().unwrap();
+    /// ```
+    pub fn fry_with(&self, options: FryOptions) -> Result<bool, InterpreterError> {
+        let entry_block = self.entry.ok_or(InterpreterError::NoEntry)?;
+
+        match options.engine() {
+            Engine::Interpreter => Executor::with_options(options).run(entry_block),
+            _ if options.requires_interpreter() => {
+                Executor::with_options(options).run(entry_block)
+            }
+            engine => {
+                let program = Program::compile(entry_block);
+
+                let mut vm = Vm::new(&program);
+                if engine == Engine::Jit {
+                    vm.enable_jit(DEFAULT_JIT_THRESHOLD);
+                }
+
+                vm.run(&Executor::with_options(options))
+            }
         }
     }
 
@@ -71,13 +200,25 @@ impl<'block> Recipe<'block> {
     pub fn len(&self) -> usize {
         self.blocks.len()
     }
+
+    /// Return `true` if the Recipe does not contain any block
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl Default for Recipe<'_> {
+    fn default() -> Self {
+        Recipe::new()
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants, clippy::single_match)]
 mod tests {
     use super::*;
 
-    use crate::blocks::Boolean;
+    use crate::blocks::{Boolean, Call, Function, Loop, Number};
 
     #[test]
     fn init() {
@@ -120,4 +261,90 @@ mod tests {
 
         assert_eq!(r.len(), 1);
     }
+
+    #[test]
+    fn remove_entry() {
+        let mut r = Recipe::new();
+        let b = Boolean::new(false);
+        let n = Number::new(1.0);
+
+        r.add_entry(&b);
+        r.add(&n);
+
+        assert!(r.remove(n.label()).is_some());
+        assert!(r.remove(n.label()).is_none());
+        assert!(r.entry().is_some());
+
+        assert!(r.remove(b.label()).is_some());
+        assert!(r.entry().is_none());
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn fry_bytecode() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(3.0);
+        let body = Boolean::new(false);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let mut r = Recipe::new();
+        r.add_entry(&l);
+
+        let mut options = FryOptions::new();
+        options.set_engine(Engine::Bytecode);
+
+        assert_eq!(r.fry_with(options.clone()), Ok(false));
+        assert_eq!(r.fry_with(options), r.fry());
+
+        options = FryOptions::new();
+        options.set_engine(Engine::Bytecode);
+        assert_eq!(
+            Recipe::new().fry_with(options),
+            Err(InterpreterError::NoEntry)
+        );
+    }
+
+    #[test]
+    fn fry_bytecode_unsupported_options() {
+        let lo = Number::new(0.0);
+        let hi = Number::new(3.0);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let mut r = Recipe::new();
+        r.add_entry(&l);
+
+        let tracer = Tracer::new();
+        let mut options = FryOptions::new();
+        options.set_engine(Engine::Bytecode);
+        options.set_threads(4);
+        options.set_tracer(&tracer);
+
+        // The interpreter runs the recipe, as the virtual machine has no
+        // tracer
+        assert_eq!(r.fry_with(options), Ok(true));
+        // The loop, then its body at each iteration
+        assert_eq!(tracer.len(), 2 * (1 + 3));
+    }
+
+    #[test]
+    fn fry_jit() {
+        let b = Boolean::new(true);
+        let stmts: Vec<&dyn BasicBlock> = vec![&b];
+        let mut f = Function::new(None, &stmts);
+        f.set_retval(&b);
+
+        let lo = Number::new(0.0);
+        let hi = Number::new(100.0);
+        let call = Call::new(&f, None);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&call));
+
+        let mut r = Recipe::new();
+        r.add_entry(&l);
+
+        let mut options = FryOptions::new();
+        options.set_engine(Engine::Jit);
+
+        assert_eq!(r.fry_with(options), Ok(true));
+    }
 }
@@ -0,0 +1,375 @@
+//! The virtual machine executes blocks compiled to a compact bytecode,
+//! instead of walking the tree of blocks. Compile a block using
+//! `Program::compile`, then run it with a `Vm`.
+//!
+//! The virtual machine runs on a single thread: statements are executed in
+//! order and `IfElse` branches are never evaluated speculatively. It still
+//! honors the cancellation token and the critical section of the
+//! `Executor` it runs with. Blocks which are not part of `stir` are
+//! interpreted through the `Executor`.
+//!
+//! On Linux x86-64, the virtual machine can compile hot functions to native
+//! code using the [`jit`](../jit/index.html).
+
+mod op;
+mod program;
+
+pub use op::Op;
+pub use program::{Chunk, Program};
+
+use crate::executor::{ExecResult, Executor};
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+use crate::jit::Jit;
+
+/// Number of calls after which functions get compiled to native code when
+/// running with `Engine::Jit`
+pub const DEFAULT_JIT_THRESHOLD: u64 = 16;
+
+/// Stack machine running a `Program`
+pub struct Vm<'p, 'block> {
+    program: &'p Program<'block>,
+    stack: Vec<i64>,
+
+    /// Compiles hot functions, if enabled
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    jit: Option<Jit>,
+}
+
+impl<'p, 'block> Vm<'p, 'block> {
+    /// Create a new virtual machine running the given program
+    pub fn new(program: &'p Program<'block>) -> Vm<'p, 'block> {
+        Vm {
+            program,
+            stack: Vec::new(),
+            #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+            jit: None,
+        }
+    }
+
+    /// Compile functions to native code once they have been called
+    /// `threshold` times. Does nothing on platforms without a JIT
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{BasicBlock, Boolean, Call, Function, Loop, Number};
+    /// use stir::executor::Executor;
+    /// use stir::vm::{Program, Vm};
+    ///
+    /// let b = Boolean::new(true);
+    /// let stmts: Vec<&dyn BasicBlock> = vec![&b];
+    /// let mut f = Function::new(None, &stmts);
+    /// f.set_retval(&b);
+    ///
+    /// let lo = Number::new(0.0);
+    /// let hi = Number::new(100.0);
+    /// let call = Call::new(&f, None);
+    /// let l = Loop::new(Some(&lo), Some(&hi), Some(&call));
+    ///
+    /// let program = Program::compile(&l);
+    ///
+    /// let mut vm = Vm::new(&program);
+    /// vm.enable_jit(10);
+    ///
+    /// assert_eq!(vm.run(&Executor::new()), Ok(true));
+    /// ```
+    #[allow(unused_variables)]
+    pub fn enable_jit(&mut self, threshold: u64) {
+        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+        {
+            let mut jit = Jit::new();
+            jit.set_threshold(threshold);
+
+            self.jit = Some(jit);
+        }
+    }
+
+    /// Run the program from its entry point
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{Boolean, Loop, Number};
+    /// use stir::executor::Executor;
+    /// use stir::vm::{Program, Vm};
+    ///
+    /// let lo = Number::new(0.0);
+    /// let hi = Number::new(1000.0);
+    /// let body = Boolean::new(true);
+    /// let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+    ///
+    /// let program = Program::compile(&l);
+    ///
+    /// assert_eq!(Vm::new(&program).run(&Executor::new()), Ok(true));
+    /// ```
+    pub fn run(&mut self, exec: &Executor) -> ExecResult {
+        self.stack.clear();
+
+        self.call(exec, 0)
+    }
+
+    /// Run a chunk, and return its value. Each run of a chunk gets its own
+    /// locals
+    fn call(&mut self, exec: &Executor, index: usize) -> ExecResult {
+        let chunk = &self.program.chunks()[index];
+        let code = chunk.code();
+
+        let mut locals = vec![0; chunk.locals()];
+
+        let mut pc = 0;
+
+        let value = loop {
+            let op = code[pc];
+            pc += 1;
+
+            match op {
+                Op::Const(value) => self.stack.push(value),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Jump(target) => pc = target,
+                Op::JumpIfZero(target) => {
+                    if self.pop() == 0 {
+                        pc = target;
+                    }
+                }
+                Op::Load(local) => self.stack.push(locals[local]),
+                Op::Store(local) => locals[local] = self.pop(),
+                Op::AndLocal(local) => {
+                    let value = self.pop();
+                    locals[local] &= value;
+                }
+                Op::Arith(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    self.stack.push(op.apply(lhs, rhs));
+                }
+                Op::Compare(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    self.stack.push(op.apply(lhs, rhs) as i64);
+                }
+                Op::LoopTest { idx, hi, exit } => {
+                    if locals[idx] >= locals[hi] {
+                        pc = exit;
+                    }
+                }
+                Op::Next { idx, hi, head } => {
+                    locals[idx] += 1;
+                    if locals[idx] < locals[hi] {
+                        pc = head;
+                    }
+                }
+                Op::Check => exec.check_cancelled()?,
+                Op::Call(index) => {
+                    let value = self.call_function(exec, index)?;
+                    self.stack.push(value as i64);
+                }
+                Op::Critical(index) => {
+                    let program = self.program;
+                    let label = program.chunks()[index].name();
+                    let value = exec.critical(label, || self.call(exec, index))?;
+                    self.stack.push(value as i64);
+                }
+                Op::Interpret(block) => {
+                    let value = exec.run(block)?;
+                    self.stack.push(value as i64);
+                }
+                Op::Ret => break self.pop() != 0,
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// Run the chunk of a function, using its native code if it is hot
+    fn call_function(&mut self, exec: &Executor, index: usize) -> ExecResult {
+        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+        {
+            let function = self.program.chunks()[index].function();
+
+            if let (Some(jit), Some(function)) = (self.jit.as_mut(), function) {
+                if let Some(value) = jit.call(function, exec) {
+                    return value;
+                }
+            }
+        }
+
+        self.call(exec, index)
+    }
+
+    fn pop(&mut self) -> i64 {
+        self.stack.pop().expect("bytecode stack underflow")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use crate::blocks::{
+        ArithOp, Arithmetic, BasicBlock, Boolean, Call, Compare, CompareOp, Critical, Function,
+        IfElse, Loop, Number,
+    };
+    use crate::executor::{CancellationToken, FryOptions, InterpreterError};
+
+    /// Block that is not part of `stir`
+    #[derive(Debug)]
+    struct Foreign {
+        label: String,
+    }
+
+    impl BasicBlock for Foreign {
+        fn label(&self) -> &String {
+            &self.label
+        }
+
+        fn output(&self) -> String {
+            String::from("foreign")
+        }
+
+        fn interpret(&self) -> bool {
+            true
+        }
+    }
+
+    fn run(block: &dyn BasicBlock) -> ExecResult {
+        Vm::new(&Program::compile(block)).run(&Executor::new())
+    }
+
+    #[test]
+    fn same_as_interpreter() {
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+        let nan = Number::new(f64::NAN);
+        let lo = Number::new(1.0);
+        let hi = Number::new(4.0);
+
+        let ie = IfElse::new(&t, &nan, Some(&t));
+        let no_else = IfElse::new(&f, &t, None);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&ie));
+        let empty = Loop::new(Some(&hi), Some(&lo), Some(&f));
+        let block_bound = Loop::new(Some(&f), Some(&t), Some(&no_else));
+
+        let stmts: Vec<&dyn BasicBlock> = vec![&l, &empty, &block_bound];
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&empty);
+        let call = Call::new(&func, None);
+        let crit = Critical::new(&call);
+        let outer = IfElse::new(&crit, &block_bound, Some(&t));
+
+        // loop 1..(4 + true) * 2 { (4 + true) * 2 > 9 }
+        let sum = Arithmetic::new(ArithOp::Add, &hi, &t);
+        let two = Number::new(2.0);
+        let product = Arithmetic::new(ArithOp::Mul, &sum, &two);
+        let nine = Number::new(9.0);
+        let cmp = Compare::new(CompareOp::Gt, &product, &nine);
+        let arith_loop = Loop::new(Some(&lo), Some(&product), Some(&cmp));
+
+        let blocks: Vec<&dyn BasicBlock> = vec![
+            &sum,
+            &cmp,
+            &arith_loop,
+            &ie,
+            &no_else,
+            &l,
+            &empty,
+            &block_bound,
+            &func,
+            &call,
+            &crit,
+            &outer,
+        ];
+
+        for block in blocks {
+            assert_eq!(run(block), Ok(block.interpret()), "{}", block.label());
+        }
+    }
+
+    #[test]
+    fn interpreted_fallback() {
+        let foreign = Foreign {
+            label: String::from("foreign"),
+        };
+        let l = Loop::new(None, Some(&foreign), Some(&foreign));
+        let ie = IfElse::new(&foreign, &foreign, None);
+
+        let program = Program::compile(&ie);
+
+        assert_eq!(
+            program.chunks()[0].code()[0].to_string(),
+            "interpret foreign"
+        );
+        assert_eq!(Vm::new(&program).run(&Executor::new()), Ok(true));
+
+        // Compiling an infinite loop terminates
+        assert_eq!(Program::compile(&l).chunks().len(), 1);
+    }
+
+    #[test]
+    fn cancelled() {
+        let body = Boolean::new(true);
+        let infinite = Loop::new(None, None, Some(&body));
+
+        let token = CancellationToken::new();
+        let mut options = FryOptions::new();
+        options.set_cancellation(token.clone());
+
+        let program = Program::compile(&infinite);
+        let exec = Executor::with_options(options);
+
+        let canceller = thread::spawn(move || token.cancel());
+
+        assert_eq!(
+            Vm::new(&program).run(&exec),
+            Err(InterpreterError::Cancelled)
+        );
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    fn jit() {
+        let t = Boolean::new(true);
+        let f = Boolean::new(false);
+        let lo = Number::new(0.0);
+        let hi = Number::new(50.0);
+
+        let ie = IfElse::new(&t, &f, Some(&t));
+        let inner = Loop::new(Some(&lo), Some(&hi), Some(&ie));
+        let stmts: Vec<&dyn BasicBlock> = vec![&inner];
+        let mut func = Function::new(None, &stmts);
+        func.set_retval(&ie);
+
+        let crit = Critical::new(&t);
+        let crit_stmts: Vec<&dyn BasicBlock> = vec![&crit];
+        let unsupported = Function::new(None, &crit_stmts);
+
+        let call = Call::new(&func, None);
+        let other = Call::new(&unsupported, None);
+        let body = IfElse::new(&other, &f, Some(&call));
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let program = Program::compile(&l);
+        let mut vm = Vm::new(&program);
+        vm.enable_jit(3);
+
+        assert_eq!(vm.run(&Executor::new()), Ok(l.interpret()));
+
+        let jit = vm.jit.as_ref().unwrap();
+        assert!(jit.is_compiled(&func));
+        assert!(!jit.is_compiled(&unsupported));
+    }
+
+    #[test]
+    fn reusable() {
+        let b = Boolean::new(true);
+        let program = Program::compile(&b);
+        let mut vm = Vm::new(&program);
+
+        assert_eq!(vm.run(&Executor::new()), Ok(true));
+        assert_eq!(vm.run(&Executor::new()), Ok(true));
+    }
+}
@@ -0,0 +1,115 @@
+//! Instructions of the bytecode virtual machine
+
+use std::fmt;
+
+use crate::blocks::{ArithOp, BasicBlock, CompareOp};
+
+/// A single bytecode instruction. Instructions operate on a stack of
+/// integers: boolean values are stored as `0` or `1`, loop bounds and
+/// indices as their value. Jump targets are indices in the current chunk.
+#[derive(Clone, Copy)]
+pub enum Op<'block> {
+    /// Push a constant
+    Const(i64),
+
+    /// Discard the value on top of the stack
+    Pop,
+
+    /// Continue at the given instruction
+    Jump(usize),
+
+    /// Pop a value and continue at the given instruction if it is `0`
+    JumpIfZero(usize),
+
+    /// Push the value of a local
+    Load(usize),
+
+    /// Pop a value and store it in a local
+    Store(usize),
+
+    /// Pop a value and store its conjunction with a local in that local
+    AndLocal(usize),
+
+    /// Pop the right then the left operand, and push the result of the
+    /// operation
+    Arith(ArithOp),
+
+    /// Pop the right then the left operand, and push `1` if the comparison
+    /// holds, `0` otherwise
+    Compare(CompareOp),
+
+    /// Continue at `exit` if the local `idx` is greater than or equal to the
+    /// local `hi`
+    LoopTest { idx: usize, hi: usize, exit: usize },
+
+    /// Increment the local `idx`, and continue at `head` if it is lower than
+    /// the local `hi`
+    Next { idx: usize, hi: usize, head: usize },
+
+    /// Stop the run if it got cancelled
+    Check,
+
+    /// Run a chunk and push its value
+    Call(usize),
+
+    /// Run a chunk inside the critical section of the executor and push its
+    /// value
+    Critical(usize),
+
+    /// Interpret a block that cannot be compiled and push its value
+    Interpret(&'block dyn BasicBlock),
+
+    /// Return the value on top of the stack
+    Ret,
+}
+
+impl fmt::Display for Op<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Const(value) => write!(f, "const {}", value),
+            Op::Pop => write!(f, "pop"),
+            Op::Jump(target) => write!(f, "jump {}", target),
+            Op::JumpIfZero(target) => write!(f, "jz {}", target),
+            Op::Load(local) => write!(f, "load {}", local),
+            Op::Store(local) => write!(f, "store {}", local),
+            Op::AndLocal(local) => write!(f, "andlocal {}", local),
+            Op::Arith(op) => write!(f, "arith {}", op.symbol()),
+            Op::Compare(op) => write!(f, "cmp {}", op.symbol()),
+            Op::LoopTest { idx, hi, exit } => write!(f, "looptest {} {} {}", idx, hi, exit),
+            Op::Next { idx, hi, head } => write!(f, "next {} {} {}", idx, hi, head),
+            Op::Check => write!(f, "check"),
+            Op::Call(chunk) => write!(f, "call {}", chunk),
+            Op::Critical(chunk) => write!(f, "critical {}", chunk),
+            Op::Interpret(block) => write!(f, "interpret {}", block.label()),
+            Op::Ret => write!(f, "ret"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::Boolean;
+
+    #[test]
+    fn display() {
+        let b = Boolean::new(true);
+
+        assert_eq!(Op::Const(-3).to_string(), "const -3");
+        assert_eq!(Op::Compare(CompareOp::Le).to_string(), "cmp <=");
+        assert_eq!(
+            Op::LoopTest {
+                idx: 0,
+                hi: 1,
+                exit: 12
+            }
+            .to_string(),
+            "looptest 0 1 12"
+        );
+        assert_eq!(
+            Op::Interpret(&b).to_string(),
+            format!("interpret {}", b.label())
+        );
+    }
+}
@@ -0,0 +1,417 @@
+//! Compilation of blocks to bytecode
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Op;
+
+use crate::blocks::{BasicBlock, BlockKind, Function, Loop, Primitive};
+
+/// Sequence of instructions, ending with `Op::Ret`. Each chunk has its own
+/// locals, used to store loop indices, bounds and values.
+pub struct Chunk<'block> {
+    name: String,
+    code: Vec<Op<'block>>,
+    locals: usize,
+
+    /// Function the chunk was compiled from, if any
+    function: Option<&'block Function<'block>>,
+}
+
+impl<'block> Chunk<'block> {
+    fn new(name: &str) -> Chunk<'block> {
+        Chunk {
+            name: String::from(name),
+            code: Vec::new(),
+            locals: 0,
+            function: None,
+        }
+    }
+
+    /// Return the name of the chunk: the label of the block it was compiled
+    /// from
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Return the instructions of the chunk
+    pub fn code(&self) -> &[Op<'block>] {
+        &self.code
+    }
+
+    /// Return the number of locals used by the chunk
+    pub fn locals(&self) -> usize {
+        self.locals
+    }
+
+    /// Return the function the chunk was compiled from, if any
+    pub fn function(&self) -> Option<&'block Function<'block>> {
+        self.function
+    }
+
+    /// Add an instruction, and return its index
+    fn push(&mut self, op: Op<'block>) -> usize {
+        self.code.push(op);
+        self.code.len() - 1
+    }
+
+    /// Return the index of the next instruction
+    fn here(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Reserve a new local
+    fn local(&mut self) -> usize {
+        self.locals += 1;
+        self.locals - 1
+    }
+
+    /// Set the target of a jump added beforehand
+    fn patch(&mut self, jump: usize, target: usize) {
+        match &mut self.code[jump] {
+            Op::Jump(t) | Op::JumpIfZero(t) => *t = target,
+            Op::LoopTest { exit, .. } => *exit = target,
+            _ => unreachable!("patching a non-jump instruction"),
+        }
+    }
+}
+
+/// Bytecode compiled from a block. The first chunk evaluates the block the
+/// program was compiled from, the other ones evaluate `Function` and
+/// `Critical` blocks.
+pub struct Program<'block> {
+    chunks: Vec<Chunk<'block>>,
+
+    /// Chunk of each function already compiled
+    functions: HashMap<&'block String, usize>,
+}
+
+impl<'block> Program<'block> {
+    /// Compile a block and the blocks it references. Blocks which are not
+    /// part of `stir` are interpreted when running the program.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stir::blocks::{BasicBlock, Boolean, IfElse};
+    /// use stir::vm::Program;
+    ///
+    /// let c = Boolean::new(true);
+    /// let t = Boolean::new(false);
+    /// let ie = IfElse::new(&c, &t, None);
+    ///
+    /// let program = Program::compile(&ie);
+    ///
+    /// assert_eq!(program.chunks().len(), 1);
+    /// assert_eq!(
+    ///     program.to_string(),
+    ///     format!(
+    ///         "{}:\n  0: const 1\n  1: jz 4\n  2: const 0\n  3: jump 5\n  4: const 0\n  5: ret\n",
+    ///         ie.label()
+    ///     )
+    /// );
+    /// ```
+    pub fn compile(entry: &'block dyn BasicBlock) -> Program<'block> {
+        let mut program = Program {
+            chunks: Vec::new(),
+            functions: HashMap::new(),
+        };
+
+        program.chunk(entry.label(), |program, chunk| program.expr(chunk, entry));
+
+        program
+    }
+
+    /// Return the chunks of the program. The first one is the entry point
+    pub fn chunks(&self) -> &[Chunk<'block>] {
+        &self.chunks
+    }
+
+    /// Reserve a new chunk, fill it, and return its index
+    fn chunk(
+        &mut self,
+        name: &str,
+        fill: impl FnOnce(&mut Program<'block>, &mut Chunk<'block>),
+    ) -> usize {
+        let index = self.chunks.len();
+        self.chunks.push(Chunk::new(name));
+
+        let mut chunk = Chunk::new(name);
+        fill(self, &mut chunk);
+        chunk.push(Op::Ret);
+
+        self.chunks[index] = chunk;
+
+        index
+    }
+
+    /// Generate the instructions pushing the value of a block
+    fn expr(&mut self, chunk: &mut Chunk<'block>, block: &'block dyn BasicBlock) {
+        // Primitives are evaluated once and for all
+        match block.kind() {
+            kind if kind.is_primitive() => {
+                chunk.push(Op::Const(block.interpret() as i64));
+            }
+            BlockKind::IfElse(ie) => {
+                self.expr(chunk, ie.cond_block());
+                let to_else = chunk.push(Op::JumpIfZero(0));
+
+                self.expr(chunk, ie.t_block());
+                let to_end = chunk.push(Op::Jump(0));
+
+                chunk.patch(to_else, chunk.here());
+                match ie.f_block() {
+                    Some(f_block) => self.expr(chunk, f_block),
+                    None => {
+                        chunk.push(Op::Const(0));
+                    }
+                }
+
+                chunk.patch(to_end, chunk.here());
+            }
+            BlockKind::Loop(l) => self.r#loop(chunk, l),
+            BlockKind::Function(f) => {
+                let index = self.function(f);
+                chunk.push(Op::Call(index));
+            }
+            BlockKind::Call(c) => {
+                let index = self.function(c.function());
+                chunk.push(Op::Check);
+                chunk.push(Op::Call(index));
+            }
+            BlockKind::Arithmetic(_) => {
+                // The result is only used as an integer: the block is `true`
+                self.integer(chunk, block);
+                chunk.push(Op::Pop);
+                chunk.push(Op::Const(1));
+            }
+            BlockKind::Compare(c) => {
+                self.integer(chunk, c.lhs());
+                self.integer(chunk, c.rhs());
+                chunk.push(Op::Compare(c.op()));
+            }
+            BlockKind::Critical(c) => {
+                let inner = c.block();
+                let index = self.chunk(c.label(), |program, chunk| program.expr(chunk, inner));
+                chunk.push(Op::Critical(index));
+            }
+            _ => {
+                chunk.push(Op::Interpret(block));
+            }
+        }
+    }
+
+    /// Generate the instructions of a loop. Its value is stored in a local,
+    /// and updated after each iteration
+    fn r#loop(&mut self, chunk: &mut Chunk<'block>, l: &'block Loop<'block>) {
+        let (lo, hi) = match (l.lo_bound(), l.hi_bound()) {
+            (Some(lo), Some(hi)) => (lo, hi),
+            _ => {
+                // Infinite loops only stop when cancelled
+                let head = chunk.push(Op::Check);
+                if let Some(body) = l.body() {
+                    self.expr(chunk, body);
+                    chunk.push(Op::Pop);
+                }
+                chunk.push(Op::Jump(head));
+
+                // Unreachable, keeps the stack balanced
+                chunk.push(Op::Const(1));
+                return;
+            }
+        };
+
+        let idx = chunk.local();
+        let end = chunk.local();
+        let acc = chunk.local();
+
+        self.integer(chunk, lo);
+        chunk.push(Op::Store(idx));
+        self.integer(chunk, hi);
+        chunk.push(Op::Store(end));
+        chunk.push(Op::Const(1));
+        chunk.push(Op::Store(acc));
+
+        // The condition is tested once before the first iteration, then
+        // after each iteration
+        let test = chunk.push(Op::LoopTest {
+            idx,
+            hi: end,
+            exit: 0,
+        });
+        let head = chunk.push(Op::Check);
+
+        if let Some(body) = l.body() {
+            self.expr(chunk, body);
+            chunk.push(Op::AndLocal(acc));
+        }
+
+        chunk.push(Op::Next { idx, hi: end, head });
+
+        chunk.patch(test, chunk.here());
+        chunk.push(Op::Load(acc));
+    }
+
+    /// Generate the instructions pushing the value of a loop bound or an
+    /// operand: `Number` and `Arithmetic` blocks count as their value, other
+    /// blocks as `1` if they evaluate to `true`
+    fn integer(&mut self, chunk: &mut Chunk<'block>, block: &'block dyn BasicBlock) {
+        match block.kind() {
+            BlockKind::Number(n) => {
+                chunk.push(Op::Const(n.get() as i64));
+            }
+            BlockKind::Arithmetic(a) => {
+                self.integer(chunk, a.lhs());
+                self.integer(chunk, a.rhs());
+                chunk.push(Op::Arith(a.op()));
+            }
+            _ => self.expr(chunk, block),
+        }
+    }
+
+    /// Compile a function if needed, and return the index of its chunk
+    fn function(&mut self, f: &'block Function<'block>) -> usize {
+        if let Some(index) = self.functions.get(f.label()) {
+            return *index;
+        }
+
+        // Register the chunk before compiling the statements, its index is
+        // the next one
+        self.functions.insert(f.label(), self.chunks.len());
+
+        let index = self.chunk(f.label(), |program, chunk| {
+            for stmt in f.stmts().iter() {
+                program.expr(chunk, *stmt);
+                chunk.push(Op::Pop);
+            }
+
+            match f.retval() {
+                Some(retval) => program.expr(chunk, retval),
+                None => {
+                    chunk.push(Op::Const(0));
+                }
+            }
+        });
+
+        self.chunks[index].function = Some(f);
+
+        index
+    }
+}
+
+impl fmt::Display for Program<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.chunks.iter() {
+            writeln!(f, "{}:", chunk.name)?;
+
+            for (index, op) in chunk.code.iter().enumerate() {
+                writeln!(f, "  {}: {}", index, op)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::blocks::{Boolean, Call, Critical, Number, Str};
+
+    fn code(chunk: &Chunk) -> Vec<String> {
+        chunk.code().iter().map(|op| op.to_string()).collect()
+    }
+
+    #[test]
+    fn primitives() {
+        let n = Number::new(f64::NAN);
+        let s = Str::new(String::from("stir"));
+
+        assert_eq!(code(&Program::compile(&n).chunks()[0]), ["const 0", "ret"]);
+        assert_eq!(code(&Program::compile(&s).chunks()[0]), ["const 1", "ret"]);
+    }
+
+    #[test]
+    fn loops() {
+        let lo = Number::new(2.0);
+        let hi = Number::new(7.0);
+        let body = Boolean::new(true);
+        let l = Loop::new(Some(&lo), Some(&hi), Some(&body));
+
+        let program = Program::compile(&l);
+        let chunk = &program.chunks()[0];
+
+        assert_eq!(chunk.locals(), 3);
+        assert_eq!(
+            code(chunk),
+            [
+                "const 2",
+                "store 0",
+                "const 7",
+                "store 1",
+                "const 1",
+                "store 2",
+                "looptest 0 1 11",
+                "check",
+                "const 1",
+                "andlocal 2",
+                "next 0 1 7",
+                "load 2",
+                "ret"
+            ]
+        );
+    }
+
+    #[test]
+    fn infinite_loop() {
+        let l = Loop::new(None, None, None);
+
+        assert_eq!(
+            code(&Program::compile(&l).chunks()[0]),
+            ["check", "jump 0", "const 1", "ret"]
+        );
+    }
+
+    #[test]
+    fn functions_compiled_once() {
+        let b = Boolean::new(true);
+        let stmts: Vec<&dyn BasicBlock> = vec![&b];
+        let f = Function::new(None, &stmts);
+
+        let call0 = Call::new(&f, None);
+        let call1 = Call::new(&f, None);
+        let crit = Critical::new(&call1);
+        let main_stmts: Vec<&dyn BasicBlock> = vec![&call0, &crit];
+        let mut main = Function::new(None, &main_stmts);
+        main.set_retval(&b);
+
+        let program = Program::compile(&main);
+        let chunks = program.chunks();
+
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[1].name(), main.label());
+        assert_eq!(chunks[2].name(), f.label());
+        assert_eq!(chunks[3].name(), crit.label());
+
+        assert!(chunks[0].function().is_none());
+        assert_eq!(chunks[2].function().unwrap().label(), f.label());
+        assert!(chunks[3].function().is_none());
+
+        assert_eq!(code(&chunks[0]), ["call 1", "ret"]);
+        assert_eq!(
+            code(&chunks[1]),
+            [
+                "check",
+                "call 2",
+                "pop",
+                "critical 3",
+                "pop",
+                "const 1",
+                "ret"
+            ]
+        );
+        assert_eq!(code(&chunks[2]), ["const 1", "pop", "const 0", "ret"]);
+        assert_eq!(code(&chunks[3]), ["check", "call 2", "ret"]);
+    }
+}